@@ -0,0 +1,359 @@
+use alloy_primitives::primitives::{address, hex, Address};
+
+use crate::{
+    context::{Context, ContextMut, MiniEthereum, Spec, HISTORY_STORAGE_ADDRESS},
+    env::BlockEnv,
+    error::{ExecError, InvalidTransaction},
+    machine::{CallInfo, GasTracker},
+    opcodes::ExecutionResult,
+    transaction::{receipts_root, Bloom, Receipt, SignedTransaction, GAS_PER_BLOB},
+    u256::U256,
+};
+
+/// Caller of the system contracts invoked at the start of a block.
+pub const SYSTEM_ADDRESS: Address = address!("fffffffffffffffffffffffffffffffffffffffe");
+
+/// EIP-4788 contract keeping the parent beacon block roots.
+pub const BEACON_ROOTS_ADDRESS: Address = address!("000F3df6D732807Ef1319fB7B8bB8522d0Beac02");
+
+/// Runtime code deployed at `BEACON_ROOTS_ADDRESS`.
+pub const BEACON_ROOTS_CODE: [u8; 97] = hex!("3373fffffffffffffffffffffffffffffffffffffffe14604d57602036146024575f5ffd5b5f35801560495762001fff810690815414603c575f5ffd5b62001fff01545f5260205ff35b5f5ffd5b62001fff42064281555f359062001fff015500");
+
+/// Runtime code deployed at `HISTORY_STORAGE_ADDRESS` (EIP-2935).
+pub const HISTORY_STORAGE_CODE: [u8; 83] = hex!("3373fffffffffffffffffffffffffffffffffffffffe14604657602036036042575f35600143038111604257611fff81430311604257611fff9006545f5260205ff35b5f5ffd5b5f35611fff60014303065500");
+
+pub const SYSTEM_CALL_GAS: usize = 30_000_000;
+
+const GWEI: u64 = 1_000_000_000;
+
+const STACK_SIZE: usize = 1024;
+
+/// An EIP-4895 withdrawal from the beacon chain, `amount` is in gwei.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: Address,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Block {
+    pub env: BlockEnv,
+    pub parent_hash: U256,
+    pub parent_beacon_block_root: Option<U256>,
+    pub transactions: Vec<SignedTransaction>,
+    pub withdrawals: Vec<Withdrawal>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockOutcome {
+    pub receipts: Vec<Receipt>,
+    pub gas_used: usize,
+    pub blob_gas_used: usize,
+    /// State root after the withdrawals.
+    pub state_root: [u8; 32],
+}
+
+impl BlockOutcome {
+    pub fn receipts_root(&self) -> [u8; 32] {
+        receipts_root(&self.receipts)
+    }
+
+    pub fn logs_bloom(&self) -> Bloom {
+        let mut bloom = Bloom::default();
+        for receipt in &self.receipts {
+            bloom.accrue_bloom(&receipt.logs_bloom());
+        }
+        bloom
+    }
+}
+
+impl MiniEthereum {
+    /// Calls a system contract from `SYSTEM_ADDRESS` without charging gas or bumping any
+    /// nonce. Returns `None` if there is no code at `address`.
+    pub fn system_call(
+        &mut self,
+        address: Address,
+        data: Vec<u8>,
+    ) -> Result<Option<ExecutionResult>, ExecError> {
+        if self.code(address)?.is_empty() {
+            return Ok(None);
+        }
+        let system_existed = self.accounts.contains_key(&SYSTEM_ADDRESS);
+        let mut gas_tracker = GasTracker::new(SYSTEM_CALL_GAS);
+        let result = self.call(
+            STACK_SIZE,
+            &mut gas_tracker,
+            address,
+            CallInfo {
+                origin: SYSTEM_ADDRESS,
+                caller: SYSTEM_ADDRESS,
+                value: U256::ZERO,
+                data,
+                is_static: false,
+            },
+        );
+        if !system_existed {
+            self.remove_account(SYSTEM_ADDRESS);
+        }
+        self.clear_transient();
+        self.logs.clear();
+        result.map(Some)
+    }
+
+    /// Applies a block on top of the current state: the pre-block system calls, every
+    /// transaction in order and the withdrawals. The block is applied as a whole: on an
+    /// invalid transaction or any other error the state is left as it was before.
+    pub fn execute_block(&mut self, block: &Block) -> Result<BlockOutcome, ExecError> {
+        let snapshot = self.snapshot();
+        let outcome = self.apply_block(block);
+        if outcome.is_ok() {
+            self.discard_snapshot(snapshot);
+        } else {
+            self.revert_to(snapshot);
+        }
+        outcome
+    }
+
+    fn apply_block(&mut self, block: &Block) -> Result<BlockOutcome, ExecError> {
+        self.set_block_env(block.env.clone());
+        if block.env.number > U256::ZERO {
+            let parent = block.env.number - U256::ONE;
+            self.insert_block_hash(parent, block.parent_hash);
+            if self.spec >= Spec::Prague {
+                self.system_call(HISTORY_STORAGE_ADDRESS, block.parent_hash.to_be_bytes_vec())?;
+            }
+        }
+        if let Some(root) = block.parent_beacon_block_root {
+            self.system_call(BEACON_ROOTS_ADDRESS, root.to_be_bytes_vec())?;
+        }
+
+        let max_blob_gas = self.spec.max_blobs_per_block() * GAS_PER_BLOB;
+        let mut outcome = BlockOutcome::default();
+        for signed in block.transactions.iter() {
+            let sender = signed.recover_sender()?;
+            let tx = &signed.tx;
+            if U256::from(outcome.gas_used + tx.gas_limit()) > block.env.gas_limit {
+                return Err(InvalidTransaction::BlockGasLimitExceeded.into());
+            }
+            if outcome.blob_gas_used + tx.blob_gas() > max_blob_gas {
+                return Err(InvalidTransaction::BlobGasLimitExceeded.into());
+            }
+            let mut receipt = self.execute_transaction(sender, tx)?;
+            outcome.gas_used += receipt.gas_used;
+            outcome.blob_gas_used += tx.blob_gas();
+            receipt.cumulative_gas_used = outcome.gas_used;
+            outcome.receipts.push(receipt);
+        }
+
+        for withdrawal in block.withdrawals.iter() {
+            self.fund(
+                withdrawal.address,
+                U256::from(withdrawal.amount) * U256::from(GWEI),
+            );
+        }
+        outcome.state_root = self.state_root();
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::{Account, Log},
+        transaction::TxEip1559,
+        wallet::dev_accounts,
+    };
+
+    fn genesis() -> MiniEthereum {
+        let mut ctx = MiniEthereum::new();
+        for (address, code) in [
+            (BEACON_ROOTS_ADDRESS, BEACON_ROOTS_CODE.to_vec()),
+            (HISTORY_STORAGE_ADDRESS, HISTORY_STORAGE_CODE.to_vec()),
+            // PUSH1 0x2a PUSH0 MSTORE PUSH1 0x20 PUSH0 LOG0 STOP
+            (
+                Address::with_last_byte(0x42),
+                vec![0x60, 0x2a, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xa0, 0x00],
+            ),
+        ] {
+            ctx.replace_account(
+                address,
+                Account {
+                    code,
+                    ..Default::default()
+                },
+            );
+        }
+        ctx
+    }
+
+    #[test]
+    fn test_execute_block() {
+        let accounts = dev_accounts(2);
+        let mut ctx = genesis().with_funded_accounts(
+            &[accounts[0].address(), accounts[1].address()],
+            U256::from(1000000000u64),
+        );
+        let coinbase = Address::with_last_byte(0xcb);
+        let env = BlockEnv::default()
+            .with_number(U256::from(100))
+            .with_timestamp(U256::from(1700000000))
+            .with_gas_limit(U256::from(30000000))
+            .with_base_fee(U256::from(7))
+            .with_coinbase(coinbase);
+        let tx = |to: Address, nonce: u64| TxEip1559 {
+            nonce: U256::from(nonce),
+            to: Some(to),
+            value: U256::from(1),
+            gas_limit: 50000,
+            max_fee_per_gas: U256::from(10),
+            max_priority_fee_per_gas: U256::from(1),
+            ..Default::default()
+        };
+        let block = Block {
+            env,
+            parent_hash: U256::from(0x99),
+            parent_beacon_block_root: Some(U256::from(0xbeac)),
+            transactions: vec![
+                accounts[0].sign_transaction(tx(accounts[1].address(), 0).into()),
+                accounts[1].sign_transaction(tx(Address::with_last_byte(0x42), 0).into()),
+                accounts[0].sign_transaction(tx(Address::with_last_byte(0x42), 1).into()),
+            ],
+            withdrawals: vec![Withdrawal {
+                address: Address::with_last_byte(0x77),
+                amount: 5,
+                ..Default::default()
+            }],
+        };
+        let outcome = ctx.execute_block(&block).unwrap();
+
+        assert_eq!(outcome.receipts.len(), 3);
+        assert!(outcome.receipts.iter().all(|r| r.success));
+        assert_eq!(outcome.receipts[0].gas_used, 21000);
+        assert_eq!(outcome.receipts[0].cumulative_gas_used, 21000);
+        assert_eq!(
+            outcome.receipts[2].cumulative_gas_used,
+            outcome.receipts.iter().map(|r| r.gas_used).sum::<usize>()
+        );
+        assert_eq!(outcome.gas_used, outcome.receipts[2].cumulative_gas_used);
+        assert!(outcome.receipts[0].logs.is_empty());
+        assert_eq!(
+            outcome.receipts[1].logs,
+            vec![Log {
+                address: Address::with_last_byte(0x42),
+                topics: vec![],
+                data: U256::from(0x2a).to_be_bytes_vec(),
+            }]
+        );
+        assert_eq!(outcome.logs_bloom(), outcome.receipts[1].logs_bloom());
+        assert!(outcome
+            .logs_bloom()
+            .contains_input(Address::with_last_byte(0x42).as_slice()));
+        assert_eq!(
+            outcome.receipts_root(),
+            crate::trie::ordered_trie_root(
+                &outcome
+                    .receipts
+                    .iter()
+                    .map(Receipt::encode)
+                    .collect::<Vec<_>>()
+            )
+        );
+        assert_eq!(ctx.balance(coinbase).unwrap(), U256::from(outcome.gas_used));
+        assert_eq!(
+            ctx.balance(Address::with_last_byte(0x77)).unwrap(),
+            U256::from(5 * GWEI)
+        );
+        assert!(!ctx.accounts.contains_key(&SYSTEM_ADDRESS));
+        assert_eq!(outcome.state_root, ctx.state_root());
+
+        // The parent hash is kept by the EIP-2935 contract.
+        assert_eq!(
+            ctx.sload(HISTORY_STORAGE_ADDRESS, U256::from(99)).unwrap(),
+            U256::from(0x99)
+        );
+        assert_eq!(ctx.block_hash(U256::from(99)).unwrap(), U256::from(0x99));
+
+        // The beacon root can be read back by timestamp.
+        let mut gt = GasTracker::new(100000);
+        assert_eq!(
+            ctx.call(
+                STACK_SIZE,
+                &mut gt,
+                BEACON_ROOTS_ADDRESS,
+                CallInfo {
+                    data: U256::from(1700000000).to_be_bytes_vec(),
+                    ..Default::default()
+                },
+            ),
+            Ok(ExecutionResult::Returned(
+                U256::from(0xbeac).to_be_bytes_vec()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_block_gas_limit() {
+        let accounts = dev_accounts(1);
+        let mut ctx = genesis().with_funded_accounts(&[accounts[0].address()], U256::from(1000000));
+        let root = ctx.state_root();
+        let tx = |nonce: u64| {
+            accounts[0].sign_transaction(
+                TxEip1559 {
+                    nonce: U256::from(nonce),
+                    to: Some(Address::with_last_byte(2)),
+                    value: U256::from(1),
+                    gas_limit: 21000,
+                    ..Default::default()
+                }
+                .into(),
+            )
+        };
+        let block = Block {
+            env: BlockEnv::default()
+                .with_number(U256::from(1))
+                .with_gas_limit(U256::from(30000)),
+            parent_hash: U256::from(0x99),
+            transactions: vec![tx(0), tx(1)],
+            ..Default::default()
+        };
+        assert_eq!(
+            ctx.execute_block(&block),
+            Err(ExecError::InvalidTransaction(
+                InvalidTransaction::BlockGasLimitExceeded
+            ))
+        );
+        // The valid first transaction is rolled back along with the block.
+        assert_eq!(ctx.nonce(accounts[0].address()).unwrap(), U256::ZERO);
+        assert_eq!(ctx.balance(Address::with_last_byte(2)).unwrap(), U256::ZERO);
+        assert_eq!(ctx.block, BlockEnv::default());
+        assert!(ctx.block_hashes.is_empty());
+        assert_eq!(ctx.state_root(), root);
+    }
+
+    #[test]
+    fn test_reverted_logs_dropped() {
+        let accounts = dev_accounts(1);
+        let mut ctx = genesis().with_funded_accounts(&[accounts[0].address()], U256::from(1000000));
+        // PUSH0 PUSH0 LOG0 PUSH0 PUSH0 REVERT
+        ctx.accounts
+            .entry(Address::with_last_byte(0x43))
+            .or_default()
+            .code = vec![0x5f, 0x5f, 0xa0, 0x5f, 0x5f, 0xfd];
+        let receipt = ctx
+            .execute_transaction(
+                accounts[0].address(),
+                &TxEip1559 {
+                    to: Some(Address::with_last_byte(0x43)),
+                    gas_limit: 50000,
+                    ..Default::default()
+                }
+                .into(),
+            )
+            .unwrap();
+        assert!(!receipt.success);
+        assert!(receipt.logs.is_empty());
+    }
+}
@@ -1,13 +1,22 @@
-use alloy_primitives::primitives::Address;
+use alloy_primitives::primitives::{address, Address};
 
 use crate::{
+    env::{BlockEnv, TxEnv},
     error::{ExecError, RevertError},
     keccak::keccak,
     machine::{CallInfo, GasTracker, Machine, Word},
     opcodes::ExecutionResult,
+    overlay::BlockHistory,
+    precompiles::{self, Precompile},
+    rlp::{self, Encodable},
+    snapshot::Journal,
+    state_trie::StateTrie,
     u256::U256,
 };
-use std::{collections::HashMap, error::Error};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Info {
@@ -30,9 +39,22 @@ pub trait Context<W: Word> {
     fn block_hash(&self, block_number: W) -> Result<W, Box<dyn Error>>;
     fn info(&self, inf: Info) -> Result<W, Box<dyn Error>>;
     fn balance(&self, address: W::Addr) -> Result<W, Box<dyn Error>>;
+    fn nonce(&self, address: W::Addr) -> Result<W, Box<dyn Error>>;
     fn sload(&self, contract: W::Addr, address: W) -> Result<W, Box<dyn Error>>;
     fn tload(&self, address: W) -> Result<W, Box<dyn Error>>;
     fn as_mut(&mut self) -> &mut Self::Mutable;
+    /// Called by `Machine::run` before each opcode, see `Inspector::step`.
+    fn step(&mut self, _machine: &mut Machine<W>) -> Result<Option<ExecutionResult>, ExecError> {
+        Ok(None)
+    }
+    /// Called by `Machine::run` after each opcode, see `Inspector::step_end`.
+    fn step_end(
+        &mut self,
+        _machine: &mut Machine<W>,
+        _opcode: u8,
+        _result: &mut Result<Option<ExecutionResult>, ExecError>,
+    ) {
+    }
 }
 
 pub trait ContextMut<W: Word>: Context<W> {
@@ -60,7 +82,200 @@ pub trait ContextMut<W: Word>: Context<W> {
     ) -> Result<(), Box<dyn Error>>;
 }
 
-#[derive(Clone, Debug, Default)]
+/// Account-level writes a context is built on. `call_frame` and `create_frame` implement
+/// the message calls of `ContextMut` on top of it, so that every backend shares the same
+/// call semantics.
+pub trait State {
+    fn set_nonce(&mut self, address: Address, nonce: U256) -> Result<(), Box<dyn Error>>;
+    fn set_balance(&mut self, address: Address, balance: U256) -> Result<(), Box<dyn Error>>;
+    fn set_code(&mut self, address: Address, code: Vec<u8>) -> Result<(), Box<dyn Error>>;
+    /// Deletes the account at `address` along with its storage.
+    fn delete_account(&mut self, address: Address) -> Result<(), Box<dyn Error>>;
+    fn precompile(&self, address: Address) -> Option<Precompile>;
+    /// Logs emitted by the current transaction, logs of reverted frames are dropped.
+    fn logs(&mut self) -> &mut Vec<Log>;
+    /// Marks the state at the start of a frame, to be rolled back to with
+    /// `revert_checkpoint` if the frame fails or kept with `discard_checkpoint`. Checkpoints
+    /// nest, and ending one ends the ones taken after it.
+    fn checkpoint(&mut self) -> usize;
+    fn revert_checkpoint(&mut self, id: usize);
+    fn discard_checkpoint(&mut self, id: usize);
+    fn spec(&self) -> Spec;
+    /// Starts a transaction running in environment `tx`, dropping the logs of the previous
+    /// one.
+    fn begin_transaction(&mut self, tx: TxEnv);
+    /// Ends the current transaction, clearing transient storage and deleting the accounts
+    /// it wrote that were left empty (EIP-161).
+    fn end_transaction(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+/// Runs `frame` under a checkpoint, rolling back its writes and logs if it fails.
+pub(crate) fn checkpointed<C: State, T>(
+    ctx: &mut C,
+    frame: impl FnOnce(&mut C) -> Result<T, ExecError>,
+) -> Result<T, ExecError> {
+    let checkpoint = ctx.checkpoint();
+    let logs_len = ctx.logs().len();
+    let result = frame(ctx);
+    match result {
+        Ok(_) => ctx.discard_checkpoint(checkpoint),
+        Err(_) => {
+            ctx.revert_checkpoint(checkpoint);
+            ctx.logs().truncate(logs_len);
+        }
+    }
+    result
+}
+
+/// Values replaced by the writes made under open checkpoints, for states that undo
+/// frames write by write instead of keeping copies.
+pub(crate) struct UndoLog<E> {
+    entries: Vec<E>,
+    /// Number of entries when each open checkpoint was taken.
+    checkpoints: Vec<usize>,
+}
+
+impl<E> Default for UndoLog<E> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+}
+
+impl<E> UndoLog<E> {
+    pub(crate) fn checkpoint(&mut self) -> usize {
+        self.checkpoints.push(self.entries.len());
+        self.checkpoints.len() - 1
+    }
+
+    /// Keeps the value replaced by a write, unless no checkpoint is open.
+    pub(crate) fn record(&mut self, entry: E) {
+        if !self.checkpoints.is_empty() {
+            self.entries.push(entry);
+        }
+    }
+
+    /// Ends checkpoint `id` and the later ones, returning what to restore, newest first.
+    pub(crate) fn revert(&mut self, id: usize) -> Vec<E> {
+        let Some(&len) = self.checkpoints.get(id) else {
+            return Vec::new();
+        };
+        self.checkpoints.truncate(id);
+        let mut entries = self.entries.split_off(len);
+        entries.reverse();
+        entries
+    }
+
+    /// Ends checkpoint `id` and the later ones, keeping their writes.
+    pub(crate) fn discard(&mut self, id: usize) {
+        self.checkpoints.truncate(id);
+        if self.checkpoints.is_empty() {
+            self.entries.clear();
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.checkpoints.clear();
+    }
+}
+
+/// Deploys `call_info.data` as init code, see `ContextMut::create`.
+pub fn create_frame<C: ContextMut<U256> + State>(
+    ctx: &mut C,
+    stack_size: usize,
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+    salt: Option<U256>,
+) -> Result<Address, ExecError> {
+    let nonce = ctx.nonce(call_info.caller)?;
+    let balance = ctx.balance(call_info.caller)?;
+    if balance < call_info.value {
+        return Err(ExecError::Revert(RevertError::InsufficientBalance));
+    }
+    ctx.set_nonce(call_info.caller, nonce + U256::ONE)?;
+    let contract_addr = if let Some(salt) = salt {
+        let mut inp = vec![0xffu8];
+        inp.extend(call_info.caller.as_slice());
+        inp.extend(&salt.to_big_endian());
+        inp.extend(&keccak(&call_info.data));
+        Address::from_slice(&keccak(&inp)[12..32])
+    } else {
+        create_address(call_info.caller, nonce)
+    };
+
+    // The nonce of the caller stays incremented if the deployment fails.
+    checkpointed(ctx, |ctx| {
+        ctx.set_balance(call_info.caller, balance - call_info.value)?;
+        if !ctx.code(contract_addr)?.is_empty() || !ctx.nonce(contract_addr)?.is_zero() {
+            return Err(ExecError::Revert(RevertError::ContractAlreadyDeployed));
+        }
+        // Contracts start with nonce 1 (EIP-161).
+        ctx.set_nonce(contract_addr, U256::ONE)?;
+        // Value sent to the address before the deployment stays with the contract.
+        let contract_balance = ctx.balance(contract_addr)?;
+        ctx.set_balance(contract_addr, contract_balance + call_info.value)?;
+
+        let exec_result = Machine::new(contract_addr, call_info.data, gas_tracker, stack_size)
+            .run(
+                ctx,
+                &CallInfo {
+                    value: call_info.value,
+                    data: vec![],
+                    origin: call_info.origin,
+                    caller: call_info.caller,
+                    is_static: false,
+                },
+            )?;
+
+        match exec_result {
+            ExecutionResult::Halted => {}
+            ExecutionResult::Returned(code) => {
+                ctx.set_code(contract_addr, code)?;
+            }
+        }
+
+        Ok(contract_addr)
+    })
+}
+
+/// Transfers `call_info.value` and runs the code of `address`, see `ContextMut::call`.
+pub fn call_frame<C: ContextMut<U256> + State>(
+    ctx: &mut C,
+    stack_size: usize,
+    gas_tracker: &mut GasTracker,
+    address: Address,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    if let Some(precompile) = ctx.precompile(address) {
+        return precompile(gas_tracker, call_info);
+    }
+    let balance = ctx.balance(call_info.caller)?;
+    if balance < call_info.value {
+        return Err(ExecError::Revert(RevertError::InsufficientBalance));
+    }
+    checkpointed(ctx, |ctx| {
+        ctx.set_balance(call_info.caller, balance - call_info.value)?;
+        let balance = ctx.balance(address)?;
+        ctx.set_balance(address, balance + call_info.value)?;
+        let mut code = ctx.code(address)?;
+        if let Some(delegate) = delegated_address(&code) {
+            code = ctx.code(delegate)?;
+        }
+        Machine::new(address, code, gas_tracker, stack_size).run(ctx, &call_info)
+    })
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<U256>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Account {
     pub nonce: U256,
     pub value: U256,
@@ -68,43 +283,234 @@ pub struct Account {
     pub storage: HashMap<U256, U256>,
 }
 
+impl Account {
+    /// No nonce, balance or code, as defined by EIP-161.
+    pub fn is_empty(&self) -> bool {
+        self.nonce.is_zero() && self.value.is_zero() && self.code.is_empty()
+    }
+}
+
+/// Number of recent blocks whose hashes are available to BLOCKHASH.
+pub const BLOCK_HASH_WINDOW: u64 = 256;
+
+/// EIP-2935 system contract keeping the hashes of recent blocks in its storage.
+pub const HISTORY_STORAGE_ADDRESS: Address = address!("0000F90827F1C53a10cb7A02335B175320002935");
+
+/// Size of the EIP-2935 ring buffer, the hash of block `n` is kept in slot
+/// `n % HISTORY_SERVE_WINDOW`.
+pub const HISTORY_SERVE_WINDOW: u64 = 8191;
+
+/// Code of an account delegating to another one is this prefix followed by the
+/// address of the delegate (EIP-7702).
+pub const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+
+pub fn delegated_address(code: &[u8]) -> Option<Address> {
+    if code.len() == 23 && code.starts_with(&DELEGATION_PREFIX) {
+        Some(Address::from_slice(&code[3..]))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Spec {
+    Cancun,
+    #[default]
+    Prague,
+}
+
+impl Spec {
+    /// Controls how fast the blob base fee reacts to excess blob gas (EIP-4844, EIP-7691).
+    pub fn blob_base_fee_update_fraction(&self) -> u64 {
+        match self {
+            Spec::Cancun => 3338477,
+            Spec::Prague => 5007716,
+        }
+    }
+    /// Number of blobs per block above which the blob base fee rises (EIP-4844, EIP-7691).
+    pub fn target_blobs_per_block(&self) -> usize {
+        match self {
+            Spec::Cancun => 3,
+            Spec::Prague => 6,
+        }
+    }
+    /// Maximum number of blobs a block may carry (EIP-4844, EIP-7691).
+    pub fn max_blobs_per_block(&self) -> usize {
+        match self {
+            Spec::Cancun => 6,
+            Spec::Prague => 9,
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct MiniEthereum {
-    precompiles:
-        HashMap<Address, &'static dyn Fn(CallInfo<U256>) -> Result<ExecutionResult, ExecError>>,
+    pub spec: Spec,
+    pub block: BlockEnv,
+    pub tx: TxEnv,
+    pub(crate) block_hashes: VecDeque<(U256, U256)>,
+    precompiles: HashMap<Address, Precompile>,
     pub accounts: HashMap<Address, Account>,
     pub transient: HashMap<U256, U256>,
+    pub logs: Vec<Log>,
+    pub(crate) state_trie: StateTrie,
+    /// Accounts and slots written since the state trie was last updated.
+    pub(crate) dirty: HashMap<Address, HashSet<U256>>,
+    pub(crate) journals: Vec<Journal>,
+    pub(crate) next_snapshot: usize,
+    /// Accounts written by the current transaction, see `remove_touched_empty`.
+    pub(crate) touched: HashSet<Address>,
 }
 
-fn rlp_address_nonce(addr: Address, nonce: U256) -> Vec<u8> {
-    let mut rlp = vec![0x94u8];
-    rlp.extend(addr.as_slice());
-    if nonce < U256::from(128) {
-        rlp.extend(&[nonce.low_u64() as u8]);
-    } else {
-        let mut bytes = nonce.to_big_endian().to_vec();
-        while bytes[0] == 0 {
-            bytes.remove(0);
-        }
-        rlp.push(0x80u8 + bytes.len() as u8);
-        rlp.extend(&bytes);
+/// Address of a contract deployed by `caller` with CREATE, `keccak(rlp([caller, nonce]))`.
+fn create_address(caller: Address, nonce: U256) -> Address {
+    let mut payload = Vec::new();
+    caller.rlp_append(&mut payload);
+    nonce.rlp_append(&mut payload);
+    let mut out = Vec::new();
+    rlp::encode_list_payload(&mut out, &payload);
+    Address::from_slice(&keccak(&out)[12..32])
+}
+
+/// Precompiles active at `spec`.
+pub(crate) fn default_precompiles(spec: Spec) -> HashMap<Address, Precompile> {
+    let mut precompiles: HashMap<Address, Precompile> = [
+        (
+            Address::with_last_byte(0x01),
+            &precompiles::ecrecover as Precompile,
+        ),
+        (Address::with_last_byte(0x09), &precompiles::blake2f),
+        (
+            Address::with_last_byte(0x0a),
+            &precompiles::point_evaluation,
+        ),
+    ]
+    .into_iter()
+    .collect();
+    if spec >= Spec::Prague {
+        precompiles.extend([
+            (
+                Address::with_last_byte(0x0b),
+                &precompiles::bls12_g1_add as Precompile,
+            ),
+            (Address::with_last_byte(0x0c), &precompiles::bls12_g1_msm),
+            (Address::with_last_byte(0x0d), &precompiles::bls12_g2_add),
+            (Address::with_last_byte(0x0e), &precompiles::bls12_g2_msm),
+            (
+                Address::with_last_byte(0x0f),
+                &precompiles::bls12_pairing_check,
+            ),
+            (
+                Address::with_last_byte(0x10),
+                &precompiles::bls12_map_fp_to_g1,
+            ),
+            (
+                Address::with_last_byte(0x11),
+                &precompiles::bls12_map_fp2_to_g2,
+            ),
+        ]);
     }
-    rlp
+    precompiles
 }
 
-fn ecrecover(_call_info: CallInfo<U256>) -> Result<ExecutionResult, ExecError> {
-    Err(ExecError::Revert(RevertError::UnknownOpcode(0x0)))
+/// Values of the block and transaction environment readable by the EVM.
+pub(crate) fn env_info(spec: Spec, block: &BlockEnv, tx: &TxEnv, inf: Info) -> U256 {
+    match inf {
+        Info::GasPrice => tx.gas_price,
+        Info::Coinbase => U256::from_addr(block.coinbase),
+        Info::Timestamp => block.timestamp,
+        Info::Number => block.number,
+        Info::PrevRandao => block.prev_randao,
+        Info::GasLimit => block.gas_limit,
+        Info::ChainId => block.chain_id,
+        Info::BaseFee => block.base_fee,
+        Info::BlobBaseFee => block.blob_base_fee(spec.blob_base_fee_update_fraction()),
+    }
 }
 
 impl MiniEthereum {
     pub fn new() -> Self {
-        let ecrecover: &'static dyn Fn(CallInfo<U256>) -> Result<ExecutionResult, ExecError> =
-            &ecrecover;
+        Self::with_spec(Spec::default())
+    }
+    pub fn with_spec(spec: Spec) -> Self {
+        let precompiles = default_precompiles(spec);
         Self {
-            precompiles: [(Address::ZERO, ecrecover)].into_iter().collect(),
+            spec,
+            block: BlockEnv::default(),
+            tx: TxEnv::default(),
+            block_hashes: VecDeque::new(),
+            precompiles,
             accounts: HashMap::new(),
             transient: HashMap::new(),
+            logs: Vec::new(),
+            state_trie: StateTrie::new(),
+            dirty: HashMap::new(),
+            journals: Vec::new(),
+            next_snapshot: 0,
+            touched: HashSet::new(),
+        }
+    }
+    /// Adds `amount` to the balance of `address`.
+    pub fn fund(&mut self, address: Address, amount: U256) {
+        self.account_mut(address).value += amount;
+    }
+    pub fn with_funded_accounts(mut self, addresses: &[Address], amount: U256) -> Self {
+        for address in addresses {
+            self.fund(*address, amount);
+        }
+        self
+    }
+    pub fn with_block_env(mut self, block: BlockEnv) -> Self {
+        self.block = block;
+        self
+    }
+    pub fn with_tx_env(mut self, tx: TxEnv) -> Self {
+        self.tx = tx;
+        self
+    }
+    /// Records the hash of a past block, keeping only the most recent
+    /// `BLOCK_HASH_WINDOW` of them.
+    pub fn insert_block_hash(&mut self, number: U256, hash: U256) {
+        let block_hashes = self.block_hashes_mut();
+        let pos = block_hashes.partition_point(|(n, _)| *n < number);
+        match block_hashes.get_mut(pos) {
+            Some(entry) if entry.0 == number => entry.1 = hash,
+            _ => block_hashes.insert(pos, (number, hash)),
         }
+        let newest = block_hashes.back().unwrap().0;
+        while newest - block_hashes.front().unwrap().0 >= U256::from(BLOCK_HASH_WINDOW) {
+            block_hashes.pop_front();
+        }
+    }
+    pub fn with_block_hash(mut self, number: U256, hash: U256) -> Self {
+        self.insert_block_hash(number, hash);
+        self
+    }
+    /// Enables the RIP-7212 P256VERIFY precompile at 0x100, as deployed on chains
+    /// supporting passkey signatures.
+    pub fn with_p256_verify(mut self) -> Self {
+        self.precompiles.insert(
+            Address::left_padding_from(&[0x01, 0x00]),
+            &precompiles::p256_verify,
+        );
+        self
+    }
+}
+
+impl BlockHistory for MiniEthereum {
+    fn past_block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>> {
+        if let Some((_, hash)) = self.block_hashes.iter().find(|(n, _)| *n == number) {
+            return Ok(*hash);
+        }
+        if self.spec >= Spec::Prague {
+            let slot = number % U256::from(HISTORY_SERVE_WINDOW);
+            let hash = self.sload(HISTORY_STORAGE_ADDRESS, slot)?;
+            if hash != U256::ZERO {
+                return Ok(hash);
+            }
+        }
+        // Unknown hashes read as zero, like in clients.
+        Ok(U256::ZERO)
     }
 }
 
@@ -119,8 +525,8 @@ impl Context<U256> for MiniEthereum {
     fn destroy(&self, _contract: Address, _target: Address) -> Result<(), ExecError> {
         Err(ExecError::Revert(RevertError::UnknownOpcode(0xff)))
     }
-    fn blob_hash(&self, _index: U256) -> Result<U256, Box<dyn Error>> {
-        Ok(U256::ZERO)
+    fn blob_hash(&self, index: U256) -> Result<U256, Box<dyn Error>> {
+        Ok(self.tx.blob_hash(index))
     }
     fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
         Ok(self
@@ -129,13 +535,14 @@ impl Context<U256> for MiniEthereum {
             .map(|a| a.code.clone())
             .unwrap_or_default())
     }
-    fn block_hash(&self, _block_number: U256) -> Result<U256, Box<dyn Error>> {
-        Ok(U256::ZERO)
+    fn block_hash(&self, block_number: U256) -> Result<U256, Box<dyn Error>> {
+        if !self.block.has_block_hash(block_number) {
+            return Ok(U256::ZERO);
+        }
+        self.past_block_hash(block_number)
     }
     fn info(&self, inf: Info) -> Result<U256, Box<dyn Error>> {
-        match inf {
-            _ => Ok(U256::ZERO),
-        }
+        Ok(env_info(self.spec, &self.block, &self.tx, inf))
     }
 
     fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
@@ -145,6 +552,13 @@ impl Context<U256> for MiniEthereum {
             .map(|a| a.value)
             .unwrap_or_default())
     }
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        Ok(self
+            .accounts
+            .get(&address)
+            .map(|a| a.nonce)
+            .unwrap_or_default())
+    }
     fn sload(&self, contract: Address, address: U256) -> Result<U256, Box<dyn Error>> {
         Ok(if let Some(acc) = self.accounts.get(&contract) {
             acc.storage.get(&address).copied().unwrap_or_default()
@@ -156,7 +570,7 @@ impl Context<U256> for MiniEthereum {
 
 impl ContextMut<U256> for MiniEthereum {
     fn tstore(&mut self, address: U256, value: U256) -> Result<(), Box<dyn Error>> {
-        self.transient.insert(address, value);
+        self.set_transient(address, value);
         Ok(())
     }
     fn create(
@@ -166,56 +580,7 @@ impl ContextMut<U256> for MiniEthereum {
         call_info: CallInfo<U256>,
         salt: Option<U256>,
     ) -> Result<Address, ExecError> {
-        let acc = self.accounts.entry(call_info.caller).or_default();
-        if acc.value >= call_info.value {
-            acc.value = acc.value - call_info.value;
-            acc.nonce = acc.nonce + U256::ONE;
-        } else {
-            return Err(ExecError::Revert(RevertError::InsufficientBalance));
-        }
-        let contract_addr = if let Some(salt) = salt {
-            let mut inp = vec![0xffu8];
-            inp.extend(call_info.caller.as_slice());
-            inp.extend(&salt.to_big_endian());
-            inp.extend(&keccak(&call_info.data));
-            Address::from_slice(&keccak(&inp)[12..32])
-        } else {
-            Address::from_slice(&keccak(&rlp_address_nonce(call_info.caller, acc.nonce))[12..32])
-        };
-
-        if self
-            .accounts
-            .get(&contract_addr)
-            .map(|a| a.code.len())
-            .unwrap_or_default()
-            != 0
-        {
-            return Err(ExecError::Revert(RevertError::ContractAlreadyDeployed));
-        }
-
-        self.accounts.entry(contract_addr).or_default();
-        self.accounts.get_mut(&contract_addr).unwrap().value = call_info.value;
-
-        let exec_result = Machine::new(contract_addr, call_info.data, gas_tracker, stack_size)
-            .run(
-                self,
-                &CallInfo {
-                    value: call_info.value,
-                    data: vec![],
-                    origin: call_info.origin,
-                    caller: call_info.caller,
-                    is_static: false,
-                },
-            )?;
-
-        match exec_result {
-            ExecutionResult::Halted => {}
-            ExecutionResult::Returned(code) => {
-                self.accounts.get_mut(&contract_addr).unwrap().code = code;
-            }
-        }
-
-        Ok(contract_addr)
+        create_frame(self, stack_size, gas_tracker, call_info, salt)
     }
     fn call(
         &mut self,
@@ -224,21 +589,7 @@ impl ContextMut<U256> for MiniEthereum {
         address: Address,
         call_info: CallInfo<U256>,
     ) -> Result<ExecutionResult, ExecError> {
-        if let Some(precompile) = self.precompiles.get(&address) {
-            return precompile(call_info);
-        }
-        let caller = self.accounts.entry(call_info.caller).or_default();
-        if caller.value >= call_info.value {
-            caller.value = caller.value - call_info.value;
-            caller.nonce = caller.nonce + U256::ONE;
-        } else {
-            return Err(ExecError::Revert(RevertError::InsufficientBalance));
-        }
-        let contract = self.accounts.entry(address).or_default();
-        contract.value = contract.value + call_info.value;
-        let machine = Machine::new(address, contract.code.clone(), gas_tracker, stack_size);
-        let exec_result = machine.run(self, &call_info)?;
-        Ok(exec_result)
+        call_frame(self, stack_size, gas_tracker, address, call_info)
     }
     fn sstore(
         &mut self,
@@ -246,11 +597,7 @@ impl ContextMut<U256> for MiniEthereum {
         address: U256,
         value: U256,
     ) -> Result<(), Box<dyn Error>> {
-        self.accounts
-            .entry(contract)
-            .or_default()
-            .storage
-            .insert(address, value);
+        self.set_storage(contract, address, value);
         Ok(())
     }
     fn log(
@@ -259,7 +606,58 @@ impl ContextMut<U256> for MiniEthereum {
         topics: Vec<U256>,
         data: Vec<u8>,
     ) -> Result<(), Box<dyn Error>> {
-        println!("New log from {}: {:?} {:?}", address, topics, data);
+        self.logs.push(Log {
+            address,
+            topics,
+            data,
+        });
+        Ok(())
+    }
+}
+
+impl State for MiniEthereum {
+    fn set_nonce(&mut self, address: Address, nonce: U256) -> Result<(), Box<dyn Error>> {
+        self.account_mut(address).nonce = nonce;
+        Ok(())
+    }
+    fn set_balance(&mut self, address: Address, balance: U256) -> Result<(), Box<dyn Error>> {
+        self.account_mut(address).value = balance;
+        Ok(())
+    }
+    fn set_code(&mut self, address: Address, code: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        self.account_mut(address).code = code;
+        Ok(())
+    }
+    fn delete_account(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        self.remove_account(address);
+        Ok(())
+    }
+    fn precompile(&self, address: Address) -> Option<Precompile> {
+        self.precompiles.get(&address).copied()
+    }
+    fn logs(&mut self) -> &mut Vec<Log> {
+        &mut self.logs
+    }
+    fn checkpoint(&mut self) -> usize {
+        self.snapshot()
+    }
+    fn revert_checkpoint(&mut self, id: usize) {
+        self.revert_to(id);
+    }
+    fn discard_checkpoint(&mut self, id: usize) {
+        self.discard_snapshot(id);
+    }
+    fn spec(&self) -> Spec {
+        self.spec
+    }
+    fn begin_transaction(&mut self, tx: TxEnv) {
+        self.set_tx_env(tx);
+        self.logs.clear();
+        self.touched.clear();
+    }
+    fn end_transaction(&mut self) -> Result<(), Box<dyn Error>> {
+        self.clear_transient();
+        self.remove_touched_empty();
         Ok(())
     }
 }
@@ -267,6 +665,8 @@ impl ContextMut<U256> for MiniEthereum {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{disk::DiskEthereum, overlay::Overlay, store::tests::TempPath};
+    use alloy_primitives::primitives::hex;
 
     fn addr(v: u8) -> Address {
         let mut arr = [0u8; 20];
@@ -384,6 +784,25 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_create_address() {
+        let caller = address!("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        assert_eq!(
+            create_address(caller, U256::ZERO),
+            address!("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+        );
+        assert_eq!(
+            create_address(caller, U256::ONE),
+            address!("343c43a37d37dff08ae8c4a11544c718abb4fcf8")
+        );
+        assert_eq!(
+            create_address(caller, U256::from(0x1000)),
+            Address::from_slice(
+                &keccak(&hex!("d8946ac7ea33f8831ea9dcc53393aaa88b25a785dbf0821000"))[12..32]
+            )
+        );
+    }
+
     #[test]
     fn test_call() {
         let mut gt = GasTracker::new(10000000);
@@ -407,7 +826,7 @@ mod tests {
             },
         )
         .unwrap();
-        assert_eq!(ctx.accounts.get(&addr(123)).unwrap().nonce, U256::from(1));
+        assert_eq!(ctx.accounts.get(&addr(123)).unwrap().nonce, U256::from(0));
         assert_eq!(ctx.balance(addr(123)).unwrap(), U256::from(3));
         assert_eq!(ctx.accounts.get(&addr(234)).unwrap().nonce, U256::from(0));
         assert_eq!(ctx.balance(addr(234)).unwrap(), U256::from(2));
@@ -453,6 +872,9 @@ mod tests {
             )
             .unwrap();
         assert_eq!(ctx.accounts.get(&addr(123)).unwrap().nonce, U256::from(1));
+        // Ether sent to the address before the deployment.
+        ctx.set_balance(create_address(addr(123), U256::ONE), U256::from(3))
+            .unwrap();
         let contract_addr_2 = ctx
             .create(
                 1024,
@@ -470,14 +892,14 @@ mod tests {
         assert_eq!(ctx.accounts.get(&addr(123)).unwrap().nonce, U256::from(2));
         assert_eq!(ctx.balance(addr(123)).unwrap(), U256::from(1));
         assert_eq!(ctx.balance(contract_addr_1).unwrap(), U256::from(2));
-        assert_eq!(ctx.balance(contract_addr_2).unwrap(), U256::from(2));
+        assert_eq!(ctx.balance(contract_addr_2).unwrap(), U256::from(5));
         assert_eq!(
             contract_addr_1.to_string(),
-            "0x838FeA66B9b3aAe5120D989B4Ab767396f2fcBf1".to_string()
+            "0xBEF8BC89756284170ec0EdD9F15dcf23EAD26233".to_string()
         );
         assert_eq!(
             contract_addr_2.to_string(),
-            "0xae7FaC60782BB47C1E93A68b344Aa5AFF8A644bA".to_string()
+            "0x2Ce562Be1E6e71f3679d1D25D6DbF1bfF7073fB8".to_string()
         );
     }
     #[test]
@@ -534,4 +956,244 @@ mod tests {
             Err(ExecError::Revert(RevertError::ContractAlreadyDeployed))
         );
     }
+
+    #[test]
+    fn test_prague_precompiles() {
+        let g1_add = Address::with_last_byte(0x0b);
+        let call_info = CallInfo {
+            origin: addr(123),
+            caller: addr(123),
+            value: U256::ZERO,
+            data: vec![],
+            is_static: false,
+        };
+        let mut gt = GasTracker::new(10000000);
+        let mut ctx = MiniEthereum::with_spec(Spec::Cancun);
+        assert_eq!(
+            ctx.call(1024, &mut gt, g1_add, call_info.clone()),
+            Ok(ExecutionResult::Halted)
+        );
+        let mut ctx = MiniEthereum::with_spec(Spec::Prague);
+        assert_eq!(
+            ctx.call(1024, &mut gt, g1_add, call_info),
+            Err(ExecError::Revert(RevertError::InvalidPrecompileInput))
+        );
+    }
+
+    #[test]
+    fn test_optional_p256_verify() {
+        let p256_verify = Address::left_padding_from(&[0x01, 0x00]);
+        let call_info = CallInfo {
+            origin: addr(123),
+            caller: addr(123),
+            value: U256::ZERO,
+            data: vec![],
+            is_static: false,
+        };
+        let mut gt = GasTracker::new(10000000);
+        let mut ctx = MiniEthereum::new();
+        assert_eq!(
+            ctx.call(1024, &mut gt, p256_verify, call_info.clone()),
+            Ok(ExecutionResult::Halted)
+        );
+        let mut ctx = MiniEthereum::new().with_p256_verify();
+        assert_eq!(
+            ctx.call(1024, &mut gt, p256_verify, call_info),
+            Ok(ExecutionResult::Returned(vec![]))
+        );
+    }
+
+    #[test]
+    fn test_block_and_tx_env() {
+        let ctx = MiniEthereum::new()
+            .with_block_env(
+                BlockEnv::default()
+                    .with_number(U256::from(100))
+                    .with_coinbase(addr(7))
+                    .with_timestamp(U256::from(1700000000))
+                    .with_gas_limit(U256::from(30000000))
+                    .with_base_fee(U256::from(1000))
+                    .with_prev_randao(U256::from(42))
+                    .with_excess_blob_gas(U256::from(10000000))
+                    .with_chain_id(U256::from(1)),
+            )
+            .with_tx_env(TxEnv::default().with_gas_price(U256::from(2000)));
+        for (inf, expected) in [
+            (Info::GasPrice, U256::from(2000)),
+            (Info::Coinbase, U256::from(7)),
+            (Info::Timestamp, U256::from(1700000000)),
+            (Info::Number, U256::from(100)),
+            (Info::PrevRandao, U256::from(42)),
+            (Info::GasLimit, U256::from(30000000)),
+            (Info::ChainId, U256::from(1)),
+            (Info::BaseFee, U256::from(1000)),
+            (Info::BlobBaseFee, U256::from(7)),
+        ] {
+            assert_eq!(ctx.info(inf).unwrap(), expected);
+        }
+
+        // CHAINID, PUSH0, MSTORE, PUSH1 0x20, PUSH0, RETURN
+        let mut ctx = ctx;
+        ctx.accounts.insert(
+            addr(0x42),
+            Account {
+                code: vec![0x46, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xf3],
+                ..Default::default()
+            },
+        );
+        let mut gt = GasTracker::new(10000000);
+        assert_eq!(
+            ctx.call(1024, &mut gt, addr(0x42), CallInfo::default()),
+            Ok(ExecutionResult::Returned(U256::from(1).to_be_bytes_vec()))
+        );
+    }
+
+    #[test]
+    fn test_block_hash() {
+        let mut ctx = MiniEthereum::with_spec(Spec::Cancun)
+            .with_block_env(BlockEnv::default().with_number(U256::from(1000)));
+        for n in 0..1000u64 {
+            ctx.insert_block_hash(U256::from(n), U256::from(n + 1));
+        }
+        assert_eq!(ctx.block_hashes.len(), 256);
+        assert_eq!(ctx.block_hash(U256::from(999)).unwrap(), U256::from(1000));
+        assert_eq!(ctx.block_hash(U256::from(744)).unwrap(), U256::from(745));
+        // Outside of the 256 block window
+        assert_eq!(ctx.block_hash(U256::from(743)).unwrap(), U256::ZERO);
+        assert_eq!(ctx.block_hash(U256::from(1000)).unwrap(), U256::ZERO);
+        assert_eq!(ctx.block_hash(U256::MAX).unwrap(), U256::ZERO);
+
+        // Unknown hash inside the window
+        ctx.block.number = U256::from(1010);
+        assert_eq!(ctx.block_hash(U256::from(1005)).unwrap(), U256::ZERO);
+
+        // Prague falls back to the EIP-2935 history contract
+        ctx.spec = Spec::Prague;
+        ctx.sstore(
+            HISTORY_STORAGE_ADDRESS,
+            U256::from(1005 % HISTORY_SERVE_WINDOW),
+            U256::from(123),
+        )
+        .unwrap();
+        assert_eq!(ctx.block_hash(U256::from(1005)).unwrap(), U256::from(123));
+        assert_eq!(ctx.block_hash(U256::from(1006)).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_blob_tx_context() {
+        let hashes = vec![U256::from(0x0101), U256::from(0x0102)];
+        let mut ctx = MiniEthereum::with_spec(Spec::Cancun)
+            .with_block_env(BlockEnv::default().with_excess_blob_gas(U256::from(10000000)))
+            .with_tx_env(TxEnv::default().with_blob_versioned_hashes(hashes.clone()));
+        assert_eq!(ctx.blob_hash(U256::ZERO).unwrap(), hashes[0]);
+        assert_eq!(ctx.blob_hash(U256::ONE).unwrap(), hashes[1]);
+        assert_eq!(ctx.blob_hash(U256::from(2)).unwrap(), U256::ZERO);
+        assert_eq!(ctx.blob_hash(U256::MAX).unwrap(), U256::ZERO);
+        assert_eq!(ctx.info(Info::BlobBaseFee).unwrap(), U256::from(19));
+
+        // PUSH1 0x01, BLOBHASH, PUSH0, MSTORE, PUSH1 0x20, PUSH0, RETURN
+        ctx.accounts.insert(
+            addr(0x42),
+            Account {
+                code: vec![0x60, 0x01, 0x49, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xf3],
+                ..Default::default()
+            },
+        );
+        let mut gt = GasTracker::new(10000000);
+        assert_eq!(
+            ctx.call(1024, &mut gt, addr(0x42), CallInfo::default()),
+            Ok(ExecutionResult::Returned(hashes[1].to_be_bytes_vec()))
+        );
+    }
+
+    /// Stores 1 in slot 0 and reverts: `PUSH1 1 PUSH0 SSTORE PUSH0 PUSH0 REVERT`.
+    const STORE_AND_REVERT: [u8; 7] = hex!("60015f555f5ffd");
+
+    /// Calls 0x02 with 1 wei, then stores 7 in slot 0.
+    const CALL_THEN_STORE: [u8; 16] = hex!("5f5f5f5f600160025af15060075f5500");
+
+    /// Stores 1 in transient slot 0 and reverts.
+    const TSTORE_AND_REVERT: [u8; 7] = hex!("60015f5d5f5ffd");
+
+    /// Stores 2 in transient slot 0, calls 0x04, then copies transient slot 0 to slot 0.
+    const TSTORE_CALL_THEN_COPY: [u8; 19] = hex!("60025f5d5f5f5f5f5f60045af1505f5c5f5500");
+
+    fn check_frame_revert<C: ContextMut<U256> + State>(ctx: &mut C) {
+        ctx.set_balance(addr(1), U256::from(100)).unwrap();
+        ctx.set_code(addr(2), STORE_AND_REVERT.to_vec()).unwrap();
+        ctx.set_balance(addr(3), U256::from(5)).unwrap();
+        ctx.set_code(addr(3), CALL_THEN_STORE.to_vec()).unwrap();
+        let call_info = |value: u64, data: &[u8]| CallInfo {
+            origin: addr(1),
+            caller: addr(1),
+            value: U256::from(value),
+            data: data.to_vec(),
+            is_static: false,
+        };
+        let reverted = |res: Result<_, ExecError>| {
+            matches!(res, Err(ExecError::Revert(RevertError::Revert(_))))
+        };
+
+        let res = ctx.call(
+            1024,
+            &mut GasTracker::new(100_000),
+            addr(2),
+            call_info(10, &[]),
+        );
+        assert!(reverted(res.map(|_| ())));
+        assert_eq!(ctx.balance(addr(1)).unwrap(), U256::from(100));
+        assert_eq!(ctx.balance(addr(2)).unwrap(), U256::ZERO);
+        assert_eq!(ctx.sload(addr(2), U256::ZERO).unwrap(), U256::ZERO);
+
+        let res = ctx.create(
+            1024,
+            &mut GasTracker::new(100_000),
+            call_info(10, &STORE_AND_REVERT),
+            None,
+        );
+        assert!(reverted(res.map(|_| ())));
+        // Only the nonce of the caller is kept.
+        assert_eq!(ctx.nonce(addr(1)).unwrap(), U256::ONE);
+        assert_eq!(ctx.balance(addr(1)).unwrap(), U256::from(100));
+        let created = create_address(addr(1), U256::ZERO);
+        assert_eq!(ctx.nonce(created).unwrap(), U256::ZERO);
+        assert_eq!(ctx.balance(created).unwrap(), U256::ZERO);
+        assert_eq!(ctx.sload(created, U256::ZERO).unwrap(), U256::ZERO);
+
+        // A reverted subcall leaves the writes of its caller in place.
+        ctx.call(
+            1024,
+            &mut GasTracker::new(100_000),
+            addr(3),
+            call_info(0, &[]),
+        )
+        .unwrap();
+        assert_eq!(ctx.sload(addr(3), U256::ZERO).unwrap(), U256::from(7));
+        assert_eq!(ctx.balance(addr(3)).unwrap(), U256::from(5));
+        assert_eq!(ctx.balance(addr(2)).unwrap(), U256::ZERO);
+        assert_eq!(ctx.sload(addr(2), U256::ZERO).unwrap(), U256::ZERO);
+
+        // Transient storage is rolled back with the frame that wrote it.
+        ctx.set_code(addr(4), TSTORE_AND_REVERT.to_vec()).unwrap();
+        ctx.set_code(addr(5), TSTORE_CALL_THEN_COPY.to_vec())
+            .unwrap();
+        ctx.call(
+            1024,
+            &mut GasTracker::new(100_000),
+            addr(5),
+            call_info(0, &[]),
+        )
+        .unwrap();
+        assert_eq!(ctx.sload(addr(5), U256::ZERO).unwrap(), U256::from(2));
+        assert_eq!(ctx.tload(U256::ZERO).unwrap(), U256::from(2));
+    }
+
+    #[test]
+    fn test_frame_revert() {
+        check_frame_revert(&mut MiniEthereum::new());
+        let base = MiniEthereum::new();
+        check_frame_revert(&mut Overlay::new(&base));
+        let path = TempPath::new("frame-revert");
+        check_frame_revert(&mut DiskEthereum::open(&path.0).unwrap());
+    }
 }
@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+
+use alloy_primitives::primitives::{hex, Address};
+use serde_json::{Map, Value};
+
+use crate::{genesis::format_word, u256::U256};
+
+/// Balance, nonce, code and the relevant storage slots of an account at one point in time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountState {
+    pub balance: U256,
+    pub nonce: U256,
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<U256, U256>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn new(before: T, after: T) -> Option<Self> {
+        (before != after).then_some(Self { before, after })
+    }
+}
+
+/// Fields of an account that changed, unchanged ones are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountDiff {
+    pub balance: Option<Change<U256>>,
+    pub nonce: Option<Change<U256>>,
+    pub code: Option<Change<Vec<u8>>>,
+    pub storage: BTreeMap<U256, Change<U256>>,
+}
+
+impl AccountDiff {
+    /// Compares two states of an account, slots missing from one side are zero.
+    pub fn new(pre: &AccountState, post: &AccountState) -> Self {
+        let mut storage = BTreeMap::new();
+        for slot in pre.storage.keys().chain(post.storage.keys()) {
+            let before = pre.storage.get(slot).copied().unwrap_or_default();
+            let after = post.storage.get(slot).copied().unwrap_or_default();
+            if let Some(change) = Change::new(before, after) {
+                storage.insert(*slot, change);
+            }
+        }
+        Self {
+            balance: Change::new(pre.balance, post.balance),
+            nonce: Change::new(pre.nonce, post.nonce),
+            code: Change::new(pre.code.clone(), post.code.clone()),
+            storage,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_none()
+            && self.nonce.is_none()
+            && self.code.is_none()
+            && self.storage.is_empty()
+    }
+}
+
+/// What an execution read and wrote: the prestate of every account it touched and the
+/// changes it made, the data of geth's `prestateTracer`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateDiff {
+    pub prestate: BTreeMap<Address, AccountState>,
+    /// Changed accounts only.
+    pub changes: BTreeMap<Address, AccountDiff>,
+}
+
+fn format_nonce(nonce: &U256) -> Value {
+    u64::try_from(*nonce)
+        .map(Value::from)
+        .unwrap_or_else(|_| format!("{:#x}", nonce).into())
+}
+
+fn storage_json<'a>(slots: impl Iterator<Item = (&'a U256, &'a U256)>) -> Option<Value> {
+    let storage: Map<String, Value> = slots
+        .filter(|(_, value)| !value.is_zero())
+        .map(|(slot, value)| (format_word(slot), format_word(value).into()))
+        .collect();
+    (!storage.is_empty()).then_some(Value::Object(storage))
+}
+
+/// An account in the format of `prestateTracer`, zero fields are left out except for the
+/// balance.
+fn account_json(account: &AccountState) -> Value {
+    let mut entry = Map::new();
+    entry.insert("balance".into(), format!("{:#x}", account.balance).into());
+    if !account.nonce.is_zero() {
+        entry.insert("nonce".into(), format_nonce(&account.nonce));
+    }
+    if !account.code.is_empty() {
+        entry.insert("code".into(), hex::encode_prefixed(&account.code).into());
+    }
+    if let Some(storage) = storage_json(account.storage.iter()) {
+        entry.insert("storage".into(), storage);
+    }
+    Value::Object(entry)
+}
+
+impl StateDiff {
+    /// Builds the diff from the state of each touched account before and after execution.
+    pub fn new(accounts: impl IntoIterator<Item = (Address, AccountState, AccountState)>) -> Self {
+        let mut diff = Self::default();
+        for (address, pre, post) in accounts {
+            let changes = AccountDiff::new(&pre, &post);
+            if !changes.is_empty() {
+                diff.changes.insert(address, changes);
+            }
+            diff.prestate.insert(address, pre);
+        }
+        diff
+    }
+
+    /// `prestateTracer` output: the prestate of every touched account.
+    pub fn prestate_json(&self) -> Value {
+        Value::Object(
+            self.prestate
+                .iter()
+                .map(|(address, account)| (hex::encode_prefixed(address), account_json(account)))
+                .collect(),
+        )
+    }
+
+    /// `prestateTracer` output in diff mode: `pre` holds the prestate of the changed
+    /// accounts with their changed slots only, `post` holds the changed fields.
+    pub fn diff_json(&self) -> Value {
+        let mut pre = Map::new();
+        let mut post = Map::new();
+        for (address, changes) in &self.changes {
+            let key = hex::encode_prefixed(address);
+            let mut account = self.prestate[address].clone();
+            account
+                .storage
+                .retain(|slot, _| changes.storage.contains_key(slot));
+            // Accounts created by the execution have no prestate.
+            let existed = !account.balance.is_zero()
+                || !account.nonce.is_zero()
+                || !account.code.is_empty()
+                || account.storage.values().any(|v| !v.is_zero());
+            if existed {
+                pre.insert(key.clone(), account_json(&account));
+            }
+
+            let mut entry = Map::new();
+            if let Some(balance) = &changes.balance {
+                entry.insert("balance".into(), format!("{:#x}", balance.after).into());
+            }
+            if let Some(nonce) = &changes.nonce {
+                entry.insert("nonce".into(), format_nonce(&nonce.after));
+            }
+            if let Some(code) = &changes.code {
+                entry.insert("code".into(), hex::encode_prefixed(&code.after).into());
+            }
+            let slots = changes.storage.iter().map(|(slot, c)| (slot, &c.after));
+            if let Some(storage) = storage_json(slots) {
+                entry.insert("storage".into(), storage);
+            }
+            post.insert(key, Value::Object(entry));
+        }
+        let mut out = Map::new();
+        out.insert("pre".into(), Value::Object(pre));
+        out.insert("post".into(), Value::Object(post));
+        Value::Object(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::{Context, ContextMut, MiniEthereum},
+        machine::{CallInfo, GasTracker},
+        overlay::Overlay,
+        tests::{addr, COUNTER},
+        transaction::{execute_transaction, TxEip1559},
+    };
+    use serde_json::json;
+
+    fn base() -> MiniEthereum {
+        let mut ctx = MiniEthereum::new();
+        ctx.fund(addr(1), U256::from(1000));
+        ctx.fund(addr(9), U256::from(7));
+        let counter = ctx.accounts.entry(addr(0x42)).or_default();
+        counter.nonce = U256::ONE;
+        counter.code = COUNTER.to_vec();
+        counter.storage.insert(U256::ZERO, U256::from(5));
+        counter.storage.insert(U256::ONE, U256::from(9));
+        ctx
+    }
+
+    #[test]
+    fn test_overlay_state_diff() {
+        let base = base();
+        let mut overlay = Overlay::new(&base);
+        overlay
+            .call(
+                1024,
+                &mut GasTracker::new(100_000),
+                addr(0x42),
+                CallInfo {
+                    origin: addr(1),
+                    caller: addr(1),
+                    value: U256::from(10),
+                    data: vec![],
+                    is_static: false,
+                },
+            )
+            .unwrap();
+        // Read only, part of the prestate but not of the changes.
+        overlay.balance(addr(9)).unwrap();
+
+        let diff = overlay.state_diff().unwrap();
+        assert_eq!(
+            diff.prestate.keys().collect::<Vec<_>>(),
+            [&addr(1), &addr(9), &addr(0x42)]
+        );
+        assert_eq!(
+            diff.changes[&addr(0x42)],
+            AccountDiff {
+                balance: Some(Change {
+                    before: U256::ZERO,
+                    after: U256::from(10)
+                }),
+                storage: [(
+                    U256::ZERO,
+                    Change {
+                        before: U256::from(5),
+                        after: U256::from(6)
+                    }
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            }
+        );
+        assert!(!diff.changes.contains_key(&addr(9)));
+
+        let counter = "0x0000000000000000000000000000000000000042";
+        let slot0 = format_word(&U256::ZERO);
+        assert_eq!(
+            diff.prestate_json(),
+            json!({
+                "0x0000000000000000000000000000000000000001": { "balance": "0x3e8" },
+                "0x0000000000000000000000000000000000000009": { "balance": "0x7" },
+                counter: {
+                    "balance": "0x0",
+                    "nonce": 1,
+                    "code": "0x5f54600101805f555f5260205ff3",
+                    "storage": { slot0.clone(): format_word(&U256::from(5)) }
+                }
+            })
+        );
+        assert_eq!(
+            diff.diff_json(),
+            json!({
+                "pre": {
+                    "0x0000000000000000000000000000000000000001": { "balance": "0x3e8" },
+                    counter: {
+                        "balance": "0x0",
+                        "nonce": 1,
+                        "code": "0x5f54600101805f555f5260205ff3",
+                        "storage": { slot0.clone(): format_word(&U256::from(5)) }
+                    }
+                },
+                "post": {
+                    "0x0000000000000000000000000000000000000001": { "balance": "0x3de" },
+                    counter: {
+                        "balance": "0xa",
+                        "storage": { slot0: format_word(&U256::from(6)) }
+                    }
+                }
+            })
+        );
+
+        overlay.discard();
+        assert_eq!(overlay.state_diff().unwrap(), StateDiff::default());
+    }
+
+    #[test]
+    fn test_transaction_state_diff() {
+        let mut ctx = base();
+        let snapshot = ctx.snapshot();
+        let tx = TxEip1559 {
+            to: Some(addr(2)),
+            value: U256::from(100),
+            gas_limit: 21000,
+            ..Default::default()
+        };
+        ctx.execute_transaction(addr(1), &tx.into()).unwrap();
+
+        let diff = ctx.state_diff(snapshot).unwrap();
+        assert_eq!(
+            diff.changes.keys().collect::<Vec<_>>(),
+            [&addr(1), &addr(2)]
+        );
+        assert_eq!(
+            diff.changes[&addr(1)].nonce,
+            Some(Change {
+                before: U256::ZERO,
+                after: U256::ONE
+            })
+        );
+        // The recipient did not exist before the transaction.
+        assert_eq!(
+            diff.diff_json(),
+            json!({
+                "pre": {
+                    "0x0000000000000000000000000000000000000001": { "balance": "0x3e8" }
+                },
+                "post": {
+                    "0x0000000000000000000000000000000000000001": { "balance": "0x384", "nonce": 1 },
+                    "0x0000000000000000000000000000000000000002": { "balance": "0x64" }
+                }
+            })
+        );
+        assert!(ctx.discard_snapshot(snapshot));
+
+        // Only the written slots are part of the diff, unread ones are left out.
+        let snapshot = ctx.snapshot();
+        let tx = TxEip1559 {
+            nonce: U256::ONE,
+            to: Some(addr(0x42)),
+            gas_limit: 50_000,
+            ..Default::default()
+        };
+        ctx.execute_transaction(addr(1), &tx.into()).unwrap();
+        let diff = ctx.state_diff(snapshot).unwrap();
+        assert!(!diff.prestate.contains_key(&addr(9)));
+        assert_eq!(
+            diff.prestate[&addr(0x42)].storage,
+            [(U256::ZERO, U256::from(5))].into_iter().collect()
+        );
+        assert_eq!(
+            diff.changes[&addr(0x42)].storage[&U256::ZERO],
+            Change {
+                before: U256::from(5),
+                after: U256::from(6)
+            }
+        );
+    }
+
+    #[test]
+    fn test_snapshot_and_overlay_state_diff() {
+        // PUSH1 0x09 BALANCE POP PUSH0 SLOAD PUSH1 0x01 ADD PUSH0 SSTORE STOP
+        let code = vec![
+            0x60, 0x09, 0x31, 0x50, 0x5f, 0x54, 0x60, 0x01, 0x01, 0x5f, 0x55, 0x00,
+        ];
+        let mut ctx = base();
+        ctx.accounts.entry(addr(0x43)).or_default().code = code;
+        let tx = TxEip1559 {
+            to: Some(addr(0x43)),
+            value: U256::from(3),
+            gas_limit: 50_000,
+            ..Default::default()
+        };
+
+        let mut overlay = Overlay::new(&ctx);
+        execute_transaction(&mut overlay, addr(1), &tx.clone().into()).unwrap();
+        let overlay_diff = overlay.state_diff().unwrap();
+        let snapshot = ctx.snapshot();
+        ctx.execute_transaction(addr(1), &tx.into()).unwrap();
+        let snapshot_diff = ctx.state_diff(snapshot).unwrap();
+
+        assert_eq!(snapshot_diff.changes, overlay_diff.changes);
+        // The balance of addr(9) was only read.
+        assert!(overlay_diff.prestate.contains_key(&addr(9)));
+        assert!(!snapshot_diff.prestate.contains_key(&addr(9)));
+        assert_eq!(
+            snapshot_diff.prestate[&addr(0x43)],
+            overlay_diff.prestate[&addr(0x43)]
+        );
+    }
+}
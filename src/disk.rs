@@ -0,0 +1,685 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    io,
+    path::Path,
+};
+
+use alloy_primitives::primitives::Address;
+
+use crate::{
+    context::{
+        call_frame, create_frame, default_precompiles, env_info, Account, Context, ContextMut,
+        Info, Log, Spec, State, UndoLog, HISTORY_SERVE_WINDOW, HISTORY_STORAGE_ADDRESS,
+    },
+    env::{BlockEnv, TxEnv},
+    error::{ExecError, RevertError},
+    machine::{CallInfo, GasTracker},
+    opcodes::ExecutionResult,
+    overlay::BlockHistory,
+    precompiles::Precompile,
+    rlp::{encode_bytes, encode_list_payload, Encodable, Rlp},
+    state_trie::{code_hash, EMPTY_CODE_HASH},
+    store::{Batch, Store},
+    u256::U256,
+};
+
+const ACCOUNT_PREFIX: u8 = b'a';
+const CODE_PREFIX: u8 = b'c';
+const STORAGE_PREFIX: u8 = b's';
+
+/// Account as stored on disk, the code is stored once per hash under its own key.
+#[derive(Debug, Clone, PartialEq)]
+struct AccountInfo {
+    nonce: U256,
+    balance: U256,
+    code_hash: [u8; 32],
+}
+
+impl Default for AccountInfo {
+    fn default() -> Self {
+        Self {
+            nonce: U256::ZERO,
+            balance: U256::ZERO,
+            code_hash: EMPTY_CODE_HASH,
+        }
+    }
+}
+
+impl AccountInfo {
+    fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        self.nonce.rlp_append(&mut payload);
+        self.balance.rlp_append(&mut payload);
+        encode_bytes(&mut payload, &self.code_hash);
+        let mut out = Vec::new();
+        encode_list_payload(&mut out, &payload);
+        out
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut list = Rlp::new(data).list()?;
+        let nonce = list.decode()?;
+        let balance = list.decode()?;
+        let code_hash = list.hash()?.to_be_bytes::<32>();
+        list.finish()?;
+        Ok(Self {
+            nonce,
+            balance,
+            code_hash,
+        })
+    }
+}
+
+/// A pending write of a `DiskEthereum` to undo, with the pending value it replaced.
+enum Undo {
+    Account(Address, Option<AccountInfo>),
+    Storage(Address, U256, Option<U256>),
+    Transient(U256, Option<U256>),
+    /// An account first written by the current transaction.
+    Touched(Address),
+}
+
+fn account_key(address: Address) -> Vec<u8> {
+    [&[ACCOUNT_PREFIX][..], address.as_slice()].concat()
+}
+
+fn code_key(hash: &[u8; 32]) -> Vec<u8> {
+    [&[CODE_PREFIX][..], hash].concat()
+}
+
+fn storage_key(address: Address, slot: U256) -> Vec<u8> {
+    [
+        &[STORAGE_PREFIX][..],
+        address.as_slice(),
+        &slot.to_be_bytes::<32>(),
+    ]
+    .concat()
+}
+
+/// A context whose state lives in a `Store` on disk, so that it outlives the process.
+///
+/// Changes are buffered in memory until `commit`, which writes them to disk as a single
+/// atomic batch: after a crash the state is the one of the last commit. Committing once
+/// per transaction, for instance through `transact`, never leaves a transaction half
+/// applied.
+pub struct DiskEthereum {
+    pub spec: Spec,
+    pub block: BlockEnv,
+    pub tx: TxEnv,
+    store: Store,
+    precompiles: HashMap<Address, Precompile>,
+    accounts: HashMap<Address, AccountInfo>,
+    code: HashMap<[u8; 32], Vec<u8>>,
+    storage: HashMap<(Address, U256), U256>,
+    /// Accounts written by the current transaction, see `end_transaction`.
+    touched: HashSet<Address>,
+    undo: UndoLog<Undo>,
+    pub transient: HashMap<U256, U256>,
+    pub logs: Vec<Log>,
+}
+
+impl DiskEthereum {
+    /// Opens the state stored at `path`, starting from an empty state if the file does not
+    /// exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_spec(path, Spec::default())
+    }
+
+    pub fn open_with_spec(path: impl AsRef<Path>, spec: Spec) -> io::Result<Self> {
+        Ok(Self {
+            spec,
+            block: BlockEnv::default(),
+            tx: TxEnv::default(),
+            store: Store::open(path)?,
+            precompiles: default_precompiles(spec),
+            accounts: HashMap::new(),
+            code: HashMap::new(),
+            storage: HashMap::new(),
+            touched: HashSet::new(),
+            undo: UndoLog::default(),
+            transient: HashMap::new(),
+            logs: Vec::new(),
+        })
+    }
+
+    pub fn with_block_env(mut self, block: BlockEnv) -> Self {
+        self.block = block;
+        self
+    }
+
+    pub fn with_tx_env(mut self, tx: TxEnv) -> Self {
+        self.tx = tx;
+        self
+    }
+
+    fn account_info(&self, address: Address) -> Result<AccountInfo, Box<dyn Error>> {
+        if let Some(info) = self.accounts.get(&address) {
+            return Ok(info.clone());
+        }
+        match self.store.get(&account_key(address))? {
+            Some(data) => AccountInfo::decode(&data),
+            None => Ok(AccountInfo::default()),
+        }
+    }
+
+    fn update_account(
+        &mut self,
+        address: Address,
+        f: impl FnOnce(&mut AccountInfo),
+    ) -> Result<(), Box<dyn Error>> {
+        let mut info = self.account_info(address)?;
+        f(&mut info);
+        self.touch(address);
+        let previous = self.accounts.insert(address, info);
+        self.undo.record(Undo::Account(address, previous));
+        Ok(())
+    }
+
+    fn touch(&mut self, address: Address) {
+        if self.touched.insert(address) {
+            self.undo.record(Undo::Touched(address));
+        }
+    }
+
+    /// Adds `amount` to the balance of `address`.
+    pub fn fund(&mut self, address: Address, amount: U256) -> Result<(), Box<dyn Error>> {
+        self.update_account(address, |info| info.balance += amount)
+    }
+
+    /// Replaces the account at `address`, e.g. to import the state of a `MiniEthereum`.
+    /// Slots of the previous account missing from `account` are not cleared.
+    pub fn insert_account(
+        &mut self,
+        address: Address,
+        account: &Account,
+    ) -> Result<(), Box<dyn Error>> {
+        self.set_nonce(address, account.nonce)?;
+        self.set_balance(address, account.value)?;
+        self.set_code(address, account.code.clone())?;
+        for (slot, value) in &account.storage {
+            self.sstore(address, *slot, *value)?;
+        }
+        Ok(())
+    }
+
+    /// Whether there are changes not yet committed.
+    pub fn is_dirty(&self) -> bool {
+        !self.accounts.is_empty() || !self.storage.is_empty()
+    }
+
+    /// Writes the pending changes to disk in one atomic batch. Empty accounts and zero
+    /// slots are removed from the store.
+    pub fn commit(&mut self) -> io::Result<()> {
+        let mut batch: Batch = Vec::new();
+        for (address, info) in &self.accounts {
+            let value = (*info != AccountInfo::default()).then(|| info.encode());
+            batch.push((account_key(*address), value));
+            if let Some(code) = self.code.get(&info.code_hash) {
+                let key = code_key(&info.code_hash);
+                if !self.store.contains_key(&key) {
+                    batch.push((key, Some(code.clone())));
+                }
+            }
+        }
+        for ((address, slot), value) in &self.storage {
+            let value = (!value.is_zero()).then(|| value.to_be_bytes::<32>().to_vec());
+            batch.push((storage_key(*address, *slot), value));
+        }
+        self.store.write(&batch)?;
+        self.accounts.clear();
+        self.code.clear();
+        self.storage.clear();
+        self.undo.clear();
+        Ok(())
+    }
+
+    /// Discards the changes made since the last commit.
+    pub fn rollback(&mut self) {
+        self.accounts.clear();
+        self.code.clear();
+        self.storage.clear();
+        self.touched.clear();
+        self.undo.clear();
+        self.transient.clear();
+        self.logs.clear();
+    }
+
+    /// Runs `f` as a transaction: its changes are committed if it succeeds and rolled back
+    /// otherwise. Transient storage does not outlive the transaction.
+    pub fn transact<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, ExecError>,
+    ) -> Result<T, ExecError> {
+        self.logs.clear();
+        let result = f(self);
+        self.transient.clear();
+        match result {
+            Ok(value) => {
+                self.commit().map_err(|e| ExecError::Context(Box::new(e)))?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.rollback();
+                Err(e)
+            }
+        }
+    }
+
+    /// Rewrites the store without the values overwritten by later commits.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.store.compact()
+    }
+}
+
+/// Hashes are read from the EIP-2935 history contract, so they are only available from
+/// Prague on.
+impl BlockHistory for DiskEthereum {
+    fn past_block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>> {
+        if self.spec < Spec::Prague {
+            return Ok(U256::ZERO);
+        }
+        let slot = number % U256::from(HISTORY_SERVE_WINDOW);
+        self.sload(HISTORY_STORAGE_ADDRESS, slot)
+    }
+}
+
+impl Context<U256> for DiskEthereum {
+    type Mutable = Self;
+    fn tload(&self, address: U256) -> Result<U256, Box<dyn Error>> {
+        Ok(self.transient.get(&address).cloned().unwrap_or_default())
+    }
+    fn as_mut(&mut self) -> &mut Self::Mutable {
+        self
+    }
+    fn destroy(&self, _contract: Address, _target: Address) -> Result<(), ExecError> {
+        Err(ExecError::Revert(RevertError::UnknownOpcode(0xff)))
+    }
+    fn blob_hash(&self, index: U256) -> Result<U256, Box<dyn Error>> {
+        Ok(self.tx.blob_hash(index))
+    }
+    fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        let hash = self.account_info(address)?.code_hash;
+        if hash == EMPTY_CODE_HASH {
+            return Ok(vec![]);
+        }
+        if let Some(code) = self.code.get(&hash) {
+            return Ok(code.clone());
+        }
+        self.store.get(&code_key(&hash))?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "missing code for hash").into()
+        })
+    }
+    fn block_hash(&self, block_number: U256) -> Result<U256, Box<dyn Error>> {
+        if !self.block.has_block_hash(block_number) {
+            return Ok(U256::ZERO);
+        }
+        self.past_block_hash(block_number)
+    }
+    fn info(&self, inf: Info) -> Result<U256, Box<dyn Error>> {
+        Ok(env_info(self.spec, &self.block, &self.tx, inf))
+    }
+    fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        Ok(self.account_info(address)?.balance)
+    }
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        Ok(self.account_info(address)?.nonce)
+    }
+    fn sload(&self, contract: Address, address: U256) -> Result<U256, Box<dyn Error>> {
+        if let Some(value) = self.storage.get(&(contract, address)) {
+            return Ok(*value);
+        }
+        Ok(self
+            .store
+            .get(&storage_key(contract, address))?
+            .map(|value| U256::from_be_slice(&value))
+            .unwrap_or_default())
+    }
+}
+
+impl ContextMut<U256> for DiskEthereum {
+    fn tstore(&mut self, address: U256, value: U256) -> Result<(), Box<dyn Error>> {
+        let previous = self.transient.insert(address, value);
+        self.undo.record(Undo::Transient(address, previous));
+        Ok(())
+    }
+    fn create(
+        &mut self,
+        stack_size: usize,
+        gas_tracker: &mut GasTracker,
+        call_info: CallInfo<U256>,
+        salt: Option<U256>,
+    ) -> Result<Address, ExecError> {
+        create_frame(self, stack_size, gas_tracker, call_info, salt)
+    }
+    fn call(
+        &mut self,
+        stack_size: usize,
+        gas_tracker: &mut GasTracker,
+        address: Address,
+        call_info: CallInfo<U256>,
+    ) -> Result<ExecutionResult, ExecError> {
+        call_frame(self, stack_size, gas_tracker, address, call_info)
+    }
+    fn sstore(
+        &mut self,
+        contract: Address,
+        address: U256,
+        value: U256,
+    ) -> Result<(), Box<dyn Error>> {
+        self.touch(contract);
+        let previous = self.storage.insert((contract, address), value);
+        self.undo.record(Undo::Storage(contract, address, previous));
+        Ok(())
+    }
+    fn log(
+        &mut self,
+        address: Address,
+        topics: Vec<U256>,
+        data: Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.logs.push(Log {
+            address,
+            topics,
+            data,
+        });
+        Ok(())
+    }
+}
+
+impl State for DiskEthereum {
+    fn set_nonce(&mut self, address: Address, nonce: U256) -> Result<(), Box<dyn Error>> {
+        self.update_account(address, |info| info.nonce = nonce)
+    }
+    fn set_balance(&mut self, address: Address, balance: U256) -> Result<(), Box<dyn Error>> {
+        self.update_account(address, |info| info.balance = balance)
+    }
+    fn set_code(&mut self, address: Address, code: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let hash = code_hash(&code);
+        if !code.is_empty() {
+            self.code.insert(hash, code);
+        }
+        self.update_account(address, |info| info.code_hash = hash)
+    }
+    fn delete_account(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        let mut slots: Vec<U256> = self
+            .storage
+            .keys()
+            .filter(|(a, _)| *a == address)
+            .map(|(_, slot)| *slot)
+            .collect();
+        // Committed slots are only looked up for accounts on disk.
+        if self.store.contains_key(&account_key(address)) {
+            let prefix = [&[STORAGE_PREFIX][..], address.as_slice()].concat();
+            slots.extend(
+                self.store
+                    .keys()
+                    .filter(|key| key.starts_with(&prefix))
+                    .map(|key| U256::from_be_slice(&key[prefix.len()..])),
+            );
+        }
+        for slot in slots {
+            self.sstore(address, slot, U256::ZERO)?;
+        }
+        // Default accounts are removed from the store on commit.
+        self.update_account(address, |info| *info = AccountInfo::default())
+    }
+    fn precompile(&self, address: Address) -> Option<Precompile> {
+        self.precompiles.get(&address).copied()
+    }
+    fn logs(&mut self) -> &mut Vec<Log> {
+        &mut self.logs
+    }
+    fn checkpoint(&mut self) -> usize {
+        self.undo.checkpoint()
+    }
+    fn revert_checkpoint(&mut self, id: usize) {
+        for undo in self.undo.revert(id) {
+            match undo {
+                Undo::Account(address, Some(info)) => {
+                    self.accounts.insert(address, info);
+                }
+                Undo::Account(address, None) => {
+                    self.accounts.remove(&address);
+                }
+                Undo::Storage(address, slot, Some(value)) => {
+                    self.storage.insert((address, slot), value);
+                }
+                Undo::Storage(address, slot, None) => {
+                    self.storage.remove(&(address, slot));
+                }
+                Undo::Transient(slot, Some(value)) => {
+                    self.transient.insert(slot, value);
+                }
+                Undo::Transient(slot, None) => {
+                    self.transient.remove(&slot);
+                }
+                Undo::Touched(address) => {
+                    self.touched.remove(&address);
+                }
+            }
+        }
+    }
+    fn discard_checkpoint(&mut self, id: usize) {
+        self.undo.discard(id);
+    }
+    fn spec(&self) -> Spec {
+        self.spec
+    }
+    fn begin_transaction(&mut self, tx: TxEnv) {
+        self.tx = tx;
+        self.logs.clear();
+        self.touched.clear();
+    }
+    fn end_transaction(&mut self) -> Result<(), Box<dyn Error>> {
+        self.transient.clear();
+        for address in std::mem::take(&mut self.touched) {
+            if self.account_info(address)? == AccountInfo::default() {
+                self.delete_account(address)?;
+            }
+        }
+        self.touched.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::TempPath;
+    use crate::tests::{addr, COUNTER_INIT};
+    use std::fs::{self, OpenOptions};
+
+    const STACK_SIZE: usize = 1024;
+
+    fn deploy(db: &mut DiskEthereum) -> Address {
+        db.transact(|db| {
+            db.create(
+                STACK_SIZE,
+                &mut GasTracker::new(1_000_000),
+                CallInfo {
+                    origin: addr(1),
+                    caller: addr(1),
+                    value: U256::from(10),
+                    data: COUNTER_INIT.to_vec(),
+                    is_static: false,
+                },
+                None,
+            )
+        })
+        .unwrap()
+    }
+
+    fn increment(db: &mut DiskEthereum, contract: Address) -> Result<U256, ExecError> {
+        db.transact(|db| {
+            let res = db.call(
+                STACK_SIZE,
+                &mut GasTracker::new(1_000_000),
+                contract,
+                CallInfo {
+                    origin: addr(1),
+                    caller: addr(1),
+                    value: U256::ZERO,
+                    data: vec![],
+                    is_static: false,
+                },
+            )?;
+            match res {
+                ExecutionResult::Returned(data) => Ok(U256::from_be_slice(&data)),
+                ExecutionResult::Halted => Ok(U256::ZERO),
+            }
+        })
+    }
+
+    #[test]
+    fn test_disk_persistence() {
+        let path = TempPath::new("disk");
+        let mut db = DiskEthereum::open(&path.0).unwrap();
+        db.fund(addr(1), U256::from(1000)).unwrap();
+        db.commit().unwrap();
+        let contract = deploy(&mut db);
+        assert_eq!(increment(&mut db, contract).unwrap(), U256::ONE);
+        assert_eq!(increment(&mut db, contract).unwrap(), U256::from(2));
+        drop(db);
+
+        // Reopening the file resumes from the last commit.
+        let mut db = DiskEthereum::open(&path.0).unwrap();
+        assert_eq!(db.balance(addr(1)).unwrap(), U256::from(990));
+        assert_eq!(db.nonce(addr(1)).unwrap(), U256::ONE);
+        assert_eq!(db.balance(contract).unwrap(), U256::from(10));
+        assert_eq!(db.code(contract).unwrap(), COUNTER_INIT[12..]);
+        assert_eq!(db.sload(contract, U256::ZERO).unwrap(), U256::from(2));
+        assert_eq!(increment(&mut db, contract).unwrap(), U256::from(3));
+
+        // The same code deployed twice is stored once.
+        let other = deploy(&mut db);
+        assert_ne!(other, contract);
+        assert_eq!(db.code(other).unwrap(), db.code(contract).unwrap());
+        let code_entries = db.store.keys().filter(|key| key[0] == CODE_PREFIX).count();
+        assert_eq!(code_entries, 1);
+
+        let size = fs::metadata(&path.0).unwrap().len();
+        db.compact().unwrap();
+        assert!(fs::metadata(&path.0).unwrap().len() < size);
+        assert_eq!(db.sload(contract, U256::ZERO).unwrap(), U256::from(3));
+    }
+
+    #[test]
+    fn test_disk_rollback() {
+        let path = TempPath::new("disk");
+        let mut db = DiskEthereum::open(&path.0).unwrap();
+        db.fund(addr(1), U256::from(1000)).unwrap();
+        db.commit().unwrap();
+        let contract = deploy(&mut db);
+
+        db.sstore(contract, U256::ZERO, U256::from(41)).unwrap();
+        db.fund(addr(2), U256::from(5)).unwrap();
+        assert!(db.is_dirty());
+        db.rollback();
+        assert!(!db.is_dirty());
+        assert_eq!(db.sload(contract, U256::ZERO).unwrap(), U256::ZERO);
+        assert_eq!(db.balance(addr(2)).unwrap(), U256::ZERO);
+
+        // A failed transaction leaves no trace.
+        let res = db.transact(|db| {
+            db.sstore(contract, U256::ZERO, U256::from(7))?;
+            db.call(
+                STACK_SIZE,
+                &mut GasTracker::new(1_000_000),
+                addr(3),
+                CallInfo {
+                    origin: addr(2),
+                    caller: addr(2),
+                    value: U256::ONE,
+                    data: vec![],
+                    is_static: false,
+                },
+            )
+        });
+        assert_eq!(
+            res,
+            Err(ExecError::Revert(RevertError::InsufficientBalance))
+        );
+        assert_eq!(db.sload(contract, U256::ZERO).unwrap(), U256::ZERO);
+        assert!(!db.is_dirty());
+    }
+
+    #[test]
+    fn test_disk_checkpoint() {
+        let path = TempPath::new("disk");
+        let mut db = DiskEthereum::open(&path.0).unwrap();
+        db.fund(addr(1), U256::from(1000)).unwrap();
+        db.commit().unwrap();
+        db.fund(addr(1), U256::from(5)).unwrap();
+
+        let outer = db.checkpoint();
+        db.fund(addr(1), U256::from(7)).unwrap();
+        db.sstore(addr(2), U256::ZERO, U256::ONE).unwrap();
+        let inner = db.checkpoint();
+        db.set_nonce(addr(3), U256::ONE).unwrap();
+        db.tstore(U256::ONE, U256::ONE).unwrap();
+        // The writes of a discarded checkpoint still roll back with the outer one.
+        db.discard_checkpoint(inner);
+        db.revert_checkpoint(outer);
+
+        assert_eq!(db.balance(addr(1)).unwrap(), U256::from(1005));
+        assert_eq!(db.sload(addr(2), U256::ZERO).unwrap(), U256::ZERO);
+        assert!(db.storage.is_empty());
+        assert_eq!(db.accounts.keys().collect::<Vec<_>>(), [&addr(1)]);
+        assert_eq!(db.tload(U256::ONE).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_disk_interrupted_commit() {
+        let path = TempPath::new("disk");
+        let mut db = DiskEthereum::open(&path.0).unwrap();
+        db.fund(addr(1), U256::from(1000)).unwrap();
+        db.commit().unwrap();
+        let committed = fs::metadata(&path.0).unwrap().len();
+        let contract = deploy(&mut db);
+        drop(db);
+
+        // The process died halfway through writing the deployment.
+        let full = fs::metadata(&path.0).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path.0)
+            .unwrap()
+            .set_len((committed + full) / 2)
+            .unwrap();
+        let db = DiskEthereum::open(&path.0).unwrap();
+        assert_eq!(db.balance(addr(1)).unwrap(), U256::from(1000));
+        assert_eq!(db.nonce(addr(1)).unwrap(), U256::ZERO);
+        assert_eq!(db.balance(contract).unwrap(), U256::ZERO);
+        assert!(db.code(contract).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_disk_matches_memory() {
+        let path = TempPath::new("disk");
+        let mut mem = crate::context::MiniEthereum::new();
+        mem.fund(addr(1), U256::from(1000));
+        let mut db = DiskEthereum::open(&path.0).unwrap();
+        db.insert_account(addr(1), &mem.accounts[&addr(1)]).unwrap();
+        db.commit().unwrap();
+
+        let contract = deploy(&mut db);
+        let info = CallInfo {
+            origin: addr(1),
+            caller: addr(1),
+            value: U256::from(10),
+            data: COUNTER_INIT.to_vec(),
+            is_static: false,
+        };
+        let mem_contract = mem
+            .create(STACK_SIZE, &mut GasTracker::new(1_000_000), info, None)
+            .unwrap();
+        assert_eq!(contract, mem_contract);
+        for address in [addr(1), contract] {
+            let account = &mem.accounts[&address];
+            assert_eq!(db.balance(address).unwrap(), account.value);
+            assert_eq!(db.nonce(address).unwrap(), account.nonce);
+            assert_eq!(db.code(address).unwrap(), account.code);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use alloy_primitives::primitives::Address;
+
+use crate::{keccak::keccak, u256::U256};
+
+/// Hash of a struct, `keccak(typeHash || encodeData)`. `type_signature` is the full
+/// `encodeType` string, referenced struct types included, and `fields` are the already
+/// encoded 32-byte members in declaration order.
+pub fn hash_struct(type_signature: &str, fields: &[[u8; 32]]) -> [u8; 32] {
+    let mut data = keccak(type_signature.as_bytes()).to_vec();
+    for field in fields {
+        data.extend(field);
+    }
+    keccak(&data)
+}
+
+/// Encodes an address member as a 32-byte word.
+pub fn encode_address(address: Address) -> [u8; 32] {
+    address.into_word().0
+}
+
+/// Encodes an integer member as a 32-byte word.
+pub fn encode_uint(value: U256) -> [u8; 32] {
+    value.to_be_bytes()
+}
+
+/// `string` and `bytes` members are encoded as the hash of their contents.
+pub fn encode_bytes(value: &[u8]) -> [u8; 32] {
+    keccak(value)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Eip712Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<U256>,
+    pub verifying_contract: Option<Address>,
+    pub salt: Option<U256>,
+}
+
+impl Eip712Domain {
+    pub fn new(name: &str, version: &str, chain_id: U256, verifying_contract: Address) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            version: Some(version.to_string()),
+            chain_id: Some(chain_id),
+            verifying_contract: Some(verifying_contract),
+            salt: None,
+        }
+    }
+
+    /// Only the fields that are set take part in the `EIP712Domain` type.
+    pub fn separator(&self) -> [u8; 32] {
+        let mut members = Vec::new();
+        let mut fields = Vec::new();
+        if let Some(name) = &self.name {
+            members.push("string name");
+            fields.push(encode_bytes(name.as_bytes()));
+        }
+        if let Some(version) = &self.version {
+            members.push("string version");
+            fields.push(encode_bytes(version.as_bytes()));
+        }
+        if let Some(chain_id) = self.chain_id {
+            members.push("uint256 chainId");
+            fields.push(encode_uint(chain_id));
+        }
+        if let Some(verifying_contract) = self.verifying_contract {
+            members.push("address verifyingContract");
+            fields.push(encode_address(verifying_contract));
+        }
+        if let Some(salt) = self.salt {
+            members.push("bytes32 salt");
+            fields.push(salt.to_be_bytes());
+        }
+        hash_struct(&format!("EIP712Domain({})", members.join(",")), &fields)
+    }
+
+    /// Digest signed for a struct under this domain, `keccak(0x1901 || separator || hash)`.
+    pub fn signing_hash(&self, struct_hash: &[u8; 32]) -> [u8; 32] {
+        let mut data = vec![0x19, 0x01];
+        data.extend(self.separator());
+        data.extend(struct_hash);
+        keccak(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::primitives::{address, hex};
+
+    #[test]
+    fn test_eip712_mail_example() {
+        let domain = Eip712Domain::new(
+            "Ether Mail",
+            "1",
+            U256::from(1),
+            address!("CcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"),
+        );
+        assert_eq!(
+            domain.separator(),
+            hex!("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f")
+        );
+        let person_type = "Person(string name,address wallet)";
+        let from = hash_struct(
+            person_type,
+            &[
+                encode_bytes(b"Cow"),
+                encode_address(address!("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826")),
+            ],
+        );
+        let to = hash_struct(
+            person_type,
+            &[
+                encode_bytes(b"Bob"),
+                encode_address(address!("bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB")),
+            ],
+        );
+        let mail = hash_struct(
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)",
+            &[from, to, encode_bytes(b"Hello, Bob!")],
+        );
+        assert_eq!(
+            mail,
+            hex!("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e")
+        );
+        assert_eq!(
+            domain.signing_hash(&mail),
+            hex!("be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2")
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use alloy_primitives::primitives::Address;
+
+use crate::{context::BLOCK_HASH_WINDOW, machine::Word, u256::U256};
+
+pub const MIN_BASE_FEE_PER_BLOB_GAS: u64 = 1;
+
+/// Approximates `factor * e ** (numerator / denominator)` using Taylor expansion, as
+/// specified in EIP-4844. Saturates at `U256::MAX`.
+pub fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut output = U256::ZERO;
+    let mut accum = factor.saturating_mul(denominator);
+    let mut i = U256::ONE;
+    while accum > U256::ZERO && output < U256::MAX {
+        output = output.saturating_add(accum);
+        accum = accum.saturating_mul(numerator) / (denominator * i);
+        i += U256::ONE;
+    }
+    output / denominator
+}
+
+/// Block-level values exposed to contracts through `Context::info`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockEnv {
+    pub number: U256,
+    pub coinbase: Address,
+    pub timestamp: U256,
+    pub gas_limit: U256,
+    pub base_fee: U256,
+    pub prev_randao: U256,
+    pub excess_blob_gas: U256,
+    pub chain_id: U256,
+}
+
+impl BlockEnv {
+    /// Price of a unit of blob gas, derived from the excess blob gas of the block.
+    pub fn blob_base_fee(&self, update_fraction: u64) -> U256 {
+        fake_exponential(
+            U256::from(MIN_BASE_FEE_PER_BLOB_GAS),
+            self.excess_blob_gas,
+            U256::from(update_fraction),
+        )
+    }
+    /// Whether BLOCKHASH can see block `number` from this block, one of the previous
+    /// `BLOCK_HASH_WINDOW` blocks.
+    pub fn has_block_hash(&self, number: U256) -> bool {
+        number < self.number && self.number - number <= U256::from(BLOCK_HASH_WINDOW)
+    }
+    pub fn with_number(mut self, number: U256) -> Self {
+        self.number = number;
+        self
+    }
+    pub fn with_coinbase(mut self, coinbase: Address) -> Self {
+        self.coinbase = coinbase;
+        self
+    }
+    pub fn with_timestamp(mut self, timestamp: U256) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+    pub fn with_gas_limit(mut self, gas_limit: U256) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+    pub fn with_base_fee(mut self, base_fee: U256) -> Self {
+        self.base_fee = base_fee;
+        self
+    }
+    pub fn with_prev_randao(mut self, prev_randao: U256) -> Self {
+        self.prev_randao = prev_randao;
+        self
+    }
+    pub fn with_excess_blob_gas(mut self, excess_blob_gas: U256) -> Self {
+        self.excess_blob_gas = excess_blob_gas;
+        self
+    }
+    pub fn with_chain_id(mut self, chain_id: U256) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+}
+
+/// Transaction-level values exposed to contracts through `Context::info`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxEnv {
+    pub gas_price: U256,
+    pub blob_versioned_hashes: Vec<U256>,
+}
+
+impl TxEnv {
+    /// Versioned hash of blob `index`, zero if the transaction has no such blob.
+    pub fn blob_hash(&self, index: U256) -> U256 {
+        index
+            .to_usize()
+            .ok()
+            .and_then(|i| self.blob_versioned_hashes.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+    pub fn with_gas_price(mut self, gas_price: U256) -> Self {
+        self.gas_price = gas_price;
+        self
+    }
+    pub fn with_blob_versioned_hashes(mut self, blob_versioned_hashes: Vec<U256>) -> Self {
+        self.blob_versioned_hashes = blob_versioned_hashes;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_exponential() {
+        for (factor, numerator, denominator, expected) in [
+            (1u64, 0u64, 1u64, 1u64),
+            (1, 2, 1, 6),
+            (1, 1, 2, 1),
+            (2, 5, 2, 23),
+            (1, 0, 3338477, 1),
+            (1, 10000000, 3338477, 19),
+            (1, 10000000, 5007716, 7),
+            (1, 100000000, 3338477, 10203769476395),
+        ] {
+            assert_eq!(
+                fake_exponential(
+                    U256::from(factor),
+                    U256::from(numerator),
+                    U256::from(denominator)
+                ),
+                U256::from(expected)
+            );
+        }
+        assert_eq!(fake_exponential(U256::ONE, U256::MAX, U256::ONE), U256::MAX);
+    }
+}
@@ -1,9 +1,14 @@
 use std::error::Error;
 
+use alloy_primitives::primitives::Address;
+
+use crate::u256::U256;
+
 #[derive(Debug)]
 pub enum ExecError {
     Revert(RevertError),
     Context(Box<dyn Error>),
+    InvalidTransaction(InvalidTransaction),
 }
 
 impl From<anyhow::Error> for ExecError {
@@ -17,12 +22,11 @@ unsafe impl Send for ExecError {}
 
 impl PartialEq for ExecError {
     fn eq(&self, other: &Self) -> bool {
-        if let ExecError::Revert(a) = self {
-            if let ExecError::Revert(b) = other {
-                return a == b;
-            }
+        match (self, other) {
+            (ExecError::Revert(a), ExecError::Revert(b)) => a == b,
+            (ExecError::InvalidTransaction(a), ExecError::InvalidTransaction(b)) => a == b,
+            _ => false,
         }
-        false
     }
 }
 
@@ -43,6 +47,90 @@ pub enum RevertError {
     InsufficientGas,
     StackFull,
     CannotMutateStatic,
+    InvalidPrecompileInput,
+}
+
+/// Reasons a transaction is rejected before any of its code is executed.
+#[derive(Debug, PartialEq, Clone)]
+pub enum InvalidTransaction {
+    NonceMismatch { expected: U256, got: U256 },
+    InsufficientFunds,
+    IntrinsicGasTooLow,
+    GasPriceBelowBaseFee,
+    PriorityFeeAboveMaxFee,
+    InitCodeTooLarge,
+    ChainIdMismatch,
+    TypeNotSupported(u8),
+    EmptyBlobList,
+    TooManyBlobs,
+    InvalidBlobVersionedHash,
+    BlobGasPriceBelowBaseFee,
+    EmptyAuthorizationList,
+    InvalidEncoding(RlpError),
+    InvalidSignature,
+    BlockGasLimitExceeded,
+    BlobGasLimitExceeded,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RlpError {
+    UnexpectedEof,
+    TrailingBytes,
+    NonCanonicalSingleByte,
+    NonCanonicalSize,
+    LeadingZero,
+    Overflow,
+    InvalidLength,
+    UnexpectedList,
+    UnexpectedString,
+    Custom(&'static str),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ProofError {
+    InvalidNode(RlpError),
+    HashMismatch,
+    MissingNode,
+    TrailingNodes,
+    ValueMismatch,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum GenesisError {
+    InvalidJson(String),
+    InvalidAddress(String),
+    /// A field of an account could not be parsed, e.g. `("0x01..", "balance")`.
+    InvalidField(String, &'static str),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RpcError {
+    /// Only plain `http://` endpoints are supported.
+    UnsupportedUrl(String),
+    Io(String),
+    /// The endpoint answered with a non-200 status.
+    Http(u16),
+    InvalidResponse(String),
+    /// Error object of a JSON-RPC response.
+    Rpc {
+        code: i64,
+        message: String,
+    },
+}
+
+/// State missing from the witness a transaction is replayed against.
+#[derive(Debug, PartialEq, Clone)]
+pub enum WitnessError {
+    MissingAccount(Address),
+    MissingSlot(Address, U256),
+    MissingBlockHash(U256),
+    InvalidJson(String),
+}
+
+impl From<RlpError> for ProofError {
+    fn from(value: RlpError) -> Self {
+        Self::InvalidNode(value)
+    }
 }
 
 impl From<Box<dyn Error>> for ExecError {
@@ -51,6 +139,12 @@ impl From<Box<dyn Error>> for ExecError {
     }
 }
 
+impl From<InvalidTransaction> for ExecError {
+    fn from(value: InvalidTransaction) -> Self {
+        Self::InvalidTransaction(value)
+    }
+}
+
 impl From<RevertError> for ExecError {
     fn from(value: RevertError) -> Self {
         Self::Revert(value)
@@ -69,29 +163,84 @@ impl std::fmt::Display for RevertError {
     }
 }
 
+impl std::fmt::Display for InvalidTransaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 impl Error for RevertError {}
 
+impl Error for InvalidTransaction {}
+
+impl std::fmt::Display for RlpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for RlpError {}
+
+impl std::fmt::Display for ProofError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for ProofError {}
+
+impl std::fmt::Display for GenesisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for GenesisError {}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for RpcError {}
+
+impl std::fmt::Display for WitnessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for WitnessError {}
+
+impl From<std::io::Error> for RpcError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value.to_string())
+    }
+}
+
 impl Error for ExecError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             ExecError::Context(parent) => Some(parent.as_ref()),
             ExecError::Revert(rev) => Some(rev),
+            ExecError::InvalidTransaction(inv) => Some(inv),
         }
     }
 }
 
-impl Into<Vec<u8>> for RevertError {
-    fn into(self) -> Vec<u8> {
-        match self {
+impl From<RevertError> for Vec<u8> {
+    fn from(value: RevertError) -> Self {
+        match value {
             RevertError::Revert(data) => data,
             _ => vec![],
         }
     }
 }
 
-impl Into<Vec<u8>> for &RevertError {
-    fn into(self) -> Vec<u8> {
-        match self {
+impl From<&RevertError> for Vec<u8> {
+    fn from(value: &RevertError) -> Self {
+        match value {
             RevertError::Revert(data) => data.clone(),
             _ => vec![],
         }
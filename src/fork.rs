@@ -0,0 +1,548 @@
+use std::{cell::RefCell, collections::HashMap, error::Error, io, path::Path, str::FromStr};
+
+use alloy_primitives::primitives::{hex, Address};
+use serde_json::{json, Value};
+
+use crate::{
+    context::Spec,
+    env::BlockEnv,
+    error::RpcError,
+    overlay::{Backend, Overlay},
+    rpc::RpcClient,
+    store::{Batch, Store},
+    transaction::GAS_PER_BLOB,
+    u256::U256,
+};
+
+const BALANCE: u8 = b'b';
+const NONCE: u8 = b'n';
+const CODE: u8 = b'c';
+const STORAGE: u8 = b's';
+const BLOCK_HASH: u8 = b'h';
+const HEADER: u8 = b'H';
+
+/// Seconds assumed between the pinned block and the next one.
+const BLOCK_TIME: u64 = 12;
+/// Ratio of the gas limit to the gas target of a block (EIP-1559).
+const ELASTICITY_MULTIPLIER: u64 = 2;
+/// Bounds the change of the base fee from one block to the next (EIP-1559).
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+
+fn parse_word(value: &Value) -> Option<Vec<u8>> {
+    let word = U256::from_str(value.as_str()?).ok()?;
+    Some(word.to_be_bytes::<32>().to_vec())
+}
+
+fn parse_code(value: &Value) -> Option<Vec<u8>> {
+    hex::decode(value.as_str()?).ok()
+}
+
+fn parse_block_hash(value: &Value) -> Option<Vec<u8>> {
+    parse_word(value.get("hash")?)
+}
+
+fn parse_chain_id(value: &Value) -> Option<u64> {
+    u64::try_from(U256::from_str(value.as_str()?).ok()?).ok()
+}
+
+/// The header is cached as the JSON object returned by the node.
+fn parse_header(value: &Value) -> Option<Vec<u8>> {
+    value.is_object().then(|| value.to_string().into_bytes())
+}
+
+/// Base fee of the block after one with `base_fee` that used `gas_used` of `gas_limit`.
+fn next_base_fee(base_fee: U256, gas_used: U256, gas_limit: U256) -> U256 {
+    let target = gas_limit / U256::from(ELASTICITY_MULTIPLIER);
+    let denominator = U256::from(BASE_FEE_MAX_CHANGE_DENOMINATOR);
+    if target.is_zero() || gas_used == target {
+        base_fee
+    } else if gas_used > target {
+        let delta = base_fee * (gas_used - target) / target / denominator;
+        base_fee + delta.max(U256::ONE)
+    } else {
+        base_fee - base_fee * (target - gas_used) / target / denominator
+    }
+}
+
+/// State of a chain at a pinned block, fetched over JSON-RPC on first access.
+///
+/// Fetched values are kept in memory and, if a cache file is set, on disk, keyed by the
+/// chain id and the pinned block so that one file can serve several forks. They are
+/// written to the cache file in one batch by `flush`, or when the state is dropped.
+pub struct RemoteState {
+    client: RpcClient,
+    chain_id: u64,
+    block_number: u64,
+    memory: RefCell<HashMap<Vec<u8>, Vec<u8>>>,
+    store: Option<RefCell<Store>>,
+    /// Fetched values not written to the cache file yet.
+    pending: RefCell<Batch>,
+}
+
+impl RemoteState {
+    /// State of chain `chain_id` at `block_number`. The chain id is trusted, it is what
+    /// keeps the cached values of different chains apart.
+    pub fn new(client: RpcClient, chain_id: u64, block_number: u64) -> Self {
+        Self {
+            client,
+            chain_id,
+            block_number,
+            memory: RefCell::new(HashMap::new()),
+            store: None,
+            pending: RefCell::new(Batch::new()),
+        }
+    }
+
+    /// State at `block_number` of the chain `client` is connected to, asking it for its
+    /// chain id.
+    pub fn connect(client: RpcClient, block_number: u64) -> Result<Self, RpcError> {
+        let result = client.request("eth_chainId", json!([]))?;
+        let chain_id = parse_chain_id(&result)
+            .ok_or_else(|| RpcError::InvalidResponse("unexpected eth_chainId result".into()))?;
+        Ok(Self::new(client, chain_id, block_number))
+    }
+
+    /// Persists fetched values in the store at `path`, reusing the ones already there.
+    pub fn with_cache(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.store = Some(RefCell::new(Store::open(path)?));
+        Ok(self)
+    }
+
+    /// Writes the values fetched since the last flush to the cache file.
+    pub fn flush(&self) -> io::Result<()> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let pending = self.pending.take();
+        if !pending.is_empty() {
+            store.borrow_mut().write(&pending)?;
+        }
+        Ok(())
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn block_tag(&self) -> String {
+        format!("{:#x}", self.block_number)
+    }
+
+    /// Cached value of `key`, otherwise the result of `method` parsed with `parse`.
+    fn fetch(
+        &self,
+        key: &[u8],
+        method: &str,
+        params: Value,
+        parse: fn(&Value) -> Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let key = [
+            &self.chain_id.to_be_bytes()[..],
+            &self.block_number.to_be_bytes(),
+            key,
+        ]
+        .concat();
+        if let Some(value) = self.memory.borrow().get(&key) {
+            return Ok(value.clone());
+        }
+        let cached = match &self.store {
+            Some(store) => store.borrow().get(&key)?,
+            None => None,
+        };
+        let value = match cached {
+            Some(value) => value,
+            None => {
+                let result = self.client.request(method, params)?;
+                let value = parse(&result).ok_or_else(|| {
+                    RpcError::InvalidResponse(format!("unexpected {} result", method))
+                })?;
+                if self.store.is_some() {
+                    self.pending
+                        .borrow_mut()
+                        .push((key.clone(), Some(value.clone())));
+                }
+                value
+            }
+        };
+        self.memory.borrow_mut().insert(key, value.clone());
+        Ok(value)
+    }
+
+    fn fetch_word(&self, key: &[u8], method: &str, params: Value) -> Result<U256, Box<dyn Error>> {
+        let value = self.fetch(key, method, params, parse_word)?;
+        Ok(U256::from_be_slice(&value))
+    }
+
+    pub fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.fetch_word(
+            &[&[BALANCE][..], address.as_slice()].concat(),
+            "eth_getBalance",
+            json!([hex::encode_prefixed(address), self.block_tag()]),
+        )
+    }
+
+    pub fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.fetch_word(
+            &[&[NONCE][..], address.as_slice()].concat(),
+            "eth_getTransactionCount",
+            json!([hex::encode_prefixed(address), self.block_tag()]),
+        )
+    }
+
+    pub fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.fetch(
+            &[&[CODE][..], address.as_slice()].concat(),
+            "eth_getCode",
+            json!([hex::encode_prefixed(address), self.block_tag()]),
+            parse_code,
+        )
+    }
+
+    pub fn storage(&self, address: Address, slot: U256) -> Result<U256, Box<dyn Error>> {
+        self.fetch_word(
+            &[
+                &[STORAGE][..],
+                address.as_slice(),
+                &slot.to_be_bytes::<32>(),
+            ]
+            .concat(),
+            "eth_getStorageAt",
+            json!([
+                hex::encode_prefixed(address),
+                format!("{:#x}", slot),
+                self.block_tag()
+            ]),
+        )
+    }
+
+    /// Environment of the block following the pinned one, derived from the pinned header:
+    /// its base fee and excess blob gas follow from the gas the pinned block used, the
+    /// timestamp is `BLOCK_TIME` later and the other fields are carried over.
+    pub fn next_block_env(&self, spec: Spec) -> Result<BlockEnv, Box<dyn Error>> {
+        let header = self.fetch(
+            &[HEADER],
+            "eth_getBlockByNumber",
+            json!([self.block_tag(), false]),
+            parse_header,
+        )?;
+        let header: Value = serde_json::from_slice(&header)?;
+        // Fields of later forks are missing from older headers.
+        let field = |name: &str| -> Result<U256, Box<dyn Error>> {
+            match header.get(name) {
+                None | Some(Value::Null) => Ok(U256::ZERO),
+                Some(value) => value
+                    .as_str()
+                    .and_then(|value| U256::from_str(value).ok())
+                    .ok_or_else(|| {
+                        RpcError::InvalidResponse(format!("invalid {} in block header", name))
+                            .into()
+                    }),
+            }
+        };
+        let coinbase = header
+            .get("miner")
+            .and_then(Value::as_str)
+            .and_then(|miner| Address::from_str(miner).ok())
+            .ok_or_else(|| RpcError::InvalidResponse("invalid miner in block header".into()))?;
+        let gas_limit = field("gasLimit")?;
+        let target_blob_gas = U256::from(spec.target_blobs_per_block() * GAS_PER_BLOB);
+        Ok(BlockEnv::default()
+            .with_number(U256::from(self.block_number + 1))
+            .with_coinbase(coinbase)
+            .with_timestamp(field("timestamp")? + U256::from(BLOCK_TIME))
+            .with_gas_limit(gas_limit)
+            .with_base_fee(next_base_fee(
+                field("baseFeePerGas")?,
+                field("gasUsed")?,
+                gas_limit,
+            ))
+            .with_prev_randao(field("mixHash")?)
+            .with_excess_blob_gas(
+                (field("excessBlobGas")? + field("blobGasUsed")?).saturating_sub(target_blob_gas),
+            )
+            .with_chain_id(U256::from(self.chain_id)))
+    }
+
+    /// Hash of a block up to the pinned one, zero for the later blocks.
+    pub fn block_hash(&self, number: u64) -> Result<U256, Box<dyn Error>> {
+        if number > self.block_number {
+            return Ok(U256::ZERO);
+        }
+        let value = self.fetch(
+            &[&[BLOCK_HASH][..], &number.to_be_bytes()].concat(),
+            "eth_getBlockByNumber",
+            json!([format!("{:#x}", number), false]),
+            parse_block_hash,
+        )?;
+        Ok(U256::from_be_slice(&value))
+    }
+}
+
+impl Drop for RemoteState {
+    /// Flushes the cache, the values fetched are lost if that fails.
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl Backend for RemoteState {
+    fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.balance(address)
+    }
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.nonce(address)
+    }
+    fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.code(address)
+    }
+    fn storage(&self, address: Address, slot: U256) -> Result<U256, Box<dyn Error>> {
+        self.storage(address, slot)
+    }
+    fn block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>> {
+        match u64::try_from(number) {
+            Ok(number) => self.block_hash(number),
+            Err(_) => Ok(U256::ZERO),
+        }
+    }
+}
+
+/// A context forked from a remote chain: reads go to the `RemoteState` of the pinned block
+/// unless they hit a local write, writes are kept locally.
+pub type ForkEthereum = Overlay<RemoteState>;
+
+impl ForkEthereum {
+    /// Forks the chain served at `url` at `block_number`.
+    pub fn fork(url: &str, block_number: u64) -> Result<Self, Box<dyn Error>> {
+        Self::from_remote(RemoteState::connect(RpcClient::new(url)?, block_number)?)
+    }
+
+    /// A fork of `remote`, the block environment set to the block following the pinned
+    /// one, see `RemoteState::next_block_env`.
+    pub fn from_remote(remote: RemoteState) -> Result<Self, Box<dyn Error>> {
+        let fork = Overlay::new(remote);
+        let block = fork.base().next_block_env(fork.spec)?;
+        Ok(fork.with_block_env(block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::{Context, ContextMut},
+        error::ExecError,
+        machine::{CallInfo, GasTracker},
+        opcodes::ExecutionResult,
+        rpc::tests::serve,
+        store::tests::TempPath,
+        tests::{addr, COUNTER},
+    };
+    use std::sync::atomic::Ordering;
+
+    const STACK_SIZE: usize = 1024;
+
+    const CHAIN_ID: u64 = 0x7a69;
+
+    /// Header of blocks 0x10 and 0x20: full, 1 gwei base fee and 4 blobs over a 6 blob
+    /// target.
+    fn header(number: &str) -> Value {
+        json!({
+            "number": number,
+            "hash": format!("0x{}", "cd".repeat(32)),
+            "miner": hex::encode_prefixed(addr(0xcb)),
+            "timestamp": "0x64",
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0x1c9c380",
+            "baseFeePerGas": "0x3b9aca00",
+            "mixHash": format!("0x{}", "ee".repeat(32)),
+            "excessBlobGas": "0xc0000",
+            "blobGasUsed": "0x80000",
+        })
+    }
+
+    /// Serves chain 0x7a69 where, at block 0x10, 0x01 holds 1000 wei with nonce 3 and 0x42 is a
+    /// counter at 5. Any other block is unknown.
+    fn mock_chain() -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        serve(Box::new(|method, params| {
+            if method == "eth_chainId" {
+                return Ok(json!(format!("{:#x}", CHAIN_ID)));
+            }
+            let params = params.as_array().unwrap();
+            let address = |i: usize| Address::from_str(params[i].as_str().unwrap()).unwrap();
+            if method == "eth_getBlockByNumber" {
+                return Ok(match params[0].as_str().unwrap() {
+                    "0xf" => json!({ "number": "0xf", "hash": format!("0x{}", "ab".repeat(32)) }),
+                    number @ ("0x10" | "0x20") => header(number),
+                    _ => Value::Null,
+                });
+            }
+            if params.last().unwrap() != "0x10" {
+                return Err((-32000, "header not found".to_string()));
+            }
+            let result = match method {
+                "eth_getBalance" if address(0) == addr(1) => "0x3e8".to_string(),
+                "eth_getBalance" => "0x0".to_string(),
+                "eth_getTransactionCount" if address(0) == addr(1) => "0x3".to_string(),
+                "eth_getTransactionCount" => "0x0".to_string(),
+                "eth_getCode" if address(0) == addr(0x42) => hex::encode_prefixed(COUNTER),
+                "eth_getCode" => "0x".to_string(),
+                "eth_getStorageAt" if address(0) == addr(0x42) && params[1] == "0x0" => {
+                    format!("0x{:064x}", 5)
+                }
+                "eth_getStorageAt" => format!("0x{:064x}", 0),
+                _ => return Err((-32601, "method not found".to_string())),
+            };
+            Ok(json!(result))
+        }))
+    }
+
+    fn increment(ctx: &mut ForkEthereum) -> Result<U256, ExecError> {
+        let res = ctx.call(
+            STACK_SIZE,
+            &mut GasTracker::new(1_000_000),
+            addr(0x42),
+            CallInfo {
+                origin: addr(1),
+                caller: addr(1),
+                value: U256::from(10),
+                data: vec![],
+                is_static: false,
+            },
+        )?;
+        match res {
+            ExecutionResult::Returned(data) => Ok(U256::from_be_slice(&data)),
+            ExecutionResult::Halted => Ok(U256::ZERO),
+        }
+    }
+
+    #[test]
+    fn test_fork_lazy_fetch() {
+        let (url, requests) = mock_chain();
+        let mut ctx = ForkEthereum::fork(&url, 0x10).unwrap();
+        // Only the chain id and the pinned header are fetched upfront.
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(ctx.base().chain_id(), CHAIN_ID);
+        assert_eq!(ctx.balance(addr(1)).unwrap(), U256::from(1000));
+        assert_eq!(ctx.nonce(addr(1)).unwrap(), U256::from(3));
+        assert_eq!(ctx.code(addr(0x42)).unwrap(), COUNTER);
+        assert_eq!(ctx.sload(addr(0x42), U256::ZERO).unwrap(), U256::from(5));
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+
+        // Writes stay local and cached values are not fetched again.
+        assert_eq!(increment(&mut ctx).unwrap(), U256::from(6));
+        assert_eq!(increment(&mut ctx).unwrap(), U256::from(7));
+        assert_eq!(ctx.balance(addr(1)).unwrap(), U256::from(980));
+        assert_eq!(ctx.balance(addr(0x42)).unwrap(), U256::from(20));
+        assert_eq!(requests.load(Ordering::SeqCst), 7);
+        assert_eq!(
+            ctx.base().storage(addr(0x42), U256::ZERO).unwrap(),
+            U256::from(5)
+        );
+
+        ctx.discard();
+        assert_eq!(ctx.sload(addr(0x42), U256::ZERO).unwrap(), U256::from(5));
+        assert_eq!(requests.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    fn test_fork_block_env() {
+        let (url, _) = mock_chain();
+        let ctx = ForkEthereum::fork(&url, 0x10).unwrap();
+        assert_eq!(
+            ctx.block,
+            BlockEnv::default()
+                .with_number(U256::from(0x11))
+                .with_coinbase(addr(0xcb))
+                .with_timestamp(U256::from(112))
+                .with_gas_limit(U256::from(30_000_000))
+                // A full block raises the base fee by 1/8.
+                .with_base_fee(U256::from(1_125_000_000))
+                .with_prev_randao(U256::from_be_bytes([0xee; 32]))
+                .with_excess_blob_gas(U256::from(4 * GAS_PER_BLOB))
+                .with_chain_id(U256::from(CHAIN_ID))
+        );
+        assert_eq!(
+            next_base_fee(U256::from(800), U256::ZERO, U256::from(100)),
+            U256::from(700)
+        );
+        assert_eq!(
+            next_base_fee(U256::from(800), U256::from(50), U256::from(100)),
+            U256::from(800)
+        );
+    }
+
+    #[test]
+    fn test_fork_block_hash() {
+        let (url, _) = mock_chain();
+        let ctx = ForkEthereum::fork(&url, 0x10).unwrap();
+        assert_eq!(ctx.block.number, U256::from(0x11));
+        assert_eq!(
+            ctx.block_hash(U256::from(0xf)).unwrap(),
+            U256::from_be_bytes([0xab; 32])
+        );
+        assert!(ctx.block_hash(U256::from(0xe)).is_err());
+        assert_eq!(ctx.block_hash(U256::from(0x11)).unwrap(), U256::ZERO);
+        let ctx = ctx.with_block_env(BlockEnv::default().with_number(U256::from(0x12)));
+        // Blocks after the pinned one are not on the remote chain.
+        assert_eq!(ctx.block_hash(U256::from(0x11)).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_fork_rpc_error() {
+        let (url, _) = mock_chain();
+        let mut ctx = ForkEthereum::fork(&url, 0x20).unwrap();
+        let err = increment(&mut ctx).unwrap_err();
+        let ExecError::Context(err) = err else {
+            panic!("unexpected error {:?}", err);
+        };
+        assert_eq!(
+            err.downcast_ref::<RpcError>(),
+            Some(&RpcError::Rpc {
+                code: -32000,
+                message: "header not found".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_fork_disk_cache() {
+        let (url, requests) = mock_chain();
+        let path = TempPath::new("fork");
+        let remote = RemoteState::connect(RpcClient::new(&url).unwrap(), 0x10).unwrap();
+        let mut ctx = ForkEthereum::from_remote(remote.with_cache(&path.0).unwrap()).unwrap();
+        assert_eq!(increment(&mut ctx).unwrap(), U256::from(6));
+        // Every request but the one for the chain id is cached.
+        let fetched = requests.load(Ordering::SeqCst);
+        // Fetched values are only written when flushed.
+        assert_eq!(Store::open(&path.0).unwrap().keys().count(), 0);
+        ctx.base().flush().unwrap();
+        assert_eq!(Store::open(&path.0).unwrap().keys().count(), fetched - 1);
+        ctx.balance(addr(3)).unwrap();
+        drop(ctx);
+
+        // Nothing listens on port 1: everything must come from the cache.
+        let offline = || RpcClient::new("http://127.0.0.1:1").unwrap();
+        let remote = RemoteState::new(offline(), CHAIN_ID, 0x10);
+        let mut ctx = ForkEthereum::from_remote(remote.with_cache(&path.0).unwrap()).unwrap();
+        assert_eq!(increment(&mut ctx).unwrap(), U256::from(6));
+        // Written when the first fork was dropped.
+        assert_eq!(ctx.balance(addr(3)).unwrap(), U256::ZERO);
+        assert!(ctx.balance(addr(2)).is_err());
+        drop(ctx);
+
+        // The same block of another chain is not served from the cache.
+        let remote = RemoteState::new(offline(), 1, 0x10);
+        assert!(ForkEthereum::from_remote(remote.with_cache(&path.0).unwrap()).is_err());
+
+        // Another block of the same cache file is fetched separately.
+        let remote = RemoteState::new(RpcClient::new(&url).unwrap(), CHAIN_ID, 0x20);
+        let mut ctx = ForkEthereum::from_remote(remote.with_cache(&path.0).unwrap()).unwrap();
+        assert!(increment(&mut ctx).is_err());
+        // The balance of 0x03, then the header and the first read of block 0x20.
+        assert_eq!(requests.load(Ordering::SeqCst), fetched + 3);
+    }
+}
@@ -0,0 +1,251 @@
+use std::str::FromStr;
+
+use alloy_primitives::primitives::{hex, Address};
+use serde_json::{Map, Value};
+
+use crate::{
+    context::{Account, MiniEthereum},
+    error::GenesisError,
+    u256::U256,
+};
+
+/// Balances and nonces are hex quantities, decimal strings or plain numbers.
+pub(crate) fn parse_quantity(value: &Value) -> Option<U256> {
+    match value {
+        Value::String(s) => U256::from_str(s).ok(),
+        Value::Number(n) => n.as_u64().map(U256::from),
+        _ => None,
+    }
+}
+
+/// Storage keys and values are always hex, not necessarily padded to 32 bytes.
+pub(crate) fn parse_word(value: &str) -> Option<U256> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    U256::from_str_radix(digits, 16).ok()
+}
+
+pub(crate) fn format_word(value: &U256) -> String {
+    hex::encode_prefixed(value.to_be_bytes::<32>())
+}
+
+/// Parses an account of the `alloc`, returning the name of the faulty field on error.
+fn parse_account(value: &Value) -> Result<Account, &'static str> {
+    let field = |name: &str| value.get(name).filter(|v| !v.is_null());
+    let mut account = Account::default();
+    if let Some(balance) = field("balance") {
+        account.value = parse_quantity(balance).ok_or("balance")?;
+    }
+    if let Some(nonce) = field("nonce") {
+        account.nonce = parse_quantity(nonce).ok_or("nonce")?;
+    }
+    if let Some(code) = field("code") {
+        account.code = code
+            .as_str()
+            .and_then(|code| hex::decode(code).ok())
+            .ok_or("code")?;
+    }
+    if let Some(storage) = field("storage") {
+        for (slot, value) in storage.as_object().ok_or("storage")? {
+            let slot = parse_word(slot).ok_or("storage")?;
+            let value = value.as_str().and_then(parse_word).ok_or("storage")?;
+            if !value.is_zero() {
+                account.storage.insert(slot, value);
+            }
+        }
+    }
+    Ok(account)
+}
+
+impl MiniEthereum {
+    /// Adds the accounts of a geth-style genesis `alloc`, given either as the object itself
+    /// or as a full genesis file. Existing accounts with the same address are replaced.
+    pub fn load_alloc(&mut self, json: &str) -> Result<(), GenesisError> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| GenesisError::InvalidJson(e.to_string()))?;
+        let alloc = value
+            .get("alloc")
+            .unwrap_or(&value)
+            .as_object()
+            .ok_or_else(|| GenesisError::InvalidJson("alloc is not an object".to_string()))?;
+        for (address, account) in alloc {
+            let parsed = Address::from_str(address)
+                .map_err(|_| GenesisError::InvalidAddress(address.clone()))?;
+            let account = parse_account(account)
+                .map_err(|field| GenesisError::InvalidField(address.clone(), field))?;
+            self.replace_account(parsed, account);
+        }
+        Ok(())
+    }
+
+    pub fn with_alloc(mut self, json: &str) -> Result<Self, GenesisError> {
+        self.load_alloc(json)?;
+        Ok(self)
+    }
+
+    /// The current state in the `alloc` format accepted by `load_alloc`. Accounts and slots
+    /// are sorted, empty accounts and zero slots are left out.
+    pub fn dump_alloc(&self) -> Value {
+        let mut accounts: Vec<(&Address, &Account)> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| {
+                !account.is_empty() || account.storage.values().any(|v| !v.is_zero())
+            })
+            .collect();
+        accounts.sort_by_key(|(address, _)| **address);
+
+        let mut alloc = Map::new();
+        for (address, account) in accounts {
+            let mut entry = Map::new();
+            entry.insert("balance".into(), format!("{:#x}", account.value).into());
+            entry.insert("nonce".into(), format!("{:#x}", account.nonce).into());
+            if !account.code.is_empty() {
+                entry.insert("code".into(), hex::encode_prefixed(&account.code).into());
+            }
+            let mut slots: Vec<(&U256, &U256)> = account
+                .storage
+                .iter()
+                .filter(|(_, value)| !value.is_zero())
+                .collect();
+            slots.sort();
+            if !slots.is_empty() {
+                let storage = slots
+                    .into_iter()
+                    .map(|(slot, value)| (format_word(slot), format_word(value).into()))
+                    .collect();
+                entry.insert("storage".into(), Value::Object(storage));
+            }
+            alloc.insert(hex::encode_prefixed(address), Value::Object(entry));
+        }
+        Value::Object(alloc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const GENESIS: &str = r#"{
+        "config": { "chainId": 31337 },
+        "gasLimit": "0x1c9c380",
+        "alloc": {
+            "f39Fd6e51aad88F6F4ce6aB8827279cffFb92266": {
+                "balance": "10000000000000000000000"
+            },
+            "0x0000000000000000000000000000000000000042": {
+                "balance": "0x0",
+                "nonce": 1,
+                "code": "0x602a5f5260205fa000",
+                "storage": {
+                    "0x00": "0x01",
+                    "0x0000000000000000000000000000000000000000000000000000000000000001": "0x2a",
+                    "0x02": "0x00"
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_load_alloc() {
+        let mut ctx = MiniEthereum::new().with_alloc(GENESIS).unwrap();
+        let funded = ctx.accounts
+            [&Address::from_str("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266").unwrap()]
+            .clone();
+        assert_eq!(funded.value, U256::from(10).pow(U256::from(22)));
+        assert_eq!(funded.nonce, U256::ZERO);
+        let contract = &ctx.accounts[&Address::with_last_byte(0x42)];
+        assert_eq!(contract.nonce, U256::ONE);
+        assert_eq!(contract.code, hex!("602a5f5260205fa000"));
+        assert_eq!(
+            contract.storage,
+            [(U256::ZERO, U256::ONE), (U256::ONE, U256::from(0x2a))]
+                .into_iter()
+                .collect()
+        );
+
+        // The dump loads back into the same state.
+        let dump = ctx.dump_alloc();
+        assert_eq!(
+            dump["0x0000000000000000000000000000000000000042"],
+            json!({
+                "balance": "0x0",
+                "nonce": "0x1",
+                "code": "0x602a5f5260205fa000",
+                "storage": {
+                    "0x0000000000000000000000000000000000000000000000000000000000000000":
+                        "0x0000000000000000000000000000000000000000000000000000000000000001",
+                    "0x0000000000000000000000000000000000000000000000000000000000000001":
+                        "0x000000000000000000000000000000000000000000000000000000000000002a"
+                }
+            })
+        );
+        let mut reloaded = MiniEthereum::new().with_alloc(&dump.to_string()).unwrap();
+        assert_eq!(reloaded.accounts, ctx.accounts);
+        assert_eq!(reloaded.state_root(), ctx.state_root());
+    }
+
+    #[test]
+    fn test_dump_alloc() {
+        let mut ctx = MiniEthereum::new().with_alloc(GENESIS).unwrap();
+        ctx.fund(Address::with_last_byte(0x77), U256::from(0x10));
+        ctx.accounts
+            .entry(Address::with_last_byte(0x78))
+            .or_default();
+        ctx.accounts
+            .get_mut(&Address::with_last_byte(0x42))
+            .unwrap()
+            .storage
+            .insert(U256::ZERO, U256::ZERO);
+        let dump = ctx.dump_alloc();
+        let addresses: Vec<&String> = dump.as_object().unwrap().keys().collect();
+        assert_eq!(
+            addresses,
+            [
+                "0x0000000000000000000000000000000000000042",
+                "0x0000000000000000000000000000000000000077",
+                "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+            ]
+        );
+        assert_eq!(
+            dump["0x0000000000000000000000000000000000000077"],
+            json!({ "balance": "0x10", "nonce": "0x0" })
+        );
+        assert_eq!(
+            dump["0x0000000000000000000000000000000000000042"]["storage"]
+                .as_object()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_invalid_alloc() {
+        let mut ctx = MiniEthereum::new();
+        assert!(matches!(
+            ctx.load_alloc("{"),
+            Err(GenesisError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            ctx.load_alloc("[]"),
+            Err(GenesisError::InvalidJson(_))
+        ));
+        assert_eq!(
+            ctx.load_alloc(r#"{"0x42": {"balance": "0x1"}}"#),
+            Err(GenesisError::InvalidAddress("0x42".to_string()))
+        );
+        let address = "0x0000000000000000000000000000000000000042";
+        for (account, field) in [
+            (r#"{"balance": "0xzz"}"#, "balance"),
+            (r#"{"nonce": -1}"#, "nonce"),
+            (r#"{"code": "0x6"}"#, "code"),
+            (r#"{"storage": {"0x1": 1}}"#, "storage"),
+        ] {
+            assert_eq!(
+                ctx.load_alloc(&format!(r#"{{"{}": {}}}"#, address, account)),
+                Err(GenesisError::InvalidField(address.to_string(), field))
+            );
+        }
+    }
+}
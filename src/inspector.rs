@@ -0,0 +1,397 @@
+use std::{cell::RefCell, error::Error};
+
+use alloy_primitives::primitives::Address;
+
+use crate::{
+    context::{call_frame, create_frame, Context, ContextMut, Info, Log, Spec, State},
+    env::TxEnv,
+    error::ExecError,
+    machine::{CallInfo, GasTracker, Machine},
+    opcodes::ExecutionResult,
+    precompiles::Precompile,
+    u256::U256,
+};
+
+/// Observes an execution run through `Inspected`. Every hook does nothing by default.
+pub trait Inspector {
+    /// Called before the opcode at `machine.pc`. Returning a result ends the frame with it,
+    /// like the opcode would.
+    fn step(&mut self, _machine: &mut Machine<U256>) -> Result<Option<ExecutionResult>, ExecError> {
+        Ok(None)
+    }
+    /// Called after `opcode` with its result, which can be replaced.
+    fn step_end(
+        &mut self,
+        _machine: &mut Machine<U256>,
+        _opcode: u8,
+        _result: &mut Result<Option<ExecutionResult>, ExecError>,
+    ) {
+    }
+    /// Called when entering a call to `address`, whose inputs can be changed. Returning a
+    /// result skips the call.
+    fn call(
+        &mut self,
+        _address: Address,
+        _call_info: &mut CallInfo<U256>,
+    ) -> Option<Result<ExecutionResult, ExecError>> {
+        None
+    }
+    /// Called when leaving a call with its result, which can be replaced.
+    fn call_end(
+        &mut self,
+        _address: Address,
+        _call_info: &CallInfo<U256>,
+        _result: &mut Result<ExecutionResult, ExecError>,
+    ) {
+    }
+    /// Called when entering a deployment, whose inputs can be changed. Returning a result
+    /// skips the deployment.
+    fn create(
+        &mut self,
+        _call_info: &mut CallInfo<U256>,
+        _salt: Option<U256>,
+    ) -> Option<Result<Address, ExecError>> {
+        None
+    }
+    /// Called when leaving a deployment with its result, which can be replaced.
+    fn create_end(
+        &mut self,
+        _call_info: &CallInfo<U256>,
+        _result: &mut Result<Address, ExecError>,
+    ) {
+    }
+    /// Called for every log emitted, including the ones later dropped by a revert.
+    fn log(&mut self, _log: &Log) {}
+    /// Called once the context has carried out a SELFDESTRUCT.
+    fn selfdestruct(&mut self, _contract: Address, _target: Address) {}
+}
+
+/// Context reporting the execution on `ctx` to an inspector, nested frames included.
+/// Contexts used without it pay nothing for the hooks.
+pub struct Inspected<'a, C, I> {
+    pub ctx: &'a mut C,
+    inspector: RefCell<&'a mut I>,
+}
+
+impl<'a, C: ContextMut<U256> + State, I: Inspector> Inspected<'a, C, I> {
+    pub fn new(ctx: &'a mut C, inspector: &'a mut I) -> Self {
+        Self {
+            ctx,
+            inspector: RefCell::new(inspector),
+        }
+    }
+}
+
+impl<C: ContextMut<U256> + State, I: Inspector> Context<U256> for Inspected<'_, C, I> {
+    type Mutable = Self;
+    fn destroy(&self, contract: Address, target: Address) -> Result<(), ExecError> {
+        self.ctx.destroy(contract, target)?;
+        self.inspector.borrow_mut().selfdestruct(contract, target);
+        Ok(())
+    }
+    fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.ctx.code(address)
+    }
+    fn blob_hash(&self, index: U256) -> Result<U256, Box<dyn Error>> {
+        self.ctx.blob_hash(index)
+    }
+    fn block_hash(&self, block_number: U256) -> Result<U256, Box<dyn Error>> {
+        self.ctx.block_hash(block_number)
+    }
+    fn info(&self, inf: Info) -> Result<U256, Box<dyn Error>> {
+        self.ctx.info(inf)
+    }
+    fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.ctx.balance(address)
+    }
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.ctx.nonce(address)
+    }
+    fn sload(&self, contract: Address, address: U256) -> Result<U256, Box<dyn Error>> {
+        self.ctx.sload(contract, address)
+    }
+    fn tload(&self, address: U256) -> Result<U256, Box<dyn Error>> {
+        self.ctx.tload(address)
+    }
+    fn as_mut(&mut self) -> &mut Self::Mutable {
+        self
+    }
+    fn step(&mut self, machine: &mut Machine<U256>) -> Result<Option<ExecutionResult>, ExecError> {
+        self.inspector.get_mut().step(machine)
+    }
+    fn step_end(
+        &mut self,
+        machine: &mut Machine<U256>,
+        opcode: u8,
+        result: &mut Result<Option<ExecutionResult>, ExecError>,
+    ) {
+        self.inspector.get_mut().step_end(machine, opcode, result)
+    }
+}
+
+impl<C: ContextMut<U256> + State, I: Inspector> ContextMut<U256> for Inspected<'_, C, I> {
+    fn create(
+        &mut self,
+        stack_size: usize,
+        gas_tracker: &mut GasTracker,
+        mut call_info: CallInfo<U256>,
+        salt: Option<U256>,
+    ) -> Result<Address, ExecError> {
+        if let Some(result) = self.inspector.get_mut().create(&mut call_info, salt) {
+            return result;
+        }
+        let mut result = create_frame(self, stack_size, gas_tracker, call_info.clone(), salt);
+        self.inspector.get_mut().create_end(&call_info, &mut result);
+        result
+    }
+    fn call(
+        &mut self,
+        stack_size: usize,
+        gas_tracker: &mut GasTracker,
+        address: Address,
+        mut call_info: CallInfo<U256>,
+    ) -> Result<ExecutionResult, ExecError> {
+        if let Some(result) = self.inspector.get_mut().call(address, &mut call_info) {
+            return result;
+        }
+        let mut result = call_frame(self, stack_size, gas_tracker, address, call_info.clone());
+        self.inspector
+            .get_mut()
+            .call_end(address, &call_info, &mut result);
+        result
+    }
+    fn sstore(
+        &mut self,
+        contract: Address,
+        address: U256,
+        value: U256,
+    ) -> Result<(), Box<dyn Error>> {
+        self.ctx.sstore(contract, address, value)
+    }
+    fn tstore(&mut self, address: U256, value: U256) -> Result<(), Box<dyn Error>> {
+        self.ctx.tstore(address, value)
+    }
+    fn log(
+        &mut self,
+        address: Address,
+        topics: Vec<U256>,
+        data: Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.inspector.get_mut().log(&Log {
+            address,
+            topics: topics.clone(),
+            data: data.clone(),
+        });
+        self.ctx.log(address, topics, data)
+    }
+}
+
+impl<C: ContextMut<U256> + State, I: Inspector> State for Inspected<'_, C, I> {
+    fn set_nonce(&mut self, address: Address, nonce: U256) -> Result<(), Box<dyn Error>> {
+        self.ctx.set_nonce(address, nonce)
+    }
+    fn set_balance(&mut self, address: Address, balance: U256) -> Result<(), Box<dyn Error>> {
+        self.ctx.set_balance(address, balance)
+    }
+    fn set_code(&mut self, address: Address, code: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        self.ctx.set_code(address, code)
+    }
+    fn delete_account(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        self.ctx.delete_account(address)
+    }
+    fn precompile(&self, address: Address) -> Option<Precompile> {
+        self.ctx.precompile(address)
+    }
+    fn logs(&mut self) -> &mut Vec<Log> {
+        self.ctx.logs()
+    }
+    fn checkpoint(&mut self) -> usize {
+        self.ctx.checkpoint()
+    }
+    fn revert_checkpoint(&mut self, id: usize) {
+        self.ctx.revert_checkpoint(id)
+    }
+    fn discard_checkpoint(&mut self, id: usize) {
+        self.ctx.discard_checkpoint(id)
+    }
+    fn spec(&self) -> Spec {
+        self.ctx.spec()
+    }
+    fn begin_transaction(&mut self, tx: TxEnv) {
+        self.ctx.begin_transaction(tx)
+    }
+    fn end_transaction(&mut self) -> Result<(), Box<dyn Error>> {
+        self.ctx.end_transaction()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::MiniEthereum,
+        error::RevertError,
+        tests::{addr, COUNTER},
+        transaction::{Transaction, TxEip1559},
+    };
+    use alloy_primitives::primitives::hex;
+
+    fn fixture() -> MiniEthereum {
+        let mut ctx = MiniEthereum::new();
+        ctx.fund(addr(1), U256::from(1_000_000));
+        ctx.accounts.entry(addr(0x42)).or_default().code = COUNTER.to_vec();
+        ctx
+    }
+
+    fn increment() -> Transaction {
+        TxEip1559 {
+            to: Some(addr(0x42)),
+            gas_limit: 100_000,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        steps: Vec<(usize, u8, usize)>,
+        ends: usize,
+        calls: Vec<Address>,
+        results: Vec<ExecutionResult>,
+        logs: Vec<Log>,
+        destroyed: Vec<(Address, Address)>,
+        /// Halts the frame before this many steps.
+        stop_at: Option<usize>,
+        /// Replaces the output of every call.
+        output: Option<Vec<u8>>,
+    }
+
+    impl Inspector for Recorder {
+        fn step(
+            &mut self,
+            machine: &mut Machine<U256>,
+        ) -> Result<Option<ExecutionResult>, ExecError> {
+            if self.stop_at == Some(self.steps.len()) {
+                return Ok(Some(ExecutionResult::Halted));
+            }
+            self.steps
+                .push((machine.pc, machine.code[machine.pc], machine.stack.len()));
+            Ok(None)
+        }
+        fn step_end(
+            &mut self,
+            _machine: &mut Machine<U256>,
+            _opcode: u8,
+            _result: &mut Result<Option<ExecutionResult>, ExecError>,
+        ) {
+            self.ends += 1;
+        }
+        fn call(
+            &mut self,
+            address: Address,
+            _call_info: &mut CallInfo<U256>,
+        ) -> Option<Result<ExecutionResult, ExecError>> {
+            self.calls.push(address);
+            None
+        }
+        fn call_end(
+            &mut self,
+            _address: Address,
+            _call_info: &CallInfo<U256>,
+            result: &mut Result<ExecutionResult, ExecError>,
+        ) {
+            if let Ok(res) = result {
+                self.results.push(res.clone());
+            }
+            if let Some(output) = &self.output {
+                *result = Ok(ExecutionResult::Returned(output.clone()));
+            }
+        }
+        fn log(&mut self, log: &Log) {
+            self.logs.push(log.clone());
+        }
+        fn selfdestruct(&mut self, contract: Address, target: Address) {
+            self.destroyed.push((contract, target));
+        }
+    }
+
+    #[test]
+    fn test_inspect_transaction() {
+        let mut ctx = fixture();
+        let mut recorder = Recorder::default();
+        let receipt = ctx
+            .inspect_transaction(addr(1), &increment(), &mut recorder)
+            .unwrap();
+        assert!(receipt.success);
+        assert_eq!(recorder.calls, [addr(0x42)]);
+        assert_eq!(recorder.steps.len(), 12);
+        assert_eq!(recorder.ends, 12);
+        assert_eq!(
+            recorder.steps[..3],
+            [(0, 0x5f, 0), (1, 0x54, 1), (2, 0x60, 1)]
+        );
+        assert_eq!(
+            recorder.results,
+            [ExecutionResult::Returned(receipt.output)]
+        );
+
+        // Same execution as without an inspector.
+        let mut plain = fixture();
+        let expected = plain.execute_transaction(addr(1), &increment()).unwrap();
+        assert_eq!(receipt.gas_used, expected.gas_used);
+        assert_eq!(ctx.accounts, plain.accounts);
+    }
+
+    #[test]
+    fn test_inspector_changes_execution() {
+        let mut ctx = fixture();
+        let mut recorder = Recorder {
+            stop_at: Some(2),
+            output: Some(vec![0xaa]),
+            ..Default::default()
+        };
+        let receipt = ctx
+            .inspect_transaction(addr(1), &increment(), &mut recorder)
+            .unwrap();
+        // Halted before SSTORE, with the output replaced.
+        assert_eq!(recorder.steps.len(), 2);
+        assert_eq!(recorder.results, [ExecutionResult::Halted]);
+        assert_eq!(receipt.output, vec![0xaa]);
+        assert_eq!(ctx.sload(addr(0x42), U256::ZERO).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_inspect_log_and_selfdestruct() {
+        let mut ctx = fixture();
+        // PUSH1 0x07 PUSH0 MSTORE8 PUSH1 0x01 PUSH0 LOG0 PUSH1 0x09 SELFDESTRUCT
+        ctx.accounts.entry(addr(0x43)).or_default().code = hex!("60075f5360015fa06009ff").to_vec();
+        let mut recorder = Recorder::default();
+        let result = Inspected::new(&mut ctx, &mut recorder).call(
+            1024,
+            &mut GasTracker::new(100_000),
+            addr(0x43),
+            CallInfo {
+                origin: addr(1),
+                caller: addr(1),
+                value: U256::ZERO,
+                data: vec![],
+                is_static: false,
+            },
+        );
+        // SELFDESTRUCT is not supported by `MiniEthereum`, so the hook never fires.
+        assert!(matches!(
+            result,
+            Err(ExecError::Revert(RevertError::UnknownOpcode(0xff)))
+        ));
+        assert_eq!(
+            recorder.logs,
+            [Log {
+                address: addr(0x43),
+                topics: vec![],
+                data: vec![0x07],
+            }]
+        );
+        assert!(recorder.destroyed.is_empty());
+        assert!(ctx.logs.is_empty());
+    }
+}
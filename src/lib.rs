@@ -1,9 +1,30 @@
+pub mod block;
 pub mod context;
+pub mod diff;
+pub mod disk;
+pub mod eip712;
+pub mod env;
 pub mod error;
+pub mod fork;
+pub mod genesis;
+pub mod inspector;
 pub mod keccak;
 pub mod machine;
 pub mod opcodes;
+pub mod overlay;
+pub mod precompiles;
+pub mod proof;
+pub mod rlp;
+pub mod rpc;
+pub mod signature;
+pub mod snapshot;
+pub mod state_trie;
+pub mod store;
+pub mod transaction;
+pub mod trie;
 pub mod u256;
+pub mod wallet;
+pub mod witness;
 
 #[cfg(test)]
 mod tests;
@@ -60,6 +60,8 @@ pub trait Word: Clone + Debug + Default + Copy + PartialEq + Eq + PartialOrd + O
 pub struct GasTracker {
     pub gas_limit: usize,
     pub gas_used: usize,
+    /// Gas to be given back at the end of the transaction, capped by the executor.
+    pub refund: usize,
 }
 
 impl GasTracker {
@@ -70,6 +72,7 @@ impl GasTracker {
         Self {
             gas_limit,
             gas_used: 0,
+            refund: 0,
         }
     }
     pub fn consume_gas(&mut self, gas: usize) -> Result<(), RevertError> {
@@ -211,14 +214,21 @@ impl<'a, W: Word> Machine<'a, W> {
         opcode_table.insert(0xff, Box::new(OpcodeSelfDestruct));
 
         while self.pc < self.code.len() {
-            self.consume_gas(3)?; // Consume gas to prevent infinite loops
+            if let Some(res) = ctx.step(&mut self)? {
+                return Ok(res);
+            }
             let opcode = self.code[self.pc];
-            if let Some(opcode_fn) = opcode_table.get(&opcode) {
-                if let Some(res) = opcode_fn.call(ctx, &mut self, call_info)? {
-                    return Ok(res);
-                }
-            } else {
-                return Err(RevertError::UnknownOpcode(opcode).into());
+            // Consume gas to prevent infinite loops
+            let mut result = match self.consume_gas(3) {
+                Err(e) => Err(e.into()),
+                Ok(()) => match opcode_table.get(&opcode) {
+                    Some(opcode_fn) => opcode_fn.call(ctx, &mut self, call_info),
+                    None => Err(RevertError::UnknownOpcode(opcode).into()),
+                },
+            };
+            ctx.step_end(&mut self, opcode, &mut result);
+            if let Some(res) = result? {
+                return Ok(res);
             }
         }
         Ok(ExecutionResult::Halted)
@@ -282,7 +292,7 @@ mod tests {
     fn test_mem_put() {
         let mut gt = GasTracker::new(10000000);
         let mut m = Machine::<U256>::new(Address::ZERO, vec![], &mut gt, 1024);
-        assert_eq!(m.memory, vec![]);
+        assert_eq!(m.memory, Vec::<u8>::new());
         m.mem_put(2, &[1, 2, 3], 1, 10).unwrap();
         assert_eq!(m.memory, vec![0, 0, 2, 3]);
         m.mem_put(1, &[4, 5, 6], 5, 10).unwrap();
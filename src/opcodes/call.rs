@@ -57,20 +57,23 @@ impl<W: Word, C: Context<W>> OpcodeHandler<W, C> for OpcodeCall {
             .as_mut()
             .call(stack_size, &mut gas_tracker, address, new_call_info)
         {
-            Ok(exec_result) => match exec_result {
-                ExecutionResult::Halted => {
-                    machine.last_return = Some(vec![]);
-                    machine.push_stack(W::ONE)?;
-                }
-                ExecutionResult::Returned(ret) => {
-                    machine.mem_put(ret_offset, &ret, 0, ret_size)?;
-                    machine.last_return = Some(ret);
-                    machine.push_stack(W::ONE)?;
+            Ok(exec_result) => {
+                machine.gas_tracker.refund += gas_tracker.refund;
+                match exec_result {
+                    ExecutionResult::Halted => {
+                        machine.last_return = Some(vec![]);
+                        machine.push_stack(W::ONE)?;
+                    }
+                    ExecutionResult::Returned(ret) => {
+                        machine.mem_put(ret_offset, &ret, 0, ret_size)?;
+                        machine.last_return = Some(ret);
+                        machine.push_stack(W::ONE)?;
+                    }
                 }
-            },
+            }
             Err(e) => match e {
-                ExecError::Context(e) => {
-                    return Err(ExecError::Context(e));
+                ExecError::Context(_) | ExecError::InvalidTransaction(_) => {
+                    return Err(e);
                 }
                 ExecError::Revert(e) => {
                     if let RevertError::Revert(ret) = e {
@@ -47,14 +47,15 @@ impl<W: Word, C: Context<W>> OpcodeHandler<W, C> for OpcodeCreate {
             salt,
         ) {
             Ok(addr) => {
+                machine.gas_tracker.refund += gas_tracker.refund;
                 machine.push_stack(W::from_addr(addr))?;
             }
             Err(e) => match e {
                 ExecError::Revert(_) => {
                     machine.push_stack(W::ZERO)?;
                 }
-                ExecError::Context(ctx_err) => {
-                    return Err(ExecError::Context(ctx_err));
+                ExecError::Context(_) | ExecError::InvalidTransaction(_) => {
+                    return Err(e);
                 }
             },
         }
@@ -194,8 +194,8 @@ impl<W: Word, C: Context<W>> OpcodeHandler<W, C> for OpcodeCalldataLoad {
     ) -> Result<Option<ExecutionResult>, ExecError> {
         let offset = machine.pop_stack()?.to_usize()?;
         let mut ret = [0u8; 32];
-        for i in 0..32 {
-            ret[i] = call_info.data.get(offset + i).copied().unwrap_or_default();
+        for (i, b) in ret.iter_mut().enumerate() {
+            *b = call_info.data.get(offset + i).copied().unwrap_or_default();
         }
         machine.push_stack(W::from_big_endian(&ret))?;
         machine.pc += 1;
@@ -249,9 +249,15 @@ impl<W: Word, C: Context<W>> OpcodeHandler<W, C> for OpcodeExtCodeHash {
         machine: &mut Machine<W>,
         _call_info: &CallInfo<W>,
     ) -> Result<Option<ExecutionResult>, ExecError> {
-        let addr = machine.pop_stack()?;
-        let code_hash = keccak(&ctx.code(addr.to_addr()?)?);
-        machine.push_stack(W::from_big_endian(&code_hash))?;
+        let addr = machine.pop_stack()?.to_addr()?;
+        let code = ctx.code(addr)?;
+        let zero = W::from_u64(0);
+        // Non-existent and empty accounts (EIP-161) have no code hash (EIP-1052).
+        if code.is_empty() && ctx.nonce(addr)? == zero && ctx.balance(addr)? == zero {
+            machine.push_stack(zero)?;
+        } else {
+            machine.push_stack(W::from_big_endian(&keccak(&code)))?;
+        }
         machine.pc += 1;
         Ok(None)
     }
@@ -0,0 +1,728 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+};
+
+use alloy_primitives::primitives::Address;
+
+use crate::{
+    context::{
+        call_frame, create_frame, default_precompiles, env_info, Context, ContextMut, Info, Log,
+        Spec, State, UndoLog,
+    },
+    diff::{AccountState, StateDiff},
+    env::{BlockEnv, TxEnv},
+    error::{ExecError, RevertError},
+    machine::{CallInfo, GasTracker},
+    opcodes::ExecutionResult,
+    precompiles::{self, Precompile},
+    u256::U256,
+    witness::Witness,
+};
+
+/// Writes made to an account on top of a base state, `None` fields are unchanged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountChange {
+    /// The account of the base was deleted, along with its storage, before the other
+    /// fields were written: unchanged fields and slots read as zero.
+    pub deleted: bool,
+    pub nonce: Option<U256>,
+    pub balance: Option<U256>,
+    pub code: Option<Vec<u8>>,
+    pub storage: HashMap<U256, U256>,
+}
+
+/// Writes buffered by an `Overlay`, detached from its base.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeSet {
+    pub accounts: HashMap<Address, AccountChange>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn nonce(&self, address: Address) -> Option<U256> {
+        let change = self.accounts.get(&address)?;
+        change.nonce.or(change.deleted.then_some(U256::ZERO))
+    }
+
+    pub fn balance(&self, address: Address) -> Option<U256> {
+        let change = self.accounts.get(&address)?;
+        change.balance.or(change.deleted.then_some(U256::ZERO))
+    }
+
+    pub fn code(&self, address: Address) -> Option<&[u8]> {
+        let change = self.accounts.get(&address)?;
+        match &change.code {
+            Some(code) => Some(code),
+            None => change.deleted.then_some(&[]),
+        }
+    }
+
+    pub fn sload(&self, address: Address, slot: U256) -> Option<U256> {
+        let change = self.accounts.get(&address)?;
+        match change.storage.get(&slot) {
+            Some(value) => Some(*value),
+            None => change.deleted.then_some(U256::ZERO),
+        }
+    }
+
+    /// Changes of `address`, created empty if needed.
+    pub fn account_mut(&mut self, address: Address) -> &mut AccountChange {
+        self.accounts.entry(address).or_default()
+    }
+
+    /// Writes the changes into `target`.
+    pub fn apply<M: ContextMut<U256> + State>(self, target: &mut M) -> Result<(), Box<dyn Error>> {
+        for (address, change) in self.accounts {
+            if change.deleted {
+                target.delete_account(address)?;
+            }
+            if let Some(nonce) = change.nonce {
+                target.set_nonce(address, nonce)?;
+            }
+            if let Some(balance) = change.balance {
+                target.set_balance(address, balance)?;
+            }
+            if let Some(code) = change.code {
+                target.set_code(address, code)?;
+            }
+            for (slot, value) in change.storage {
+                target.sstore(address, slot, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A write of an `Overlay` to undo, with the value it replaced.
+enum Undo {
+    Nonce(Address, Option<U256>),
+    Balance(Address, Option<U256>),
+    Code(Address, Option<Vec<u8>>),
+    Storage(Address, U256, Option<U256>),
+    Transient(U256, Option<U256>),
+    /// An account deleted or replaced as a whole.
+    Account(Address, Option<AccountChange>),
+    /// An account first written by the current transaction.
+    Written(Address),
+}
+
+/// State an `Overlay` reads through to.
+///
+/// Contexts keeping a `BlockHistory` are backends through a reference to them, so that
+/// overlays can be stacked.
+pub trait Backend {
+    fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>>;
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>>;
+    fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>>;
+    fn storage(&self, address: Address, slot: U256) -> Result<U256, Box<dyn Error>>;
+    /// Hash of block `number`, only asked for blocks BLOCKHASH can see.
+    fn block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>>;
+    /// Fails if the account, or its `slot`, cannot be written on top of this state.
+    fn check_write(&self, _address: Address, _slot: Option<U256>) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Hashes of the past blocks a context knows.
+pub trait BlockHistory {
+    /// Hash of block `number`, zero if unknown. Unlike `Context::block_hash`, the number is
+    /// not checked against the block environment of the context: an overlay simulating a
+    /// later block checks it against its own.
+    fn past_block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>>;
+}
+
+impl<C: Context<U256> + BlockHistory> Backend for &C {
+    fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        Context::balance(*self, address)
+    }
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        Context::nonce(*self, address)
+    }
+    fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        Context::code(*self, address)
+    }
+    fn storage(&self, address: Address, slot: U256) -> Result<U256, Box<dyn Error>> {
+        Context::sload(*self, address, slot)
+    }
+    fn block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>> {
+        self.past_block_hash(number)
+    }
+}
+
+/// A context reading through to a `Backend` and keeping every write to itself, to run
+/// transactions against a state without copying or modifying it.
+///
+/// The base is only read: the buffered writes can be taken out with `into_changes` and
+/// applied to a mutable state, or dropped along with the overlay. The block and
+/// transaction environments are the overlay's own, not the ones of the base.
+pub struct Overlay<B> {
+    pub spec: Spec,
+    pub block: BlockEnv,
+    pub tx: TxEnv,
+    base: B,
+    precompiles: HashMap<Address, Precompile>,
+    changes: ChangeSet,
+    /// Accounts read or written, with the slots accessed.
+    touched: RefCell<BTreeMap<Address, BTreeSet<U256>>>,
+    block_hashes: RefCell<BTreeMap<U256, U256>>,
+    /// Accounts written by the current transaction, see `end_transaction`.
+    written: BTreeSet<Address>,
+    undo: UndoLog<Undo>,
+    pub transient: HashMap<U256, U256>,
+    pub logs: Vec<Log>,
+}
+
+impl<B: Backend> Overlay<B> {
+    pub fn new(base: B) -> Self {
+        let spec = Spec::default();
+        Self {
+            spec,
+            block: BlockEnv::default(),
+            tx: TxEnv::default(),
+            base,
+            precompiles: default_precompiles(spec),
+            changes: ChangeSet::default(),
+            touched: RefCell::new(BTreeMap::new()),
+            block_hashes: RefCell::new(BTreeMap::new()),
+            written: BTreeSet::new(),
+            undo: UndoLog::default(),
+            transient: HashMap::new(),
+            logs: Vec::new(),
+        }
+    }
+
+    pub fn with_spec(mut self, spec: Spec) -> Self {
+        self.spec = spec;
+        self.precompiles = default_precompiles(spec);
+        self
+    }
+
+    pub fn with_block_env(mut self, block: BlockEnv) -> Self {
+        self.block = block;
+        self
+    }
+
+    pub fn with_tx_env(mut self, tx: TxEnv) -> Self {
+        self.tx = tx;
+        self
+    }
+
+    /// Enables the RIP-7212 P256VERIFY precompile at 0x100.
+    pub fn with_p256_verify(mut self) -> Self {
+        self.precompiles.insert(
+            Address::left_padding_from(&[0x01, 0x00]),
+            &precompiles::p256_verify,
+        );
+        self
+    }
+
+    pub fn base(&self) -> &B {
+        &self.base
+    }
+
+    /// Writes made so far.
+    pub fn changes(&self) -> &ChangeSet {
+        &self.changes
+    }
+
+    /// Ends the overlay, releasing the base so that the changes can be applied to it.
+    pub fn into_changes(self) -> ChangeSet {
+        self.changes
+    }
+
+    /// Drops the writes made so far, leaving a view of the base only.
+    pub fn discard(&mut self) {
+        self.changes = ChangeSet::default();
+        self.touched.borrow_mut().clear();
+        self.block_hashes.borrow_mut().clear();
+        self.written.clear();
+        self.undo.clear();
+        self.transient.clear();
+        self.logs.clear();
+    }
+
+    fn touch(&self, address: Address, slot: Option<U256>) {
+        let mut touched = self.touched.borrow_mut();
+        let slots = touched.entry(address).or_default();
+        slots.extend(slot);
+    }
+
+    /// Touches the account, checks that the base accepts writing it and marks it written
+    /// by the transaction.
+    fn check_write(&mut self, address: Address, slot: Option<U256>) -> Result<(), Box<dyn Error>> {
+        self.touch(address, slot);
+        let first_write = match slot {
+            Some(slot) => self.changes.sload(address, slot).is_none(),
+            None => true,
+        };
+        if first_write {
+            self.base.check_write(address, slot)?;
+        }
+        if self.written.insert(address) {
+            self.undo.record(Undo::Written(address));
+        }
+        Ok(())
+    }
+
+    fn write_account(&mut self, address: Address) -> Result<&mut AccountChange, Box<dyn Error>> {
+        self.check_write(address, None)?;
+        Ok(self.changes.account_mut(address))
+    }
+
+    /// Prestate of the accounts touched so far and the changes made to them.
+    pub fn state_diff(&self) -> Result<StateDiff, Box<dyn Error>> {
+        let accounts = self
+            .witness()?
+            .accounts
+            .into_iter()
+            .map(|(address, pre)| {
+                let mut post = AccountState {
+                    balance: self.balance(address)?,
+                    nonce: self.nonce(address)?,
+                    code: self.code(address)?,
+                    storage: BTreeMap::new(),
+                };
+                for slot in pre.storage.keys() {
+                    post.storage.insert(*slot, self.sload(address, *slot)?);
+                }
+                Ok((address, pre, post))
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        Ok(StateDiff::new(accounts))
+    }
+
+    /// Base state accessed so far, enough to replay the same execution on top of the
+    /// `Witness` alone.
+    pub fn witness(&self) -> Result<Witness, Box<dyn Error>> {
+        let mut witness = Witness {
+            block_hashes: self.block_hashes.borrow().clone(),
+            ..Default::default()
+        };
+        for (address, slots) in self.touched.borrow().iter() {
+            let mut account = AccountState {
+                balance: self.base.balance(*address)?,
+                nonce: self.base.nonce(*address)?,
+                code: self.base.code(*address)?,
+                storage: BTreeMap::new(),
+            };
+            for slot in slots {
+                account
+                    .storage
+                    .insert(*slot, self.base.storage(*address, *slot)?);
+            }
+            witness.accounts.insert(*address, account);
+        }
+        Ok(witness)
+    }
+}
+
+impl<B: Backend> Context<U256> for Overlay<B> {
+    type Mutable = Self;
+    fn tload(&self, address: U256) -> Result<U256, Box<dyn Error>> {
+        Ok(self.transient.get(&address).cloned().unwrap_or_default())
+    }
+    fn as_mut(&mut self) -> &mut Self::Mutable {
+        self
+    }
+    fn destroy(&self, _contract: Address, _target: Address) -> Result<(), ExecError> {
+        Err(ExecError::Revert(RevertError::UnknownOpcode(0xff)))
+    }
+    fn blob_hash(&self, index: U256) -> Result<U256, Box<dyn Error>> {
+        Ok(self.tx.blob_hash(index))
+    }
+    fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.touch(address, None);
+        match self.changes.code(address) {
+            Some(code) => Ok(code.to_vec()),
+            None => self.base.code(address),
+        }
+    }
+    fn block_hash(&self, block_number: U256) -> Result<U256, Box<dyn Error>> {
+        if !self.block.has_block_hash(block_number) {
+            return Ok(U256::ZERO);
+        }
+        self.past_block_hash(block_number)
+    }
+    fn info(&self, inf: Info) -> Result<U256, Box<dyn Error>> {
+        Ok(env_info(self.spec, &self.block, &self.tx, inf))
+    }
+    fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.touch(address, None);
+        match self.changes.balance(address) {
+            Some(balance) => Ok(balance),
+            None => self.base.balance(address),
+        }
+    }
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        self.touch(address, None);
+        match self.changes.nonce(address) {
+            Some(nonce) => Ok(nonce),
+            None => self.base.nonce(address),
+        }
+    }
+    fn sload(&self, contract: Address, address: U256) -> Result<U256, Box<dyn Error>> {
+        self.touch(contract, Some(address));
+        match self.changes.sload(contract, address) {
+            Some(value) => Ok(value),
+            None => self.base.storage(contract, address),
+        }
+    }
+}
+
+impl<B: Backend> BlockHistory for Overlay<B> {
+    fn past_block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>> {
+        let hash = self.base.block_hash(number)?;
+        self.block_hashes.borrow_mut().insert(number, hash);
+        Ok(hash)
+    }
+}
+
+impl<B: Backend> ContextMut<U256> for Overlay<B> {
+    fn tstore(&mut self, address: U256, value: U256) -> Result<(), Box<dyn Error>> {
+        let previous = self.transient.insert(address, value);
+        self.undo.record(Undo::Transient(address, previous));
+        Ok(())
+    }
+    fn create(
+        &mut self,
+        stack_size: usize,
+        gas_tracker: &mut GasTracker,
+        call_info: CallInfo<U256>,
+        salt: Option<U256>,
+    ) -> Result<Address, ExecError> {
+        create_frame(self, stack_size, gas_tracker, call_info, salt)
+    }
+    fn call(
+        &mut self,
+        stack_size: usize,
+        gas_tracker: &mut GasTracker,
+        address: Address,
+        call_info: CallInfo<U256>,
+    ) -> Result<ExecutionResult, ExecError> {
+        call_frame(self, stack_size, gas_tracker, address, call_info)
+    }
+    fn sstore(
+        &mut self,
+        contract: Address,
+        address: U256,
+        value: U256,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_write(contract, Some(address))?;
+        let previous = self
+            .changes
+            .account_mut(contract)
+            .storage
+            .insert(address, value);
+        self.undo.record(Undo::Storage(contract, address, previous));
+        Ok(())
+    }
+    fn log(
+        &mut self,
+        address: Address,
+        topics: Vec<U256>,
+        data: Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.logs.push(Log {
+            address,
+            topics,
+            data,
+        });
+        Ok(())
+    }
+}
+
+impl<B: Backend> State for Overlay<B> {
+    fn set_nonce(&mut self, address: Address, nonce: U256) -> Result<(), Box<dyn Error>> {
+        let previous = self.write_account(address)?.nonce.replace(nonce);
+        self.undo.record(Undo::Nonce(address, previous));
+        Ok(())
+    }
+    fn set_balance(&mut self, address: Address, balance: U256) -> Result<(), Box<dyn Error>> {
+        let previous = self.write_account(address)?.balance.replace(balance);
+        self.undo.record(Undo::Balance(address, previous));
+        Ok(())
+    }
+    fn set_code(&mut self, address: Address, code: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let previous = self.write_account(address)?.code.replace(code);
+        self.undo.record(Undo::Code(address, previous));
+        Ok(())
+    }
+    fn delete_account(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        self.check_write(address, None)?;
+        let deleted = AccountChange {
+            deleted: true,
+            ..Default::default()
+        };
+        let previous = self.changes.accounts.insert(address, deleted);
+        self.undo.record(Undo::Account(address, previous));
+        Ok(())
+    }
+    fn precompile(&self, address: Address) -> Option<Precompile> {
+        self.precompiles.get(&address).copied()
+    }
+    fn logs(&mut self) -> &mut Vec<Log> {
+        &mut self.logs
+    }
+    fn checkpoint(&mut self) -> usize {
+        self.undo.checkpoint()
+    }
+    fn revert_checkpoint(&mut self, id: usize) {
+        for undo in self.undo.revert(id) {
+            let address = match undo {
+                Undo::Nonce(address, nonce) => {
+                    self.changes.account_mut(address).nonce = nonce;
+                    address
+                }
+                Undo::Balance(address, balance) => {
+                    self.changes.account_mut(address).balance = balance;
+                    address
+                }
+                Undo::Code(address, code) => {
+                    self.changes.account_mut(address).code = code;
+                    address
+                }
+                Undo::Storage(address, slot, value) => {
+                    let storage = &mut self.changes.account_mut(address).storage;
+                    match value {
+                        Some(value) => storage.insert(slot, value),
+                        None => storage.remove(&slot),
+                    };
+                    address
+                }
+                Undo::Account(address, change) => {
+                    match change {
+                        Some(change) => self.changes.accounts.insert(address, change),
+                        None => self.changes.accounts.remove(&address),
+                    };
+                    address
+                }
+                Undo::Written(address) => {
+                    self.written.remove(&address);
+                    continue;
+                }
+                Undo::Transient(slot, value) => {
+                    match value {
+                        Some(value) => self.transient.insert(slot, value),
+                        None => self.transient.remove(&slot),
+                    };
+                    continue;
+                }
+            };
+            // Accounts first written under the checkpoint leave no empty change behind.
+            if self.changes.accounts.get(&address) == Some(&AccountChange::default()) {
+                self.changes.accounts.remove(&address);
+            }
+        }
+    }
+    fn discard_checkpoint(&mut self, id: usize) {
+        self.undo.discard(id);
+    }
+    fn spec(&self) -> Spec {
+        self.spec
+    }
+    fn begin_transaction(&mut self, tx: TxEnv) {
+        self.tx = tx;
+        self.logs.clear();
+        self.written.clear();
+    }
+    fn end_transaction(&mut self) -> Result<(), Box<dyn Error>> {
+        self.transient.clear();
+        for address in std::mem::take(&mut self.written) {
+            if self.nonce(address)?.is_zero()
+                && self.balance(address)?.is_zero()
+                && self.code(address)?.is_empty()
+            {
+                self.delete_account(address)?;
+            }
+        }
+        self.written.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::MiniEthereum,
+        disk::DiskEthereum,
+        store::tests::TempPath,
+        tests::{addr, COUNTER_INIT},
+    };
+
+    const STACK_SIZE: usize = 1024;
+
+    fn call_info(value: u64, data: Vec<u8>) -> CallInfo<U256> {
+        CallInfo {
+            origin: addr(1),
+            caller: addr(1),
+            value: U256::from(value),
+            data,
+            is_static: false,
+        }
+    }
+
+    fn increment<C: ContextMut<U256>>(ctx: &mut C, contract: Address) -> U256 {
+        let res = ctx
+            .call(
+                STACK_SIZE,
+                &mut GasTracker::new(1_000_000),
+                contract,
+                call_info(0, vec![]),
+            )
+            .unwrap();
+        match res {
+            ExecutionResult::Returned(data) => U256::from_be_slice(&data),
+            ExecutionResult::Halted => panic!("no output"),
+        }
+    }
+
+    fn base() -> (MiniEthereum, Address) {
+        let mut ctx = MiniEthereum::new();
+        ctx.fund(addr(1), U256::from(1000));
+        let contract = ctx
+            .create(
+                STACK_SIZE,
+                &mut GasTracker::new(1_000_000),
+                call_info(10, COUNTER_INIT.to_vec()),
+                None,
+            )
+            .unwrap();
+        (ctx, contract)
+    }
+
+    #[test]
+    fn test_overlay_leaves_base_untouched() {
+        let (base, contract) = base();
+        let snapshot = base.accounts.clone();
+
+        let mut overlay = Overlay::new(&base);
+        assert_eq!(increment(&mut overlay, contract), U256::ONE);
+        assert_eq!(increment(&mut overlay, contract), U256::from(2));
+        overlay
+            .call(
+                STACK_SIZE,
+                &mut GasTracker::new(1_000_000),
+                addr(2),
+                call_info(100, vec![]),
+            )
+            .unwrap();
+        let deployed = overlay
+            .create(
+                STACK_SIZE,
+                &mut GasTracker::new(1_000_000),
+                call_info(0, COUNTER_INIT.to_vec()),
+                None,
+            )
+            .unwrap();
+        overlay.tstore(U256::ONE, U256::ONE).unwrap();
+
+        assert_eq!(overlay.sload(contract, U256::ZERO).unwrap(), U256::from(2));
+        assert_eq!(overlay.balance(addr(1)).unwrap(), U256::from(890));
+        assert_eq!(overlay.balance(addr(2)).unwrap(), U256::from(100));
+        assert_eq!(overlay.nonce(addr(1)).unwrap(), U256::from(2));
+        assert_eq!(overlay.code(deployed).unwrap(), COUNTER_INIT[12..]);
+        assert_eq!(base.accounts, snapshot);
+        assert_eq!(base.tload(U256::ONE).unwrap(), U256::ZERO);
+
+        // Two overlays share the same base without seeing each other's writes.
+        let other = Overlay::new(&base);
+        assert_eq!(other.sload(contract, U256::ZERO).unwrap(), U256::ZERO);
+
+        overlay.discard();
+        assert!(overlay.changes().is_empty());
+        assert_eq!(overlay.balance(addr(1)).unwrap(), U256::from(990));
+        assert_eq!(overlay.tload(U256::ONE).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_overlay_commit() {
+        let (mut base, contract) = base();
+        let mut expected = base.clone();
+        increment(&mut expected, contract);
+        expected.fund(addr(2), U256::from(5));
+
+        let mut overlay = Overlay::new(&base);
+        increment(&mut overlay, contract);
+        overlay.set_balance(addr(2), U256::from(5)).unwrap();
+        let changes = overlay.into_changes();
+        assert_eq!(
+            changes.accounts[&contract].storage,
+            [(U256::ZERO, U256::ONE)].into_iter().collect()
+        );
+        changes.apply(&mut base).unwrap();
+        assert_eq!(base.accounts, expected.accounts);
+
+        // The same changes apply to any mutable state.
+        let path = TempPath::new("overlay");
+        let mut db = DiskEthereum::open(&path.0).unwrap();
+        let mut overlay = Overlay::new(&base);
+        increment(&mut overlay, contract);
+        overlay.into_changes().apply(&mut db).unwrap();
+        db.commit().unwrap();
+        assert_eq!(db.sload(contract, U256::ZERO).unwrap(), U256::from(2));
+        assert!(db.code(contract).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stacked_overlays() {
+        let (base, contract) = base();
+        let mut lower = Overlay::new(&base);
+        increment(&mut lower, contract);
+
+        let mut upper = Overlay::new(&lower);
+        assert_eq!(increment(&mut upper, contract), U256::from(2));
+        let changes = upper.into_changes();
+        assert_eq!(lower.sload(contract, U256::ZERO).unwrap(), U256::ONE);
+        changes.apply(&mut lower).unwrap();
+        assert_eq!(lower.sload(contract, U256::ZERO).unwrap(), U256::from(2));
+        assert_eq!(base.sload(contract, U256::ZERO).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_overlay_checkpoint() {
+        let (base, contract) = base();
+        let mut overlay = Overlay::new(&base);
+        overlay.set_balance(addr(2), U256::from(5)).unwrap();
+        let expected = overlay.changes().clone();
+
+        let outer = overlay.checkpoint();
+        overlay.set_balance(addr(2), U256::from(6)).unwrap();
+        increment(&mut overlay, contract);
+        let inner = overlay.checkpoint();
+        overlay.set_code(addr(3), vec![0x00]).unwrap();
+        overlay.tstore(U256::ONE, U256::ONE).unwrap();
+        // The writes of a discarded checkpoint still roll back with the outer one.
+        overlay.discard_checkpoint(inner);
+        overlay.revert_checkpoint(outer);
+
+        assert_eq!(overlay.changes(), &expected);
+        assert_eq!(overlay.balance(addr(2)).unwrap(), U256::from(5));
+        assert_eq!(overlay.tload(U256::ONE).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_overlay_next_block_hash() {
+        let base = MiniEthereum::new()
+            .with_block_env(BlockEnv::default().with_number(U256::from(10)))
+            .with_block_hash(U256::from(10), U256::from(0xaa));
+        assert_eq!(base.block_hash(U256::from(10)).unwrap(), U256::ZERO);
+
+        // The hash of the base block is visible to the block after it.
+        let overlay =
+            Overlay::new(&base).with_block_env(BlockEnv::default().with_number(U256::from(11)));
+        assert_eq!(
+            overlay.block_hash(U256::from(10)).unwrap(),
+            U256::from(0xaa)
+        );
+        let upper =
+            Overlay::new(&overlay).with_block_env(BlockEnv::default().with_number(U256::from(11)));
+        assert_eq!(upper.block_hash(U256::from(10)).unwrap(), U256::from(0xaa));
+        assert_eq!(overlay.block_hash(U256::from(11)).unwrap(), U256::ZERO);
+    }
+}
@@ -0,0 +1,408 @@
+use ark_bls12_381::{
+    g1, g2, Bls12_381, Fq, Fq2, Fr, G1Affine, G1Projective, G2Affine, G2Projective,
+};
+use ark_ec::{
+    hashing::{curve_maps::wb::WBMap, map_to_curve_hasher::MapToCurve},
+    pairing::Pairing,
+    short_weierstrass::{Affine, SWCurveConfig},
+    AffineRepr, CurveGroup, VariableBaseMSM,
+};
+use ark_ff::{BigInt, PrimeField, Zero};
+
+use crate::error::{ExecError, RevertError};
+use crate::machine::{CallInfo, GasTracker};
+use crate::opcodes::ExecutionResult;
+use crate::u256::U256;
+
+const FP_LENGTH: usize = 64;
+const FP2_LENGTH: usize = 2 * FP_LENGTH;
+const G1_LENGTH: usize = 2 * FP_LENGTH;
+const G2_LENGTH: usize = 2 * FP2_LENGTH;
+const SCALAR_LENGTH: usize = 32;
+const G1_MSM_PAIR_LENGTH: usize = G1_LENGTH + SCALAR_LENGTH;
+const G2_MSM_PAIR_LENGTH: usize = G2_LENGTH + SCALAR_LENGTH;
+const PAIRING_PAIR_LENGTH: usize = G1_LENGTH + G2_LENGTH;
+
+const G1_ADD_GAS: usize = 375;
+const G2_ADD_GAS: usize = 600;
+const G1_MUL_GAS: usize = 12000;
+const G2_MUL_GAS: usize = 22500;
+const PAIRING_BASE_GAS: usize = 37700;
+const PAIRING_PER_PAIR_GAS: usize = 32600;
+const MAP_FP_TO_G1_GAS: usize = 5500;
+const MAP_FP2_TO_G2_GAS: usize = 23800;
+
+const MSM_MULTIPLIER: usize = 1000;
+
+/// Discounts for G1MSM with `k` pairs, indexed by `k - 1`. The last entry applies to
+/// every `k` beyond the table.
+const G1_MSM_DISCOUNT_TABLE: [u16; 128] = [
+    1000, 949, 848, 797, 764, 750, 738, 728, 719, 712, 705, 698, 692, 687, 682, 677, 673, 669, 665,
+    661, 658, 654, 651, 648, 645, 642, 640, 637, 635, 632, 630, 627, 625, 623, 621, 619, 617, 615,
+    613, 611, 609, 608, 606, 604, 603, 601, 599, 598, 596, 595, 593, 592, 591, 589, 588, 586, 585,
+    584, 582, 581, 580, 579, 577, 576, 575, 574, 573, 572, 570, 569, 568, 567, 566, 565, 564, 563,
+    562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 551, 550, 549, 548, 547, 547, 546, 545,
+    544, 543, 542, 541, 540, 540, 539, 538, 537, 536, 536, 535, 534, 533, 532, 532, 531, 530, 529,
+    528, 528, 527, 526, 525, 525, 524, 523, 522, 522, 521, 520, 520, 519,
+];
+
+/// Discounts for G2MSM with `k` pairs, indexed by `k - 1`.
+const G2_MSM_DISCOUNT_TABLE: [u16; 128] = [
+    1000, 1000, 923, 884, 855, 832, 812, 796, 782, 770, 759, 749, 740, 732, 724, 717, 711, 704,
+    699, 693, 688, 683, 679, 674, 670, 666, 663, 659, 655, 652, 649, 646, 643, 640, 637, 634, 632,
+    629, 627, 624, 622, 620, 618, 615, 613, 611, 609, 607, 606, 604, 602, 600, 598, 597, 595, 593,
+    592, 590, 589, 587, 586, 584, 583, 582, 580, 579, 578, 576, 575, 574, 573, 571, 570, 569, 568,
+    567, 566, 565, 563, 562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 552, 551, 550, 549,
+    548, 547, 546, 545, 545, 544, 543, 542, 541, 541, 540, 539, 538, 537, 537, 536, 535, 535, 534,
+    533, 532, 532, 531, 530, 530, 529, 528, 528, 527, 526, 526, 525, 524, 524,
+];
+
+fn msm_gas(k: usize, mul_gas: usize, discount_table: &[u16; 128]) -> usize {
+    let discount = discount_table[std::cmp::min(k, discount_table.len()) - 1] as usize;
+    k * mul_gas * discount / MSM_MULTIPLIER
+}
+
+/// Reads a base field element encoded as 64 big-endian bytes, the top 16 of which
+/// must be zero.
+fn read_fp(bytes: &[u8]) -> Result<Fq, RevertError> {
+    if bytes[..16].iter().any(|b| *b != 0) {
+        return Err(RevertError::InvalidPrecompileInput);
+    }
+    let mut limbs = [0u64; 6];
+    for (limb, chunk) in limbs.iter_mut().zip(bytes[16..].rchunks(8)) {
+        *limb = u64::from_be_bytes(chunk.try_into().unwrap());
+    }
+    Fq::from_bigint(BigInt(limbs)).ok_or(RevertError::InvalidPrecompileInput)
+}
+
+fn read_fp2(bytes: &[u8]) -> Result<Fq2, RevertError> {
+    Ok(Fq2::new(
+        read_fp(&bytes[..FP_LENGTH])?,
+        read_fp(&bytes[FP_LENGTH..])?,
+    ))
+}
+
+fn write_fp(out: &mut Vec<u8>, fp: Fq) {
+    out.extend([0u8; 16]);
+    for limb in fp.into_bigint().0.iter().rev() {
+        out.extend(limb.to_be_bytes());
+    }
+}
+
+fn write_fp2(out: &mut Vec<u8>, fp2: Fq2) {
+    write_fp(out, fp2.c0);
+    write_fp(out, fp2.c1);
+}
+
+/// Builds an affine point from its coordinates, mapping `(0, 0)` to the point at
+/// infinity. Points must always be on the curve, while the subgroup check is only
+/// required by the MSM and pairing precompiles.
+fn to_point<P: SWCurveConfig>(
+    x: P::BaseField,
+    y: P::BaseField,
+    subgroup_check: bool,
+) -> Result<Affine<P>, RevertError> {
+    if x.is_zero() && y.is_zero() {
+        return Ok(Affine::zero());
+    }
+    let point = Affine::new_unchecked(x, y);
+    if !point.is_on_curve() || (subgroup_check && !point.is_in_correct_subgroup_assuming_on_curve())
+    {
+        return Err(RevertError::InvalidPrecompileInput);
+    }
+    Ok(point)
+}
+
+fn read_g1(bytes: &[u8], subgroup_check: bool) -> Result<G1Affine, RevertError> {
+    to_point(
+        read_fp(&bytes[..FP_LENGTH])?,
+        read_fp(&bytes[FP_LENGTH..G1_LENGTH])?,
+        subgroup_check,
+    )
+}
+
+fn read_g2(bytes: &[u8], subgroup_check: bool) -> Result<G2Affine, RevertError> {
+    to_point(
+        read_fp2(&bytes[..FP2_LENGTH])?,
+        read_fp2(&bytes[FP2_LENGTH..G2_LENGTH])?,
+        subgroup_check,
+    )
+}
+
+fn write_g1(point: G1Affine) -> Vec<u8> {
+    let mut out = Vec::with_capacity(G1_LENGTH);
+    match point.xy() {
+        Some((x, y)) => {
+            write_fp(&mut out, *x);
+            write_fp(&mut out, *y);
+        }
+        None => out.resize(G1_LENGTH, 0),
+    }
+    out
+}
+
+fn write_g2(point: G2Affine) -> Vec<u8> {
+    let mut out = Vec::with_capacity(G2_LENGTH);
+    match point.xy() {
+        Some((x, y)) => {
+            write_fp2(&mut out, *x);
+            write_fp2(&mut out, *y);
+        }
+        None => out.resize(G2_LENGTH, 0),
+    }
+    out
+}
+
+fn read_scalar(bytes: &[u8]) -> Fr {
+    Fr::from_be_bytes_mod_order(bytes)
+}
+
+/// EIP-2537 G1ADD precompile at address 0x0b.
+pub fn bls12_g1_add(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    gas_tracker.consume_gas(G1_ADD_GAS)?;
+    let data = &call_info.data;
+    if data.len() != 2 * G1_LENGTH {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let a = read_g1(&data[..G1_LENGTH], false)?;
+    let b = read_g1(&data[G1_LENGTH..], false)?;
+    Ok(ExecutionResult::Returned(write_g1((a + b).into_affine())))
+}
+
+/// EIP-2537 G1MSM precompile at address 0x0c.
+pub fn bls12_g1_msm(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    let data = &call_info.data;
+    if data.is_empty() || !data.len().is_multiple_of(G1_MSM_PAIR_LENGTH) {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let k = data.len() / G1_MSM_PAIR_LENGTH;
+    gas_tracker.consume_gas(msm_gas(k, G1_MUL_GAS, &G1_MSM_DISCOUNT_TABLE))?;
+    let mut bases = Vec::with_capacity(k);
+    let mut scalars = Vec::with_capacity(k);
+    for pair in data.chunks(G1_MSM_PAIR_LENGTH) {
+        bases.push(read_g1(&pair[..G1_LENGTH], true)?);
+        scalars.push(read_scalar(&pair[G1_LENGTH..]));
+    }
+    let result = G1Projective::msm(&bases, &scalars).expect("bases and scalars have equal length");
+    Ok(ExecutionResult::Returned(write_g1(result.into_affine())))
+}
+
+/// EIP-2537 G2ADD precompile at address 0x0d.
+pub fn bls12_g2_add(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    gas_tracker.consume_gas(G2_ADD_GAS)?;
+    let data = &call_info.data;
+    if data.len() != 2 * G2_LENGTH {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let a = read_g2(&data[..G2_LENGTH], false)?;
+    let b = read_g2(&data[G2_LENGTH..], false)?;
+    Ok(ExecutionResult::Returned(write_g2((a + b).into_affine())))
+}
+
+/// EIP-2537 G2MSM precompile at address 0x0e.
+pub fn bls12_g2_msm(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    let data = &call_info.data;
+    if data.is_empty() || !data.len().is_multiple_of(G2_MSM_PAIR_LENGTH) {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let k = data.len() / G2_MSM_PAIR_LENGTH;
+    gas_tracker.consume_gas(msm_gas(k, G2_MUL_GAS, &G2_MSM_DISCOUNT_TABLE))?;
+    let mut bases = Vec::with_capacity(k);
+    let mut scalars = Vec::with_capacity(k);
+    for pair in data.chunks(G2_MSM_PAIR_LENGTH) {
+        bases.push(read_g2(&pair[..G2_LENGTH], true)?);
+        scalars.push(read_scalar(&pair[G2_LENGTH..]));
+    }
+    let result = G2Projective::msm(&bases, &scalars).expect("bases and scalars have equal length");
+    Ok(ExecutionResult::Returned(write_g2(result.into_affine())))
+}
+
+/// EIP-2537 PAIRING_CHECK precompile at address 0x0f.
+///
+/// Returns 1 as a 32-byte word if the product of the pairings of all the given
+/// `(G1, G2)` pairs is the identity, and 0 otherwise.
+pub fn bls12_pairing_check(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    let data = &call_info.data;
+    if data.is_empty() || !data.len().is_multiple_of(PAIRING_PAIR_LENGTH) {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let k = data.len() / PAIRING_PAIR_LENGTH;
+    gas_tracker.consume_gas(PAIRING_BASE_GAS + k * PAIRING_PER_PAIR_GAS)?;
+    let mut g1_points = Vec::with_capacity(k);
+    let mut g2_points = Vec::with_capacity(k);
+    for pair in data.chunks(PAIRING_PAIR_LENGTH) {
+        g1_points.push(read_g1(&pair[..G1_LENGTH], true)?);
+        g2_points.push(read_g2(&pair[G1_LENGTH..], true)?);
+    }
+    let success = Bls12_381::multi_pairing(g1_points, g2_points).is_zero();
+    let mut out = vec![0u8; 32];
+    out[31] = success as u8;
+    Ok(ExecutionResult::Returned(out))
+}
+
+/// EIP-2537 MAP_FP_TO_G1 precompile at address 0x10.
+pub fn bls12_map_fp_to_g1(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    gas_tracker.consume_gas(MAP_FP_TO_G1_GAS)?;
+    let data = &call_info.data;
+    if data.len() != FP_LENGTH {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let fp = read_fp(data)?;
+    let point = WBMap::<g1::Config>::new()
+        .and_then(|map| map.map_to_curve(fp))
+        .map_err(|_| RevertError::InvalidPrecompileInput)?;
+    Ok(ExecutionResult::Returned(write_g1(point.clear_cofactor())))
+}
+
+/// EIP-2537 MAP_FP2_TO_G2 precompile at address 0x11.
+pub fn bls12_map_fp2_to_g2(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    gas_tracker.consume_gas(MAP_FP2_TO_G2_GAS)?;
+    let data = &call_info.data;
+    if data.len() != FP2_LENGTH {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let fp2 = read_fp2(data)?;
+    let point = WBMap::<g2::Config>::new()
+        .and_then(|map| map.map_to_curve(fp2))
+        .map_err(|_| RevertError::InvalidPrecompileInput)?;
+    Ok(ExecutionResult::Returned(write_g2(point.clear_cofactor())))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::primitives::hex;
+
+    use super::*;
+
+    type PrecompileFn = fn(&mut GasTracker, CallInfo<U256>) -> Result<ExecutionResult, ExecError>;
+
+    fn run(precompile: PrecompileFn, data: Vec<u8>) -> Result<ExecutionResult, ExecError> {
+        let mut gt = GasTracker::new(100000000);
+        precompile(
+            &mut gt,
+            CallInfo {
+                data,
+                ..Default::default()
+            },
+        )
+    }
+
+    // Vectors from the EIP-2537 reference test suite
+    fn check_vectors(precompile: PrecompileFn, vectors: &str) {
+        let vectors: Vec<serde_json::Value> = serde_json::from_str(vectors).unwrap();
+        assert!(!vectors.is_empty());
+        for vector in vectors {
+            let input = hex::decode(vector["Input"].as_str().unwrap()).unwrap();
+            let res = run(precompile, input);
+            if let Some(expected) = vector["Expected"].as_str() {
+                assert_eq!(
+                    res,
+                    Ok(ExecutionResult::Returned(hex::decode(expected).unwrap())),
+                    "{}",
+                    vector["Name"]
+                );
+            } else {
+                assert_eq!(
+                    res,
+                    Err(RevertError::InvalidPrecompileInput.into()),
+                    "{}",
+                    vector["Name"]
+                );
+            }
+        }
+    }
+
+    macro_rules! vectors {
+        ($name:literal) => {
+            include_str!(concat!("test_vectors/bls12_381/", $name, ".json"))
+        };
+    }
+
+    #[test]
+    fn test_bls12_g1_add() {
+        check_vectors(bls12_g1_add, vectors!("add_G1_bls"));
+        check_vectors(bls12_g1_add, vectors!("fail-add_G1_bls"));
+    }
+
+    #[test]
+    fn test_bls12_g2_add() {
+        check_vectors(bls12_g2_add, vectors!("add_G2_bls"));
+        check_vectors(bls12_g2_add, vectors!("fail-add_G2_bls"));
+    }
+
+    #[test]
+    fn test_bls12_g1_msm() {
+        check_vectors(bls12_g1_msm, vectors!("mul_G1_bls"));
+        check_vectors(bls12_g1_msm, vectors!("multiexp_G1_bls"));
+        check_vectors(bls12_g1_msm, vectors!("fail-multiexp_G1_bls"));
+    }
+
+    #[test]
+    fn test_bls12_g2_msm() {
+        check_vectors(bls12_g2_msm, vectors!("mul_G2_bls"));
+        check_vectors(bls12_g2_msm, vectors!("multiexp_G2_bls"));
+        check_vectors(bls12_g2_msm, vectors!("fail-multiexp_G2_bls"));
+    }
+
+    #[test]
+    fn test_bls12_pairing_check() {
+        check_vectors(bls12_pairing_check, vectors!("pairing_check_bls"));
+        check_vectors(bls12_pairing_check, vectors!("fail-pairing_check_bls"));
+    }
+
+    #[test]
+    fn test_bls12_map_fp_to_g1() {
+        check_vectors(bls12_map_fp_to_g1, vectors!("map_fp_to_G1_bls"));
+        check_vectors(bls12_map_fp_to_g1, vectors!("fail-map_fp_to_G1_bls"));
+    }
+
+    #[test]
+    fn test_bls12_map_fp2_to_g2() {
+        check_vectors(bls12_map_fp2_to_g2, vectors!("map_fp2_to_G2_bls"));
+        check_vectors(bls12_map_fp2_to_g2, vectors!("fail-map_fp2_to_G2_bls"));
+    }
+
+    #[test]
+    fn test_bls12_msm_gas() {
+        assert_eq!(msm_gas(1, G1_MUL_GAS, &G1_MSM_DISCOUNT_TABLE), 12000);
+        assert_eq!(msm_gas(2, G1_MUL_GAS, &G1_MSM_DISCOUNT_TABLE), 22776);
+        assert_eq!(msm_gas(128, G1_MUL_GAS, &G1_MSM_DISCOUNT_TABLE), 797184);
+        assert_eq!(msm_gas(200, G1_MUL_GAS, &G1_MSM_DISCOUNT_TABLE), 1245600);
+        assert_eq!(msm_gas(1, G2_MUL_GAS, &G2_MSM_DISCOUNT_TABLE), 22500);
+        assert_eq!(msm_gas(2, G2_MUL_GAS, &G2_MSM_DISCOUNT_TABLE), 45000);
+        assert_eq!(msm_gas(200, G2_MUL_GAS, &G2_MSM_DISCOUNT_TABLE), 2358000);
+
+        let mut gt = GasTracker::new(22775);
+        assert_eq!(
+            bls12_g1_msm(
+                &mut gt,
+                CallInfo {
+                    data: vec![0u8; 2 * G1_MSM_PAIR_LENGTH],
+                    ..Default::default()
+                }
+            ),
+            Err(RevertError::InsufficientGas.into())
+        );
+    }
+}
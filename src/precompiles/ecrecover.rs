@@ -0,0 +1,84 @@
+use crate::error::ExecError;
+use crate::machine::{CallInfo, GasTracker};
+use crate::opcodes::ExecutionResult;
+use crate::signature::{recover_address, Signature};
+use crate::u256::U256;
+
+const GAS_COST: usize = 3000;
+const INPUT_LENGTH: usize = 128;
+
+/// Secp256k1 public key recovery precompile at 0x01.
+///
+/// Input is `hash (32) | v (32) | r (32) | s (32)`, right-padded with zeros. Returns the
+/// signer address as a 32-byte word, or empty output if the signature is invalid.
+pub fn ecrecover(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    gas_tracker.consume_gas(GAS_COST)?;
+    let mut input = [0u8; INPUT_LENGTH];
+    let len = std::cmp::min(call_info.data.len(), INPUT_LENGTH);
+    input[..len].copy_from_slice(&call_info.data[..len]);
+
+    let v = U256::from_be_slice(&input[32..64]);
+    if v != U256::from(27) && v != U256::from(28) {
+        return Ok(ExecutionResult::Returned(vec![]));
+    }
+    let signature = Signature {
+        y_parity: v == U256::from(28),
+        r: U256::from_be_slice(&input[64..96]),
+        s: U256::from_be_slice(&input[96..128]),
+    };
+    let hash: [u8; 32] = input[..32].try_into().unwrap();
+    Ok(ExecutionResult::Returned(
+        match recover_address(&hash, &signature) {
+            Some(addr) => addr.into_word().to_vec(),
+            None => vec![],
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::primitives::hex;
+
+    use super::*;
+
+    fn run(input: &str) -> Result<ExecutionResult, ExecError> {
+        let mut gt = GasTracker::new(10000000);
+        let res = ecrecover(
+            &mut gt,
+            CallInfo {
+                data: hex::decode(input).unwrap(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(gt.gas_used, GAS_COST);
+        res
+    }
+
+    #[test]
+    fn test_ecrecover() {
+        // Signature of the EIP-155 example transaction, key 0x4646...46.
+        let hash = "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53";
+        let r = "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276";
+        let s = "67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+        let v27 = "000000000000000000000000000000000000000000000000000000000000001b";
+        assert_eq!(
+            run(&format!("{hash}{v27}{r}{s}")),
+            Ok(ExecutionResult::Returned(
+                hex!("0000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").to_vec()
+            ))
+        );
+        let v29 = "000000000000000000000000000000000000000000000000000000000000001d";
+        assert_eq!(
+            run(&format!("{hash}{v29}{r}{s}")),
+            Ok(ExecutionResult::Returned(vec![]))
+        );
+        assert_eq!(
+            run(&format!("{hash}{v27}{r}")),
+            Ok(ExecutionResult::Returned(vec![]))
+        );
+        assert_eq!(run(""), Ok(ExecutionResult::Returned(vec![])));
+    }
+}
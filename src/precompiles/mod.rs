@@ -0,0 +1,22 @@
+mod blake2f;
+mod bls12_381;
+mod ecrecover;
+mod p256_verify;
+mod point_evaluation;
+
+pub use blake2f::blake2f;
+pub use bls12_381::{
+    bls12_g1_add, bls12_g1_msm, bls12_g2_add, bls12_g2_msm, bls12_map_fp2_to_g2,
+    bls12_map_fp_to_g1, bls12_pairing_check,
+};
+pub use ecrecover::ecrecover;
+pub use p256_verify::p256_verify;
+pub use point_evaluation::{kzg_to_versioned_hash, point_evaluation};
+
+use crate::error::ExecError;
+use crate::machine::{CallInfo, GasTracker};
+use crate::opcodes::ExecutionResult;
+use crate::u256::U256;
+
+pub type Precompile =
+    &'static dyn Fn(&mut GasTracker, CallInfo<U256>) -> Result<ExecutionResult, ExecError>;
@@ -0,0 +1,157 @@
+use alloy_primitives::primitives::hex;
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G2Affine};
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup};
+use ark_ff::{BigInt, PrimeField, Zero};
+use ark_serialize::CanonicalDeserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{ExecError, RevertError};
+use crate::machine::{CallInfo, GasTracker};
+use crate::opcodes::ExecutionResult;
+use crate::u256::U256;
+
+const GAS_COST: usize = 50000;
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// `FIELD_ELEMENTS_PER_BLOB` followed by `BLS_MODULUS`, both as 32-byte words.
+const RETURN_VALUE: [u8; 64] = hex!(
+    "0000000000000000000000000000000000000000000000000000000000001000"
+    "73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001"
+);
+
+/// `[τ]G2` from the KZG ceremony trusted setup (second point of the G2 monomial
+/// setup), which is the only setup element needed to verify a single opening.
+const TRUSTED_SETUP_TAU_G2: [u8; 96] = hex!(
+    "b5bfd7dd8cdeb128843bc287230af38926187075cbfbefa81009a2ce615ac53d"
+    "2914e5870cb452d2afaaab24f3499f72185cbfee53492714734429b7b38608e2"
+    "3926c911cceceac9a36851477ba4c60b087041de621000edc98edada20c1def2"
+);
+
+pub fn kzg_to_versioned_hash(commitment: &[u8]) -> [u8; 32] {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    hash
+}
+
+fn read_g1(bytes: &[u8]) -> Result<G1Affine, RevertError> {
+    G1Affine::deserialize_compressed(bytes).map_err(|_| RevertError::InvalidPrecompileInput)
+}
+
+fn read_fr(bytes: &[u8]) -> Result<Fr, RevertError> {
+    Fr::from_bigint(BigInt(U256::from_be_slice(bytes).into_limbs()))
+        .ok_or(RevertError::InvalidPrecompileInput)
+}
+
+/// Checks that `p(z) == y` given a commitment to `p` and an opening proof, i.e.
+/// `e(commitment - [y]G1, G2) == e(proof, [τ]G2 - [z]G2)`.
+fn verify_kzg_proof(commitment: G1Affine, z: Fr, y: Fr, proof: G1Affine) -> bool {
+    let tau_g2 = G2Affine::deserialize_compressed(&TRUSTED_SETUP_TAU_G2[..])
+        .expect("trusted setup point is valid");
+    let p_minus_y = (commitment.into_group() - G1Affine::generator() * y).into_affine();
+    let x_minus_z = (tau_g2.into_group() - G2Affine::generator() * z).into_affine();
+    Bls12_381::multi_pairing([p_minus_y, proof], [-G2Affine::generator(), x_minus_z]).is_zero()
+}
+
+/// EIP-4844 point evaluation precompile at address 0x0a.
+///
+/// Input is `versioned_hash (32) | z (32) | y (32) | commitment (48) | proof (48)`.
+/// Verifies that the blob committed to by `commitment` evaluates to `y` at `z`.
+pub fn point_evaluation(
+    gas_tracker: &mut GasTracker,
+    call_info: CallInfo<U256>,
+) -> Result<ExecutionResult, ExecError> {
+    gas_tracker.consume_gas(GAS_COST)?;
+    let data = &call_info.data;
+    if data.len() != 192 {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let versioned_hash = &data[0..32];
+    let commitment = &data[96..144];
+    if kzg_to_versioned_hash(commitment) != versioned_hash {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    let z = read_fr(&data[32..64])?;
+    let y = read_fr(&data[64..96])?;
+    let commitment = read_g1(commitment)?;
+    let proof = read_g1(&data[144..192])?;
+    if !verify_kzg_proof(commitment, z, y, proof) {
+        return Err(RevertError::InvalidPrecompileInput.into());
+    }
+    Ok(ExecutionResult::Returned(RETURN_VALUE.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(z: &str, y: &str) -> Vec<u8> {
+        let commitment = hex!("8f59a8d2a1a625a17f3fea0fe5eb8c896db3764f3185481bc22f91b4aaffcca25f26936857bc3a7c2539ea8ec3a952b7");
+        let proof = hex!("a62ad71d14c5719385c0686f1871430475bf3a00f0aa3f7b8dd99a9abc2160744faf0070725e00b60ad9a026a15b1a8c");
+        [
+            kzg_to_versioned_hash(&commitment).to_vec(),
+            hex::decode(z).unwrap(),
+            hex::decode(y).unwrap(),
+            commitment.to_vec(),
+            proof.to_vec(),
+        ]
+        .concat()
+    }
+
+    fn run(data: Vec<u8>) -> (Result<ExecutionResult, ExecError>, usize) {
+        let mut gt = GasTracker::new(10000000);
+        let res = point_evaluation(
+            &mut gt,
+            CallInfo {
+                data,
+                ..Default::default()
+            },
+        );
+        (res, gt.gas_used)
+    }
+
+    const Z: &str = "73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000000";
+    const Y: &str = "1522a4a7f34e1ea350ae07c29c96c7e79655aa926122e95fe69fcbd932ca49e9";
+
+    #[test]
+    fn test_point_evaluation() {
+        assert_eq!(
+            run(input(Z, Y)),
+            (Ok(ExecutionResult::Returned(RETURN_VALUE.to_vec())), 50000)
+        );
+    }
+
+    #[test]
+    fn test_point_evaluation_invalid() {
+        // Wrong evaluation
+        let wrong_y = "1522a4a7f34e1ea350ae07c29c96c7e79655aa926122e95fe69fcbd932ca49ea";
+        assert_eq!(
+            run(input(Z, wrong_y)).0,
+            Err(RevertError::InvalidPrecompileInput.into())
+        );
+        // z is not a canonical field element
+        let z_overflow = "73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001";
+        assert_eq!(
+            run(input(z_overflow, Y)).0,
+            Err(RevertError::InvalidPrecompileInput.into())
+        );
+        // Versioned hash mismatch
+        let mut data = input(Z, Y);
+        data[0] = 0x02;
+        assert_eq!(run(data).0, Err(RevertError::InvalidPrecompileInput.into()));
+        // Wrong length
+        let mut data = input(Z, Y);
+        data.pop();
+        assert_eq!(run(data).0, Err(RevertError::InvalidPrecompileInput.into()));
+        let mut gt = GasTracker::new(49999);
+        assert_eq!(
+            point_evaluation(
+                &mut gt,
+                CallInfo {
+                    data: input(Z, Y),
+                    ..Default::default()
+                }
+            ),
+            Err(RevertError::InsufficientGas.into())
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use alloy_primitives::primitives::Address;
+
+use crate::{
+    context::MiniEthereum,
+    error::ProofError,
+    keccak::keccak,
+    state_trie::{code_hash, encode_account, encode_slot, storage_key, EMPTY_CODE_HASH},
+    trie::{verify_proof, EMPTY_ROOT},
+    u256::U256,
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageProof {
+    pub key: U256,
+    pub value: U256,
+    pub proof: Vec<Vec<u8>>,
+}
+
+/// Account and storage proofs in the shape of an `eth_getProof` response. Absent accounts
+/// are reported with a zero nonce and balance, the empty code hash and the empty root.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountProof {
+    pub address: Address,
+    pub nonce: U256,
+    pub balance: U256,
+    pub code_hash: [u8; 32],
+    pub storage_hash: [u8; 32],
+    pub account_proof: Vec<Vec<u8>>,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+impl AccountProof {
+    fn is_empty(&self) -> bool {
+        self.nonce.is_zero() && self.balance.is_zero() && self.code_hash == EMPTY_CODE_HASH
+    }
+
+    /// Checks the account fields against `state_root` and each slot against the storage
+    /// hash.
+    pub fn verify(&self, state_root: &[u8; 32]) -> Result<(), ProofError> {
+        let expected = (!self.is_empty()).then(|| {
+            encode_account(
+                &self.nonce,
+                &self.balance,
+                &self.storage_hash,
+                &self.code_hash,
+            )
+        });
+        let key = keccak(self.address.as_slice());
+        if verify_proof(state_root, &key, &self.account_proof)? != expected {
+            return Err(ProofError::ValueMismatch);
+        }
+        for slot in &self.storage_proof {
+            let proven = verify_proof(&self.storage_hash, &storage_key(&slot.key), &slot.proof)?;
+            if proven != encode_slot(&slot.value) {
+                return Err(ProofError::ValueMismatch);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MiniEthereum {
+    /// Proves an account and some of its storage slots against `state_root()`.
+    pub fn get_proof(&mut self, address: Address, slots: &[U256]) -> AccountProof {
+        self.update_state_trie();
+        let account = self
+            .accounts
+            .get(&address)
+            .filter(|account| !account.is_empty())
+            .cloned()
+            .unwrap_or_default();
+        let storage_hash = if account.is_empty() {
+            EMPTY_ROOT
+        } else {
+            self.state_trie.storage_root(address)
+        };
+        let storage_proof = slots
+            .iter()
+            .map(|slot| StorageProof {
+                key: *slot,
+                value: account.storage.get(slot).copied().unwrap_or_default(),
+                proof: self.state_trie.storage_proof(address, slot),
+            })
+            .collect();
+        AccountProof {
+            address,
+            nonce: account.nonce,
+            balance: account.value,
+            code_hash: code_hash(&account.code),
+            storage_hash,
+            account_proof: self.state_trie.account_proof(address),
+            storage_proof,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Account;
+    use alloy_primitives::primitives::hex;
+
+    fn state() -> MiniEthereum {
+        let mut ctx = MiniEthereum::new();
+        ctx.replace_account(
+            Address::with_last_byte(1),
+            Account {
+                nonce: U256::ONE,
+                value: U256::from(10).pow(U256::from(18)),
+                ..Default::default()
+            },
+        );
+        ctx.replace_account(
+            Address::with_last_byte(0x42),
+            Account {
+                nonce: U256::ONE,
+                code: vec![0x60, 0x2a, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xa0, 0x00],
+                storage: [
+                    (U256::ZERO, U256::ONE),
+                    (U256::ONE, U256::from(0x2a)),
+                    (U256::from(0xdeadbeefu64), U256::ONE << 255),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            },
+        );
+        ctx.fund(Address::with_last_byte(0xaa), U256::from(5));
+        ctx
+    }
+
+    #[test]
+    fn test_get_proof() {
+        let mut ctx = state();
+        let root = ctx.state_root();
+        let proof = ctx.get_proof(Address::with_last_byte(0x42), &[U256::ONE, U256::from(2)]);
+        // Same nodes as returned by other clients for this state.
+        assert_eq!(
+            proof.account_proof,
+            vec![
+                hex!("f87180a07759dbc485a2c62b9ffab3181389238c306d2748d9bcead3c32d8928f4820152808080a0217969fc9779bb95841c9bdb7fa7b8cf8585824f7cf742fc5ca3b7d38273b87b808080808080a06978063a0f336f31436eb999be748ee39b73d03d9d03ec3e21f504a77633f28c80808080").to_vec(),
+                hex!("f869a0300c28a6bfe2829613432a2c21eefc25da5bad983c5430ca28bc1a92d2b65ec5b846f8440180a041277def93aa6e921a92435098b0d64905a54fd8d740b842a490f1c5bb314bd4a0591627607d7635161387cb1742e9a02c45816f5f148559fd93e3c92597295b53").to_vec(),
+            ]
+        );
+        assert_eq!(
+            proof.storage_proof[0].proof,
+            vec![
+                hex!("f8518080a0e54e4cc2c4ab469676d1d0cd2f7ecf342831bb030c6a9796645f6c8ef1a081358080808080808080a0e4449cb51d628e6e071cbba31d760efcf09715ce230ac380c7a239722c0f22118080808080").to_vec(),
+                hex!("e2a0310e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf62a").to_vec(),
+            ]
+        );
+        assert_eq!(proof.storage_proof[0].value, U256::from(0x2a));
+        assert_eq!(proof.storage_proof[1].value, U256::ZERO);
+        assert_eq!(proof.verify(&root), Ok(()));
+
+        let mut forged = proof.clone();
+        forged.storage_proof[0].value = U256::from(0x2b);
+        assert_eq!(forged.verify(&root), Err(ProofError::ValueMismatch));
+        let mut forged = proof.clone();
+        forged.balance = U256::from(1);
+        assert_eq!(forged.verify(&root), Err(ProofError::ValueMismatch));
+        let mut forged = proof.clone();
+        forged.storage_proof[1].value = U256::ONE;
+        assert_eq!(forged.verify(&root), Err(ProofError::ValueMismatch));
+    }
+
+    #[test]
+    fn test_absent_account_proof() {
+        let mut ctx = state();
+        let root = ctx.state_root();
+        let proof = ctx.get_proof(Address::with_last_byte(0x77), &[U256::ZERO]);
+        assert_eq!(proof.code_hash, EMPTY_CODE_HASH);
+        assert_eq!(proof.storage_hash, EMPTY_ROOT);
+        assert!(proof.storage_proof[0].proof.is_empty());
+        assert_eq!(proof.verify(&root), Ok(()));
+
+        let mut forged = proof.clone();
+        forged.balance = U256::from(1);
+        assert_eq!(forged.verify(&root), Err(ProofError::ValueMismatch));
+
+        // Proofs follow the state as it changes.
+        ctx.fund(Address::with_last_byte(0x77), U256::from(3));
+        let root = ctx.state_root();
+        assert_eq!(proof.verify(&root), Err(ProofError::HashMismatch));
+        let proof = ctx.get_proof(Address::with_last_byte(0x77), &[]);
+        assert_eq!(proof.balance, U256::from(3));
+        assert_eq!(proof.verify(&root), Ok(()));
+    }
+}
@@ -0,0 +1,429 @@
+use alloy_primitives::primitives::Address;
+
+use crate::{error::RlpError, u256::U256};
+
+pub trait Encodable {
+    fn rlp_append(&self, out: &mut Vec<u8>);
+}
+
+pub trait Decodable: Sized {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError>;
+}
+
+pub fn encode<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    value.rlp_append(&mut out);
+    out
+}
+
+/// Decodes a single value, rejecting any trailing bytes.
+pub fn decode<T: Decodable>(data: &[u8]) -> Result<T, RlpError> {
+    let mut rlp = Rlp::new(data);
+    let value = T::rlp_decode(&mut rlp)?;
+    rlp.finish()?;
+    Ok(value)
+}
+
+fn be_bytes_trimmed(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    bytes[start..].to_vec()
+}
+
+fn encode_header(out: &mut Vec<u8>, offset: u8, len: usize) {
+    if len <= 55 {
+        out.push(offset + len as u8);
+    } else {
+        let len_bytes = be_bytes_trimmed(len as u64);
+        out.push(offset + 55 + len_bytes.len() as u8);
+        out.extend(len_bytes);
+    }
+}
+
+pub fn encode_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        encode_header(out, 0x80, bytes.len());
+        out.extend(bytes);
+    }
+}
+
+/// Wraps an already encoded list payload with its header.
+pub fn encode_list_payload(out: &mut Vec<u8>, payload: &[u8]) {
+    encode_header(out, 0xc0, payload.len());
+    out.extend(payload);
+}
+
+pub fn encode_list<T: Encodable>(out: &mut Vec<u8>, items: &[T]) {
+    let mut payload = Vec::new();
+    for item in items {
+        item.rlp_append(&mut payload);
+    }
+    encode_list_payload(out, &payload);
+}
+
+/// Hashes and storage keys are fixed 32-byte strings rather than integers.
+pub fn encode_hash(out: &mut Vec<u8>, hash: &U256) {
+    encode_bytes(out, &hash.to_be_bytes::<32>());
+}
+
+pub fn encode_hash_list(out: &mut Vec<u8>, hashes: &[U256]) {
+    let mut payload = Vec::new();
+    for hash in hashes {
+        encode_hash(&mut payload, hash);
+    }
+    encode_list_payload(out, &payload);
+}
+
+impl Encodable for [u8] {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        encode_bytes(out, self);
+    }
+}
+
+impl Encodable for Vec<u8> {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        encode_bytes(out, self);
+    }
+}
+
+impl Encodable for u64 {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        encode_bytes(out, &be_bytes_trimmed(*self));
+    }
+}
+
+impl Encodable for usize {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        (*self as u64).rlp_append(out);
+    }
+}
+
+impl Encodable for u8 {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        (*self as u64).rlp_append(out);
+    }
+}
+
+impl Encodable for bool {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        (*self as u64).rlp_append(out);
+    }
+}
+
+impl Encodable for U256 {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        let bytes = self.to_be_bytes::<32>();
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+        encode_bytes(out, &bytes[start..]);
+    }
+}
+
+impl Encodable for Address {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        encode_bytes(out, self.as_slice());
+    }
+}
+
+/// An absent address, e.g. the recipient of a contract creation, is the empty string.
+impl Encodable for Option<Address> {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(addr) => addr.rlp_append(out),
+            None => encode_bytes(out, &[]),
+        }
+    }
+}
+
+/// Cursor over a sequence of RLP items.
+#[derive(Debug, Clone, Copy)]
+pub struct Rlp<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Rlp<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// Fails if there are items left to be read.
+    pub fn finish(&self) -> Result<(), RlpError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(RlpError::TrailingBytes)
+        }
+    }
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RlpError> {
+        if self.data.len() < len {
+            return Err(RlpError::UnexpectedEof);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+    fn long_length(&mut self, len_of_len: usize) -> Result<usize, RlpError> {
+        let len_bytes = self.take(len_of_len)?;
+        if len_bytes[0] == 0 {
+            return Err(RlpError::NonCanonicalSize);
+        }
+        if len_of_len > 8 {
+            return Err(RlpError::Overflow);
+        }
+        let len = len_bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64) as usize;
+        if len <= 55 {
+            return Err(RlpError::NonCanonicalSize);
+        }
+        Ok(len)
+    }
+    /// Reads the header of the next item, returning whether it is a list and its payload.
+    fn next_item(&mut self) -> Result<(bool, &'a [u8]), RlpError> {
+        let prefix = *self.data.first().ok_or(RlpError::UnexpectedEof)?;
+        if prefix < 0x80 {
+            return Ok((false, self.take(1)?));
+        }
+        self.data = &self.data[1..];
+        match prefix {
+            0x80..=0xb7 => {
+                let payload = self.take((prefix - 0x80) as usize)?;
+                if payload.len() == 1 && payload[0] < 0x80 {
+                    return Err(RlpError::NonCanonicalSingleByte);
+                }
+                Ok((false, payload))
+            }
+            0xb8..=0xbf => {
+                let len = self.long_length((prefix - 0xb7) as usize)?;
+                Ok((false, self.take(len)?))
+            }
+            0xc0..=0xf7 => Ok((true, self.take((prefix - 0xc0) as usize)?)),
+            _ => {
+                let len = self.long_length((prefix - 0xf7) as usize)?;
+                Ok((true, self.take(len)?))
+            }
+        }
+    }
+    /// Returns true if the next item is a list.
+    pub fn is_list(&self) -> Result<bool, RlpError> {
+        let prefix = *self.data.first().ok_or(RlpError::UnexpectedEof)?;
+        Ok(prefix >= 0xc0)
+    }
+    /// Reads the next item as a byte string.
+    pub fn bytes(&mut self) -> Result<&'a [u8], RlpError> {
+        match self.next_item()? {
+            (false, payload) => Ok(payload),
+            (true, _) => Err(RlpError::UnexpectedList),
+        }
+    }
+    /// Reads the next item as a list, returning a cursor over its elements.
+    pub fn list(&mut self) -> Result<Rlp<'a>, RlpError> {
+        match self.next_item()? {
+            (true, payload) => Ok(Rlp::new(payload)),
+            (false, _) => Err(RlpError::UnexpectedString),
+        }
+    }
+    /// Reads the next item without interpreting it, header included.
+    pub fn raw(&mut self) -> Result<&'a [u8], RlpError> {
+        let start = self.data;
+        self.next_item()?;
+        Ok(&start[..start.len() - self.data.len()])
+    }
+    pub fn decode<T: Decodable>(&mut self) -> Result<T, RlpError> {
+        T::rlp_decode(self)
+    }
+    /// Reads a list whose elements all have the same type.
+    pub fn decode_list<T: Decodable>(&mut self) -> Result<Vec<T>, RlpError> {
+        let mut list = self.list()?;
+        let mut items = Vec::new();
+        while !list.is_empty() {
+            items.push(list.decode()?);
+        }
+        Ok(items)
+    }
+    /// Reads a 32-byte hash.
+    pub fn hash(&mut self) -> Result<U256, RlpError> {
+        let bytes = self.bytes()?;
+        if bytes.len() != 32 {
+            return Err(RlpError::InvalidLength);
+        }
+        Ok(U256::from_be_slice(bytes))
+    }
+    pub fn hash_list(&mut self) -> Result<Vec<U256>, RlpError> {
+        let mut list = self.list()?;
+        let mut hashes = Vec::new();
+        while !list.is_empty() {
+            hashes.push(list.hash()?);
+        }
+        Ok(hashes)
+    }
+    /// Reads a canonical big-endian integer of at most `max_len` bytes.
+    fn uint(&mut self, max_len: usize) -> Result<&'a [u8], RlpError> {
+        let bytes = self.bytes()?;
+        if bytes.first() == Some(&0) {
+            return Err(RlpError::LeadingZero);
+        }
+        if bytes.len() > max_len {
+            return Err(RlpError::Overflow);
+        }
+        Ok(bytes)
+    }
+}
+
+impl Decodable for Vec<u8> {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        Ok(rlp.bytes()?.to_vec())
+    }
+}
+
+impl Decodable for u64 {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        Ok(rlp
+            .uint(8)?
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+}
+
+impl Decodable for usize {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        usize::try_from(u64::rlp_decode(rlp)?).map_err(|_| RlpError::Overflow)
+    }
+}
+
+impl Decodable for u8 {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        Ok(rlp.uint(1)?.first().copied().unwrap_or_default())
+    }
+}
+
+impl Decodable for bool {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        match u8::rlp_decode(rlp)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(RlpError::Overflow),
+        }
+    }
+}
+
+impl Decodable for U256 {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        Ok(U256::from_be_slice(rlp.uint(32)?))
+    }
+}
+
+impl Decodable for Address {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        let bytes = rlp.bytes()?;
+        if bytes.len() != 20 {
+            return Err(RlpError::InvalidLength);
+        }
+        Ok(Address::from_slice(bytes))
+    }
+}
+
+impl Decodable for Option<Address> {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        let mut peek = *rlp;
+        if peek.bytes()?.is_empty() {
+            *rlp = peek;
+            Ok(None)
+        } else {
+            Ok(Some(rlp.decode()?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::primitives::hex;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(&b"dog".to_vec()), hex!("83646f67"));
+        assert_eq!(encode(&Vec::<u8>::new()), hex!("80"));
+        assert_eq!(encode(&vec![0x0fu8]), hex!("0f"));
+        assert_eq!(encode(&vec![0x80u8]), hex!("8180"));
+        assert_eq!(encode(&0u64), hex!("80"));
+        assert_eq!(encode(&1024u64), hex!("820400"));
+        assert_eq!(encode(&U256::from(0x7f)), hex!("7f"));
+        assert_eq!(encode(&U256::MAX)[..2], hex!("a0ff"));
+        let mut out = Vec::new();
+        encode_list(&mut out, &[b"cat".to_vec(), b"dog".to_vec()]);
+        assert_eq!(out, hex!("c88363617483646f67"));
+        let mut out = Vec::new();
+        encode_list::<u64>(&mut out, &[]);
+        assert_eq!(out, hex!("c0"));
+        let long = vec![b'a'; 56];
+        assert_eq!(encode(&long)[..2], hex!("b838"));
+        let mut out = Vec::new();
+        encode_list(&mut out, &[long]);
+        assert_eq!(out[..3], hex!("f83ab8"));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for v in [0u64, 1, 0x7f, 0x80, 0xff, 0x100, u64::MAX] {
+            assert_eq!(decode::<u64>(&encode(&v)), Ok(v));
+        }
+        for v in [U256::ZERO, U256::from(1024), U256::MAX] {
+            assert_eq!(decode::<U256>(&encode(&v)), Ok(v));
+        }
+        let addr = Address::with_last_byte(0x42);
+        assert_eq!(decode::<Address>(&encode(&addr)), Ok(addr));
+        assert_eq!(
+            decode::<Option<Address>>(&encode(&Some(addr))),
+            Ok(Some(addr))
+        );
+        assert_eq!(
+            decode::<Option<Address>>(&encode(&None::<Address>)),
+            Ok(None)
+        );
+        let mut out = Vec::new();
+        encode_hash_list(&mut out, &[U256::ZERO, U256::MAX]);
+        assert_eq!(out[..3], hex!("f842a0"));
+        assert_eq!(Rlp::new(&out).hash_list(), Ok(vec![U256::ZERO, U256::MAX]));
+        assert_eq!(Rlp::new(&hex!("80")).hash(), Err(RlpError::InvalidLength));
+        let data = vec![7u8; 1000];
+        assert_eq!(decode::<Vec<u8>>(&encode(&data)), Ok(data));
+
+        let mut out = Vec::new();
+        encode_list(&mut out, &[1u64, 2, 3]);
+        let mut rlp = Rlp::new(&out);
+        assert_eq!(rlp.decode_list::<u64>(), Ok(vec![1, 2, 3]));
+        assert!(rlp.is_empty());
+    }
+
+    #[test]
+    fn test_non_canonical() {
+        for (input, err) in [
+            (&hex!("8100")[..], RlpError::NonCanonicalSingleByte),
+            (&hex!("817f")[..], RlpError::NonCanonicalSingleByte),
+            (&hex!("b80161")[..], RlpError::NonCanonicalSize),
+            (&hex!("b90000")[..], RlpError::NonCanonicalSize),
+            (&hex!("83646f")[..], RlpError::UnexpectedEof),
+            (&hex!("")[..], RlpError::UnexpectedEof),
+            (&hex!("83646f6767")[..], RlpError::TrailingBytes),
+        ] {
+            assert_eq!(decode::<Vec<u8>>(input), Err(err));
+        }
+        assert_eq!(decode::<u64>(&hex!("820001")), Err(RlpError::LeadingZero));
+        assert_eq!(decode::<u64>(&hex!("00")), Err(RlpError::LeadingZero));
+        assert_eq!(
+            decode::<u64>(&hex!("89010000000000000000")),
+            Err(RlpError::Overflow)
+        );
+        assert_eq!(decode::<u64>(&hex!("c0")), Err(RlpError::UnexpectedList));
+        assert_eq!(
+            Rlp::new(&hex!("80")).list().unwrap_err(),
+            RlpError::UnexpectedString
+        );
+        assert_eq!(
+            decode::<Address>(&hex!("820001")),
+            Err(RlpError::InvalidLength)
+        );
+    }
+}
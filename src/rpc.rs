@@ -0,0 +1,305 @@
+use std::{
+    cell::Cell,
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::error::RpcError;
+
+/// Minimal JSON-RPC client over HTTP/1.1, one connection per request.
+#[derive(Debug)]
+pub struct RpcClient {
+    /// `host:port` to connect to.
+    authority: String,
+    path: String,
+    timeout: Duration,
+    next_id: Cell<u64>,
+}
+
+/// Body of an HTTP/1.1 response, whose connection has been closed by the server.
+fn response_body(response: &[u8]) -> Result<Vec<u8>, RpcError> {
+    let invalid = |reason: &str| RpcError::InvalidResponse(reason.to_string());
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("missing header terminator"))?;
+    let head = std::str::from_utf8(&response[..split]).map_err(|_| invalid("invalid header"))?;
+    let body = &response[split + 4..];
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+    if status != 200 {
+        return Err(RpcError::Http(status));
+    }
+    let mut chunked = false;
+    let mut length = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else if name.eq_ignore_ascii_case("content-length") {
+            length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| invalid("invalid content length"))?,
+            );
+        }
+    }
+    if chunked {
+        return decode_chunked(body);
+    }
+    match length {
+        Some(length) => body
+            .get(..length)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| invalid("truncated body")),
+        None => Ok(body.to_vec()),
+    }
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, RpcError> {
+    let invalid = || RpcError::InvalidResponse("invalid chunked body".to_string());
+    let mut out = Vec::new();
+    loop {
+        let end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(invalid)?;
+        let size = std::str::from_utf8(&body[..end])
+            .ok()
+            .map(|line| line.split(';').next().unwrap().trim())
+            .and_then(|size| usize::from_str_radix(size, 16).ok())
+            .ok_or_else(invalid)?;
+        body = &body[end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        out.extend(body.get(..size).ok_or_else(invalid)?);
+        body = body.get(size + 2..).ok_or_else(invalid)?;
+    }
+}
+
+impl RpcClient {
+    /// Client for an `http://host[:port][/path]` endpoint.
+    pub fn new(url: &str) -> Result<Self, RpcError> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| RpcError::UnsupportedUrl(url.to_string()))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            return Err(RpcError::UnsupportedUrl(url.to_string()));
+        }
+        let authority = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+        Ok(Self {
+            authority,
+            path: path.to_string(),
+            timeout: Duration::from_secs(30),
+            next_id: Cell::new(1),
+        })
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn post(&self, body: &[u8]) -> Result<Vec<u8>, RpcError> {
+        let mut stream = TcpStream::connect(&self.authority)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let host = self
+            .authority
+            .strip_suffix(":80")
+            .unwrap_or(&self.authority);
+        let head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            host,
+            body.len()
+        );
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        response_body(&response)
+    }
+
+    /// Sends a request and returns its `result`.
+    pub fn request(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        let body = self.post(request.to_string().as_bytes())?;
+        let mut response: Value =
+            serde_json::from_slice(&body).map_err(|e| RpcError::InvalidResponse(e.to_string()))?;
+        if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+            return Err(RpcError::Rpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        match response.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err(RpcError::InvalidResponse("missing result".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{
+        io::BufRead,
+        io::BufReader,
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    pub(crate) type Handler = dyn Fn(&str, &Value) -> Result<Value, (i64, String)> + Send + Sync;
+
+    /// Local JSON-RPC server answering with `handler` until the test process exits.
+    /// Returns its URL and the number of requests it has served.
+    pub(crate) fn serve(handler: Box<Handler>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rpc", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let served = count.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                served.fetch_add(1, Ordering::SeqCst);
+                let response =
+                    match handler(request["method"].as_str().unwrap(), &request["params"]) {
+                        Ok(result) => {
+                            json!({"jsonrpc": "2.0", "id": request["id"], "result": result})
+                        }
+                        Err((code, message)) => json!({
+                            "jsonrpc": "2.0",
+                            "id": request["id"],
+                            "error": {"code": code, "message": message},
+                        }),
+                    }
+                    .to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+            }
+        });
+        (url, count)
+    }
+
+    #[test]
+    fn test_rpc_request() {
+        let (url, count) = serve(Box::new(|method, params| match method {
+            "eth_chainId" => Ok(json!("0x1")),
+            "echo" => Ok(params.clone()),
+            _ => Err((-32601, "method not found".to_string())),
+        }));
+        let client = RpcClient::new(&url).unwrap();
+        assert_eq!(client.request("eth_chainId", json!([])), Ok(json!("0x1")));
+        assert_eq!(
+            client.request("echo", json!(["0x2a", true])),
+            Ok(json!(["0x2a", true]))
+        );
+        assert_eq!(
+            client.request("eth_foo", json!([])),
+            Err(RpcError::Rpc {
+                code: -32601,
+                message: "method not found".to_string()
+            })
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_rpc_url() {
+        assert!(matches!(
+            RpcClient::new("https://example.com"),
+            Err(RpcError::UnsupportedUrl(_))
+        ));
+        assert!(matches!(
+            RpcClient::new("http://"),
+            Err(RpcError::UnsupportedUrl(_))
+        ));
+        let client = RpcClient::new("http://localhost").unwrap();
+        assert_eq!(
+            (client.authority.as_str(), client.path.as_str()),
+            ("localhost:80", "/")
+        );
+        let client = RpcClient::new("http://127.0.0.1:8545/v1/key").unwrap();
+        assert_eq!(
+            (client.authority.as_str(), client.path.as_str()),
+            ("127.0.0.1:8545", "/v1/key")
+        );
+    }
+
+    #[test]
+    fn test_response_body() {
+        assert_eq!(
+            response_body(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}"),
+            Ok(b"{}".to_vec())
+        );
+        assert_eq!(
+            response_body(
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3;x=y\r\n:1}\r\n0\r\n\r\n"
+            ),
+            Ok(b"{\"a\":1}".to_vec())
+        );
+        assert_eq!(
+            response_body(b"HTTP/1.1 429 Too Many Requests\r\n\r\n"),
+            Err(RpcError::Http(429))
+        );
+        assert!(response_body(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n{}").is_err());
+        assert!(response_body(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n9\r\n{}\r\n0\r\n\r\n"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use alloy_primitives::primitives::Address;
+use k256::ecdsa::{RecoveryId, Signature as EcdsaSignature, VerifyingKey};
+
+use crate::{keccak::keccak, u256::U256};
+
+/// Half of the secp256k1 group order, signatures with a larger `s` are malleable (EIP-2).
+pub const SECP256K1N_HALF: U256 = U256::from_limbs([
+    0xdfe92f46681b20a0,
+    0x5d576e7357a4501d,
+    0xffffffffffffffff,
+    0x7fffffffffffffff,
+]);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Signature {
+    pub y_parity: bool,
+    pub r: U256,
+    pub s: U256,
+}
+
+impl Signature {
+    pub fn is_low_s(&self) -> bool {
+        self.s <= SECP256K1N_HALF
+    }
+}
+
+/// Ethereum address of an uncompressed public key.
+pub fn public_key_to_address(key: &VerifyingKey) -> Address {
+    let point = key.to_encoded_point(false);
+    Address::from_slice(&keccak(&point.as_bytes()[1..])[12..32])
+}
+
+/// Recovers the address that signed `hash`, `None` if the signature is invalid. High `s`
+/// values are accepted, callers enforcing EIP-2 check `Signature::is_low_s`.
+pub fn recover_address(hash: &[u8; 32], signature: &Signature) -> Option<Address> {
+    let sig = EcdsaSignature::from_scalars(
+        signature.r.to_be_bytes::<32>(),
+        signature.s.to_be_bytes::<32>(),
+    )
+    .ok()?;
+    // A high `s` recovers the same key as its low counterpart with the parity flipped.
+    let (sig, y_parity) = match sig.normalize_s() {
+        Some(normalized) => (normalized, !signature.y_parity),
+        None => (sig, signature.y_parity),
+    };
+    let recovery_id = RecoveryId::new(y_parity, false);
+    let key = VerifyingKey::recover_from_prehash(hash, &sig, recovery_id).ok()?;
+    Some(public_key_to_address(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::primitives::{address, hex};
+
+    #[test]
+    fn test_recover_address() {
+        // EIP-155 example transaction, signed with the key 0x4646...46.
+        let hash = hex!("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53");
+        let signature = Signature {
+            y_parity: false,
+            r: U256::from_be_bytes(hex!(
+                "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276"
+            )),
+            s: U256::from_be_bytes(hex!(
+                "67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+            )),
+        };
+        assert!(signature.is_low_s());
+        assert_eq!(
+            recover_address(&hash, &signature),
+            Some(address!("9d8A62f656a8d1615C1294fd71e9CFb3E4855A4F"))
+        );
+        assert_ne!(
+            recover_address(
+                &hash,
+                &Signature {
+                    y_parity: true,
+                    ..signature
+                }
+            ),
+            Some(address!("9d8A62f656a8d1615C1294fd71e9CFb3E4855A4F"))
+        );
+        assert_eq!(
+            recover_address(
+                &hash,
+                &Signature {
+                    r: U256::ZERO,
+                    ..signature
+                }
+            ),
+            None
+        );
+        let secp256k1n = SECP256K1N_HALF * U256::from(2) + U256::ONE;
+        assert_eq!(
+            recover_address(
+                &hash,
+                &Signature {
+                    y_parity: true,
+                    s: secp256k1n - signature.s,
+                    ..signature
+                }
+            ),
+            Some(address!("9d8A62f656a8d1615C1294fd71e9CFb3E4855A4F"))
+        );
+    }
+}
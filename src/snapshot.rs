@@ -0,0 +1,422 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use alloy_primitives::primitives::Address;
+
+use crate::{
+    context::{Account, MiniEthereum},
+    diff::{AccountState, StateDiff},
+    env::{BlockEnv, TxEnv},
+    u256::U256,
+};
+
+/// Values overwritten since a snapshot was taken, recorded on first write only.
+#[derive(Clone, Default)]
+pub(crate) struct Journal {
+    id: usize,
+    /// Nonce, balance and code of each modified account, `None` if it did not exist.
+    accounts: HashMap<Address, Option<(U256, U256, Vec<u8>)>>,
+    /// Previous value of each modified slot, `None` if it was not set.
+    slots: HashMap<(Address, U256), Option<U256>>,
+    /// Accounts first touched since the snapshot.
+    touched: Vec<Address>,
+    /// Previous value of each modified transient slot, `None` if it was not set.
+    transient: HashMap<U256, Option<U256>>,
+    /// Environment and block hashes before their first change, `None` if unchanged.
+    block: Option<BlockEnv>,
+    tx: Option<TxEnv>,
+    block_hashes: Option<VecDeque<(U256, U256)>>,
+}
+
+impl Journal {
+    /// Keeps the older records of `self` over the ones of `newer`.
+    fn merge(&mut self, newer: Journal) {
+        for (address, account) in newer.accounts {
+            self.accounts.entry(address).or_insert(account);
+        }
+        for (slot, value) in newer.slots {
+            self.slots.entry(slot).or_insert(value);
+        }
+        self.touched.extend(newer.touched);
+        for (slot, value) in newer.transient {
+            self.transient.entry(slot).or_insert(value);
+        }
+        self.block = self.block.take().or(newer.block);
+        self.tx = self.tx.take().or(newer.tx);
+        self.block_hashes = self.block_hashes.take().or(newer.block_hashes);
+    }
+}
+
+impl MiniEthereum {
+    /// Records the state so that it can be restored with `revert_to`. Snapshots nest, and
+    /// taking one copies nothing: afterwards every write keeps the value it replaces, so
+    /// reverting is proportional to what changed in between.
+    ///
+    /// Only the writes made through the context, transactions, blocks and `fund` are
+    /// recorded, not the ones made directly to the public fields.
+    pub fn snapshot(&mut self) -> usize {
+        self.next_snapshot += 1;
+        self.journals.push(Journal {
+            id: self.next_snapshot,
+            ..Default::default()
+        });
+        self.next_snapshot
+    }
+
+    /// Restores the state of snapshot `id`, which is consumed along with the snapshots
+    /// taken after it. Returns false if there is no such snapshot.
+    pub fn revert_to(&mut self, id: usize) -> bool {
+        let Some(pos) = self.journals.iter().position(|j| j.id == id) else {
+            return false;
+        };
+        for journal in self.journals.drain(pos..).rev().collect::<Vec<_>>() {
+            let mut created = Vec::new();
+            for (address, account) in journal.accounts {
+                self.dirty.entry(address).or_default();
+                match account {
+                    Some((nonce, value, code)) => {
+                        let account = self.accounts.entry(address).or_default();
+                        account.nonce = nonce;
+                        account.value = value;
+                        account.code = code;
+                    }
+                    None => created.push(address),
+                }
+            }
+            // Every written slot has its account recorded in the same journal.
+            for ((address, slot), value) in journal.slots {
+                self.dirty.entry(address).or_default().insert(slot);
+                if let Some(account) = self.accounts.get_mut(&address) {
+                    match value {
+                        Some(value) => account.storage.insert(slot, value),
+                        None => account.storage.remove(&slot),
+                    };
+                }
+            }
+            for address in created {
+                self.accounts.remove(&address);
+            }
+            for address in journal.touched {
+                self.touched.remove(&address);
+            }
+            for (slot, value) in journal.transient {
+                match value {
+                    Some(value) => self.transient.insert(slot, value),
+                    None => self.transient.remove(&slot),
+                };
+            }
+            if let Some(block) = journal.block {
+                self.block = block;
+            }
+            if let Some(tx) = journal.tx {
+                self.tx = tx;
+            }
+            if let Some(block_hashes) = journal.block_hashes {
+                self.block_hashes = block_hashes;
+            }
+        }
+        true
+    }
+
+    /// Forgets snapshot `id` and the ones taken after it, keeping the current state.
+    /// Earlier snapshots can still be reverted to.
+    pub fn discard_snapshot(&mut self, id: usize) -> bool {
+        let Some(pos) = self.journals.iter().position(|j| j.id == id) else {
+            return false;
+        };
+        let discarded: Vec<Journal> = self.journals.drain(pos..).collect();
+        if let Some(parent) = self.journals.last_mut() {
+            for journal in discarded {
+                parent.merge(journal);
+            }
+        }
+        true
+    }
+
+    /// Changes made since snapshot `id`, which stays open. Only the accounts and slots
+    /// written in between are part of the diff, read from the journal. Returns `None` if
+    /// there is no such snapshot.
+    ///
+    /// The changes are the ones `Overlay::state_diff` reports for the same execution, but
+    /// the prestate leaves out the accounts and slots that were only read, which the
+    /// journal does not record.
+    pub fn state_diff(&self, id: usize) -> Option<StateDiff> {
+        let pos = self.journals.iter().position(|j| j.id == id)?;
+        let current = |address: &Address| {
+            self.accounts
+                .get(address)
+                .map(|a| AccountState {
+                    balance: a.value,
+                    nonce: a.nonce,
+                    code: a.code.clone(),
+                    storage: BTreeMap::new(),
+                })
+                .unwrap_or_default()
+        };
+        let mut accounts: BTreeMap<Address, (AccountState, AccountState)> = BTreeMap::new();
+        for journal in &self.journals[pos..] {
+            for (address, previous) in &journal.accounts {
+                accounts.entry(*address).or_insert_with(|| {
+                    let pre = previous
+                        .as_ref()
+                        .map(|(nonce, balance, code)| AccountState {
+                            balance: *balance,
+                            nonce: *nonce,
+                            code: code.clone(),
+                            storage: BTreeMap::new(),
+                        })
+                        .unwrap_or_default();
+                    (pre, current(address))
+                });
+            }
+            for ((address, slot), previous) in &journal.slots {
+                let Some((pre, post)) = accounts.get_mut(address) else {
+                    continue;
+                };
+                if pre.storage.contains_key(slot) {
+                    continue;
+                }
+                pre.storage.insert(*slot, previous.unwrap_or_default());
+                let value = self
+                    .accounts
+                    .get(address)
+                    .and_then(|a| a.storage.get(slot))
+                    .copied()
+                    .unwrap_or_default();
+                post.storage.insert(*slot, value);
+            }
+        }
+        Some(StateDiff::new(
+            accounts
+                .into_iter()
+                .map(|(address, (pre, post))| (address, pre, post)),
+        ))
+    }
+
+    /// The account at `address`, created if needed, for changing its nonce, balance or
+    /// code. Storage is written with `set_storage`.
+    pub(crate) fn account_mut(&mut self, address: Address) -> &mut Account {
+        self.dirty.entry(address).or_default();
+        let first_touch = self.touched.insert(address);
+        if let Some(journal) = self.journals.last_mut() {
+            if first_touch {
+                journal.touched.push(address);
+            }
+            journal.accounts.entry(address).or_insert_with(|| {
+                self.accounts
+                    .get(&address)
+                    .map(|a| (a.nonce, a.value, a.code.clone()))
+            });
+        }
+        self.accounts.entry(address).or_default()
+    }
+
+    pub(crate) fn set_storage(&mut self, address: Address, slot: U256, value: U256) {
+        self.dirty.entry(address).or_default().insert(slot);
+        let account = self.account_mut(address);
+        let previous = account.storage.insert(slot, value);
+        if let Some(journal) = self.journals.last_mut() {
+            journal.slots.entry((address, slot)).or_insert(previous);
+        }
+    }
+
+    pub(crate) fn set_transient(&mut self, slot: U256, value: U256) {
+        let previous = self.transient.insert(slot, value);
+        if let Some(journal) = self.journals.last_mut() {
+            journal.transient.entry(slot).or_insert(previous);
+        }
+    }
+
+    pub(crate) fn clear_transient(&mut self) {
+        let transient = std::mem::take(&mut self.transient);
+        if let Some(journal) = self.journals.last_mut() {
+            for (slot, value) in transient {
+                journal.transient.entry(slot).or_insert(Some(value));
+            }
+        }
+    }
+
+    pub(crate) fn set_block_env(&mut self, block: BlockEnv) {
+        let previous = std::mem::replace(&mut self.block, block);
+        if let Some(journal) = self.journals.last_mut() {
+            journal.block.get_or_insert(previous);
+        }
+    }
+
+    pub(crate) fn set_tx_env(&mut self, tx: TxEnv) {
+        let previous = std::mem::replace(&mut self.tx, tx);
+        if let Some(journal) = self.journals.last_mut() {
+            journal.tx.get_or_insert(previous);
+        }
+    }
+
+    /// The block hashes, for changing them. The whole list is journaled on first change,
+    /// which happens once per block.
+    pub(crate) fn block_hashes_mut(&mut self) -> &mut VecDeque<(U256, U256)> {
+        if let Some(journal) = self.journals.last_mut() {
+            if journal.block_hashes.is_none() {
+                journal.block_hashes = Some(self.block_hashes.clone());
+            }
+        }
+        &mut self.block_hashes
+    }
+
+    /// Deletes the account at `address` along with its storage.
+    pub(crate) fn remove_account(&mut self, address: Address) {
+        let Some(account) = self.accounts.remove(&address) else {
+            return;
+        };
+        self.dirty
+            .entry(address)
+            .or_default()
+            .extend(account.storage.keys());
+        if let Some(journal) = self.journals.last_mut() {
+            journal.accounts.entry(address).or_insert(Some((
+                account.nonce,
+                account.value,
+                account.code,
+            )));
+            for (slot, value) in account.storage {
+                journal.slots.entry((address, slot)).or_insert(Some(value));
+            }
+        }
+    }
+
+    /// Deletes the accounts written by the transaction that ended up empty (EIP-161).
+    pub(crate) fn remove_touched_empty(&mut self) {
+        let touched: Vec<Address> = self.touched.drain().collect();
+        for address in touched {
+            if self.accounts.get(&address).is_some_and(Account::is_empty) {
+                self.remove_account(address);
+            }
+        }
+    }
+
+    /// Replaces the account at `address` altogether.
+    pub(crate) fn replace_account(&mut self, address: Address, account: Account) {
+        let slots: Vec<U256> = self
+            .accounts
+            .get(&address)
+            .into_iter()
+            .flat_map(|old| old.storage.keys())
+            .chain(account.storage.keys())
+            .copied()
+            .collect();
+        for slot in slots {
+            let value = account.storage.get(&slot).copied();
+            match value {
+                Some(value) => self.set_storage(address, slot, value),
+                None => {
+                    self.dirty.entry(address).or_default().insert(slot);
+                    let previous = self.account_mut(address).storage.remove(&slot);
+                    if let Some(journal) = self.journals.last_mut() {
+                        journal.slots.entry((address, slot)).or_insert(previous);
+                    }
+                }
+            }
+        }
+        let current = self.account_mut(address);
+        current.nonce = account.nonce;
+        current.value = account.value;
+        current.code = account.code;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::ContextMut,
+        machine::{CallInfo, GasTracker},
+        tests::{addr, COUNTER},
+        transaction::TxEip1559,
+    };
+
+    fn fixture() -> MiniEthereum {
+        let mut ctx = MiniEthereum::new();
+        ctx.fund(addr(1), U256::from(1_000_000));
+        ctx.accounts.entry(addr(0x42)).or_default().code = COUNTER.to_vec();
+        ctx
+    }
+
+    fn increment(ctx: &mut MiniEthereum) {
+        ctx.call(
+            1024,
+            &mut GasTracker::new(100_000),
+            addr(0x42),
+            CallInfo {
+                origin: addr(1),
+                caller: addr(1),
+                value: U256::ONE,
+                data: vec![],
+                is_static: false,
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_revert() {
+        let mut ctx = fixture();
+        let initial = ctx.accounts.clone();
+
+        let outer = ctx.snapshot();
+        increment(&mut ctx);
+        ctx.fund(addr(2), U256::from(5));
+        let after_first = ctx.accounts.clone();
+
+        let inner = ctx.snapshot();
+        increment(&mut ctx);
+        let tx = TxEip1559 {
+            to: Some(addr(3)),
+            value: U256::from(7),
+            gas_limit: 21000,
+            ..Default::default()
+        };
+        ctx.execute_transaction(addr(1), &tx.into()).unwrap();
+        ctx.replace_account(addr(0x42), Account::default());
+        assert_ne!(ctx.accounts, after_first);
+
+        assert!(ctx.revert_to(inner));
+        assert_eq!(ctx.accounts, after_first);
+        // A snapshot is consumed by reverting to it.
+        assert!(!ctx.revert_to(inner));
+
+        let again = ctx.snapshot();
+        increment(&mut ctx);
+        assert!(ctx.revert_to(outer));
+        assert_eq!(ctx.accounts, initial);
+        // Later snapshots go along with the one reverted to.
+        assert!(!ctx.revert_to(again));
+        assert!(ctx.journals.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_per_test() {
+        let mut ctx = fixture();
+        let fixture = ctx.accounts.clone();
+        for _ in 0..3 {
+            let id = ctx.snapshot();
+            increment(&mut ctx);
+            assert_eq!(ctx.accounts[&addr(0x42)].storage[&U256::ZERO], U256::ONE);
+            assert!(ctx.revert_to(id));
+            assert_eq!(ctx.accounts, fixture);
+        }
+    }
+
+    #[test]
+    fn test_discard_snapshot() {
+        let mut ctx = fixture();
+        let initial = ctx.accounts.clone();
+        let outer = ctx.snapshot();
+        let inner = ctx.snapshot();
+        increment(&mut ctx);
+        ctx.set_block_env(BlockEnv::default().with_number(U256::from(5)));
+
+        // The writes recorded by the discarded snapshot still revert with the outer one.
+        assert!(ctx.discard_snapshot(inner));
+        assert!(!ctx.revert_to(inner));
+        assert!(ctx.revert_to(outer));
+        assert_eq!(ctx.accounts, initial);
+        assert_eq!(ctx.block.number, U256::ZERO);
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+
+use alloy_primitives::primitives::{hex, Address};
+
+use crate::{
+    context::{Account, MiniEthereum},
+    keccak::keccak,
+    rlp::{self, encode_bytes, encode_list_payload, Encodable},
+    trie::{Trie, EMPTY_ROOT},
+    u256::U256,
+};
+
+/// Hash of empty code, `keccak("")`.
+pub const EMPTY_CODE_HASH: [u8; 32] =
+    hex!("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+
+pub fn code_hash(code: &[u8]) -> [u8; 32] {
+    if code.is_empty() {
+        EMPTY_CODE_HASH
+    } else {
+        keccak(code)
+    }
+}
+
+/// Leaf of the state trie, `rlp([nonce, balance, storage_root, code_hash])`.
+pub fn encode_account(
+    nonce: &U256,
+    balance: &U256,
+    storage_root: &[u8; 32],
+    code_hash: &[u8; 32],
+) -> Vec<u8> {
+    let mut payload = Vec::new();
+    nonce.rlp_append(&mut payload);
+    balance.rlp_append(&mut payload);
+    encode_bytes(&mut payload, storage_root);
+    encode_bytes(&mut payload, code_hash);
+    let mut out = Vec::new();
+    encode_list_payload(&mut out, &payload);
+    out
+}
+
+/// Storage tries are keyed by the hash of the slot.
+pub fn storage_key(slot: &U256) -> [u8; 32] {
+    keccak(&slot.to_be_bytes::<32>())
+}
+
+/// Leaf of a storage trie, zero slots are not part of the trie.
+pub fn encode_slot(value: &U256) -> Option<Vec<u8>> {
+    (!value.is_zero()).then(|| rlp::encode(value))
+}
+
+fn set_slot(trie: &mut Trie, slot: &U256, value: &U256) {
+    match encode_slot(value) {
+        Some(encoded) => trie.insert(&storage_key(slot), encoded),
+        None => {
+            trie.remove(&storage_key(slot));
+        }
+    }
+}
+
+/// The state trie and the storage tries of a set of accounts, updated with the accounts and
+/// slots written since the previous update so that only the modified paths are hashed again.
+#[derive(Debug, Clone, Default)]
+pub struct StateTrie {
+    accounts: Trie,
+    storage: HashMap<Address, Trie>,
+    /// Whether the trie was ever updated, before that every account is hashed.
+    built: bool,
+}
+
+impl StateTrie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Brings the `dirty` accounts and slots in line with `accounts`. Empty accounts
+    /// (EIP-161) are left out of the state trie.
+    pub fn update(
+        &mut self,
+        accounts: &HashMap<Address, Account>,
+        dirty: &HashMap<Address, HashSet<U256>>,
+    ) {
+        self.built = true;
+        for (address, slots) in dirty {
+            let key = keccak(address.as_slice());
+            let Some(account) = accounts.get(address) else {
+                self.storage.remove(address);
+                self.accounts.remove(&key);
+                continue;
+            };
+            let trie = self.storage.entry(*address).or_default();
+            for slot in slots {
+                let value = account.storage.get(slot).copied().unwrap_or_default();
+                set_slot(trie, slot, &value);
+            }
+            if account.is_empty() {
+                self.accounts.remove(&key);
+            } else {
+                let leaf = encode_account(
+                    &account.nonce,
+                    &account.value,
+                    &trie.root(),
+                    &code_hash(&account.code),
+                );
+                self.accounts.insert(&key, leaf);
+            }
+        }
+    }
+
+    /// Root as of the last update.
+    pub fn state_root(&mut self) -> [u8; 32] {
+        self.accounts.root()
+    }
+
+    /// Storage root of an account as of the last update.
+    pub fn storage_root(&mut self, address: Address) -> [u8; 32] {
+        self.storage
+            .get_mut(&address)
+            .map(Trie::root)
+            .unwrap_or(EMPTY_ROOT)
+    }
+
+    pub fn account_proof(&mut self, address: Address) -> Vec<Vec<u8>> {
+        self.accounts.proof(&keccak(address.as_slice()))
+    }
+
+    pub fn storage_proof(&mut self, address: Address, slot: &U256) -> Vec<Vec<u8>> {
+        self.storage
+            .get_mut(&address)
+            .map(|trie| trie.proof(&storage_key(slot)))
+            .unwrap_or_default()
+    }
+}
+
+impl MiniEthereum {
+    /// Root of the current state. Only the accounts changed since the previous call are
+    /// hashed again, so it can be computed after every transaction. The first call hashes
+    /// every account; after it, the accounts changed directly through `accounts` rather
+    /// than through the context must be reported with `mark_dirty`.
+    pub fn state_root(&mut self) -> [u8; 32] {
+        self.update_state_trie();
+        self.state_trie.state_root()
+    }
+
+    pub fn storage_root(&mut self, address: Address) -> [u8; 32] {
+        self.update_state_trie();
+        self.state_trie.storage_root(address)
+    }
+
+    /// Has the account at `address` hashed again by the next `state_root`, after it was
+    /// changed through `accounts`.
+    pub fn mark_dirty(&mut self, address: Address) {
+        // Its storage trie is rebuilt from scratch, as slots may have been removed.
+        self.state_trie.storage.remove(&address);
+        let slots = self.dirty.entry(address).or_default();
+        if let Some(account) = self.accounts.get(&address) {
+            slots.extend(account.storage.keys());
+        }
+    }
+
+    pub(crate) fn update_state_trie(&mut self) {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        if !self.state_trie.built {
+            for (address, account) in &self.accounts {
+                dirty
+                    .entry(*address)
+                    .or_default()
+                    .extend(account.storage.keys());
+            }
+        }
+        self.state_trie.update(&self.accounts, &dirty);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: [u8; 9] = [0x60, 0x2a, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xa0, 0x00];
+
+    #[test]
+    fn test_state_root() {
+        let mut ctx = MiniEthereum::new();
+        assert_eq!(ctx.state_root(), EMPTY_ROOT);
+
+        let eth = U256::from(10).pow(U256::from(18));
+        ctx.replace_account(
+            Address::with_last_byte(1),
+            Account {
+                nonce: U256::ONE,
+                value: eth,
+                ..Default::default()
+            },
+        );
+        ctx.replace_account(
+            Address::with_last_byte(0x42),
+            Account {
+                nonce: U256::ONE,
+                code: CODE.to_vec(),
+                storage: [
+                    (U256::ZERO, U256::ONE),
+                    (U256::ONE, U256::from(0x2a)),
+                    (U256::from(0xdeadbeefu64), U256::ONE << 255),
+                    (U256::from(7), U256::ZERO),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            },
+        );
+        ctx.fund(Address::with_last_byte(0xaa), U256::from(5));
+        // Empty accounts are not part of the state.
+        ctx.replace_account(Address::with_last_byte(0xcc), Account::default());
+
+        assert_eq!(
+            ctx.storage_root(Address::with_last_byte(0x42)),
+            hex!("41277def93aa6e921a92435098b0d64905a54fd8d740b842a490f1c5bb314bd4")
+        );
+        assert_eq!(ctx.storage_root(Address::with_last_byte(1)), EMPTY_ROOT);
+        assert_eq!(
+            ctx.state_root(),
+            hex!("d05baf151bf383a3e33fd1d6cbc0b318004f96fdf4610e36ac6c9754e29f9d25")
+        );
+
+        ctx.set_storage(Address::with_last_byte(0x42), U256::ONE, U256::ZERO);
+        ctx.remove_account(Address::with_last_byte(0xaa));
+        ctx.fund(Address::with_last_byte(0xbb), U256::from(7));
+        assert_eq!(
+            ctx.storage_root(Address::with_last_byte(0x42)),
+            hex!("712f6804e2e06fe53682822c45cee6b968ed8cb98034faa577bf74d92c47aab5")
+        );
+        assert_eq!(
+            ctx.state_root(),
+            hex!("ad5600241d97eb6768ed839c8e12aade7542627f3462707265d981fcb6f7383f")
+        );
+    }
+
+    #[test]
+    fn test_incremental_state_root() {
+        let mut ctx = MiniEthereum::new();
+        for i in 1..=100u8 {
+            ctx.fund(Address::with_last_byte(i), U256::from(i));
+        }
+        let root = ctx.state_root();
+        assert_eq!(
+            root,
+            hex!("c7e89d4efdbbd3c1642dc5dc9410f8e1a4264c2df4ff9bd99aa87a2a69696f67")
+        );
+
+        // Changing then restoring an account gives back the same root, and the
+        // incremental result matches a root computed from scratch.
+        ctx.fund(Address::with_last_byte(50), U256::from(1));
+        let changed = ctx.state_root();
+        assert_ne!(changed, root);
+        let mut fresh = StateTrie::new();
+        let all = ctx
+            .accounts
+            .iter()
+            .map(|(address, account)| (*address, account.storage.keys().copied().collect()))
+            .collect();
+        fresh.update(&ctx.accounts, &all);
+        assert_eq!(fresh.state_root(), changed);
+
+        ctx.account_mut(Address::with_last_byte(50)).value = U256::from(50);
+        assert_eq!(ctx.state_root(), root);
+
+        // Reverting a snapshot rewinds the trie along with the accounts.
+        let snapshot = ctx.snapshot();
+        ctx.fund(Address::with_last_byte(101), U256::ONE);
+        ctx.set_storage(Address::with_last_byte(1), U256::ONE, U256::ONE);
+        assert_ne!(ctx.state_root(), root);
+        assert!(ctx.revert_to(snapshot));
+        assert_eq!(ctx.state_root(), root);
+    }
+
+    #[test]
+    fn test_direct_writes_state_root() {
+        let account = Account {
+            nonce: U256::ONE,
+            code: CODE.to_vec(),
+            storage: [(U256::ZERO, U256::ONE), (U256::ONE, U256::from(2))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let mut expected = MiniEthereum::new();
+        expected.replace_account(Address::with_last_byte(0x42), account.clone());
+
+        // The first root sees the accounts inserted directly.
+        let mut ctx = MiniEthereum::new();
+        ctx.accounts.insert(Address::with_last_byte(0x42), account);
+        assert_eq!(ctx.state_root(), expected.state_root());
+
+        // Later direct writes are seen once reported.
+        let account = ctx
+            .accounts
+            .get_mut(&Address::with_last_byte(0x42))
+            .unwrap();
+        account.value = U256::from(5);
+        account.storage.remove(&U256::ONE);
+        ctx.mark_dirty(Address::with_last_byte(0x42));
+        expected.fund(Address::with_last_byte(0x42), U256::from(5));
+        expected.set_storage(Address::with_last_byte(0x42), U256::ONE, U256::ZERO);
+        assert_eq!(ctx.state_root(), expected.state_root());
+        assert_eq!(
+            ctx.storage_root(Address::with_last_byte(0x42)),
+            expected.storage_root(Address::with_last_byte(0x42))
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::keccak::keccak;
+
+const PUT: u8 = 0;
+const DELETE: u8 = 1;
+const HEADER_SIZE: usize = 8;
+
+/// A write of a batch, `None` deletes the key.
+pub type Batch = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+/// Embedded key-value store kept in a single append-only file.
+///
+/// Writes are grouped in batches, each appended as one record
+/// `[payload length: u32][checksum: u32][payload]` where the checksum is the start of the
+/// keccak of the payload. A batch is only visible once its whole record is on disk: a
+/// record cut short by a crash fails its checksum and is dropped when the file is opened
+/// again, so a batch is applied either entirely or not at all.
+///
+/// Only the position of each value is kept in memory, values are read from the file.
+#[derive(Debug)]
+pub struct Store {
+    path: PathBuf,
+    file: File,
+    index: HashMap<Vec<u8>, (u64, usize)>,
+    len: u64,
+}
+
+fn read_u32(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let bytes = data.get(*pos..*pos + 4)?;
+    *pos += 4;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    keccak(payload)[..4].try_into().unwrap()
+}
+
+/// Key of an entry with the offset and length of its value, `None` for a deletion.
+type Entry<'a> = (&'a [u8], Option<(usize, usize)>);
+
+/// Entries of a record payload, with value offsets relative to the payload.
+fn parse_payload(payload: &[u8]) -> Option<Vec<Entry<'_>>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos < payload.len() {
+        let op = payload[pos];
+        pos += 1;
+        let key_len = read_u32(payload, &mut pos)?;
+        let key = payload.get(pos..pos + key_len)?;
+        pos += key_len;
+        match op {
+            PUT => {
+                let value_len = read_u32(payload, &mut pos)?;
+                payload.get(pos..pos + value_len)?;
+                entries.push((key, Some((pos, value_len))));
+                pos += value_len;
+            }
+            DELETE => entries.push((key, None)),
+            _ => return None,
+        }
+    }
+    Some(entries)
+}
+
+/// Updates the index with the entries of a record whose payload starts at `start`.
+fn apply(index: &mut HashMap<Vec<u8>, (u64, usize)>, start: u64, entries: Vec<Entry>) {
+    for (key, value) in entries {
+        match value {
+            Some((offset, len)) => {
+                index.insert(key.to_vec(), (start + offset as u64, len));
+            }
+            None => {
+                index.remove(key);
+            }
+        }
+    }
+}
+
+fn encode_batch(batch: &Batch) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for (key, value) in batch {
+        payload.push(if value.is_some() { PUT } else { DELETE });
+        payload.extend((key.len() as u32).to_le_bytes());
+        payload.extend(key);
+        if let Some(value) = value {
+            payload.extend((value.len() as u32).to_le_bytes());
+            payload.extend(value);
+        }
+    }
+    let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+    record.extend((payload.len() as u32).to_le_bytes());
+    record.extend(checksum(&payload));
+    record.extend(payload);
+    record
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it if needed. A partially written batch at the
+    /// end of the file is discarded.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        let mut index = HashMap::new();
+        let mut pos = 0;
+        while pos + HEADER_SIZE <= data.len() {
+            let mut header = pos;
+            let size = read_u32(&data, &mut header).unwrap();
+            let Some(payload) = data.get(pos + HEADER_SIZE..pos + HEADER_SIZE + size) else {
+                break;
+            };
+            if data[pos + 4..pos + HEADER_SIZE] != checksum(payload) {
+                break;
+            }
+            let Some(entries) = parse_payload(payload) else {
+                break;
+            };
+            apply(&mut index, (pos + HEADER_SIZE) as u64, entries);
+            pos += HEADER_SIZE + size;
+        }
+        if pos < data.len() {
+            file.set_len(pos as u64)?;
+            file.sync_data()?;
+        }
+        Ok(Self {
+            path,
+            file,
+            index,
+            len: pos as u64,
+        })
+    }
+
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let Some(&(offset, len)) = self.index.get(key) else {
+            return Ok(None);
+        };
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut value = vec![0; len];
+        file.read_exact(&mut value)?;
+        Ok(Some(value))
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Keys currently stored, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.index.keys().map(Vec::as_slice)
+    }
+
+    /// Applies all the writes of `batch` atomically and flushes them to disk.
+    pub fn write(&mut self, batch: &Batch) -> io::Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let record = encode_batch(batch);
+        self.file.seek(SeekFrom::Start(self.len))?;
+        if let Err(e) = self
+            .file
+            .write_all(&record)
+            .and_then(|_| self.file.sync_data())
+        {
+            // Leave no partial record behind for the next batch to be appended to.
+            self.file.set_len(self.len)?;
+            return Err(e);
+        }
+
+        let entries = parse_payload(&record[HEADER_SIZE..]).unwrap();
+        apply(&mut self.index, self.len + HEADER_SIZE as u64, entries);
+        self.len += record.len() as u64;
+        Ok(())
+    }
+
+    /// Rewrites the file with the live entries only. The new file replaces the old one
+    /// with a rename, so a crash during compaction leaves the store untouched.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".compact");
+        let batch = self
+            .index
+            .keys()
+            .map(|key| Ok((key.clone(), self.get(key)?)))
+            .collect::<io::Result<Batch>>()?;
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(&encode_batch(&batch))?;
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        // The rename itself is only durable once the directory entry is.
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+
+    /// Size of the file, including the entries overwritten since the last compaction.
+    pub fn file_size(&self) -> u64 {
+        self.len
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Path of a fresh file in the temporary directory, removed when dropped.
+    pub(crate) struct TempPath(pub PathBuf);
+
+    impl TempPath {
+        pub(crate) fn new(name: &str) -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "xevm-{}-{}-{}",
+                name,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn put(key: &[u8], value: &[u8]) -> (Vec<u8>, Option<Vec<u8>>) {
+        (key.to_vec(), Some(value.to_vec()))
+    }
+
+    #[test]
+    fn test_store_reopen() {
+        let path = TempPath::new("store");
+        let mut store = Store::open(&path.0).unwrap();
+        store
+            .write(&vec![put(b"a", b"1"), put(b"b", b"2"), put(b"c", b"")])
+            .unwrap();
+        store
+            .write(&vec![put(b"a", b"3"), (b"b".to_vec(), None)])
+            .unwrap();
+        assert_eq!(store.get(b"a").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(store.get(b"b").unwrap(), None);
+        assert_eq!(store.get(b"c").unwrap(), Some(vec![]));
+        drop(store);
+
+        let mut store = Store::open(&path.0).unwrap();
+        assert_eq!(store.get(b"a").unwrap(), Some(b"3".to_vec()));
+        assert!(!store.contains_key(b"b"));
+        assert_eq!(store.get(b"c").unwrap(), Some(vec![]));
+
+        let size = store.file_size();
+        store.compact().unwrap();
+        assert!(store.file_size() < size);
+        assert_eq!(fs::metadata(&path.0).unwrap().len(), store.file_size());
+        let mut keys: Vec<&[u8]> = store.keys().collect();
+        keys.sort();
+        assert_eq!(keys, [b"a", b"c"]);
+        assert_eq!(store.get(b"a").unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn test_store_torn_write() {
+        let path = TempPath::new("store");
+        let mut store = Store::open(&path.0).unwrap();
+        store.write(&vec![put(b"a", b"1")]).unwrap();
+        let committed = store.file_size();
+        store
+            .write(&vec![put(b"a", b"2"), put(b"b", b"2")])
+            .unwrap();
+        drop(store);
+
+        // Cut the second batch short, as if the process died while writing it.
+        let full = fs::metadata(&path.0).unwrap().len();
+        for len in [full - 1, committed + 3] {
+            OpenOptions::new()
+                .write(true)
+                .open(&path.0)
+                .unwrap()
+                .set_len(len)
+                .unwrap();
+            let store = Store::open(&path.0).unwrap();
+            assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+            assert_eq!(store.get(b"b").unwrap(), None);
+            assert_eq!(store.file_size(), committed);
+        }
+
+        // A corrupted record is dropped along with everything after it.
+        let mut store = Store::open(&path.0).unwrap();
+        store.write(&vec![put(b"b", b"2")]).unwrap();
+        store.write(&vec![put(b"c", b"3")]).unwrap();
+        drop(store);
+        let mut data = fs::read(&path.0).unwrap();
+        data[committed as usize + HEADER_SIZE + 2] ^= 0xff;
+        fs::write(&path.0, data).unwrap();
+        let store = Store::open(&path.0).unwrap();
+        assert_eq!(store.get(b"b").unwrap(), None);
+        assert_eq!(store.get(b"c").unwrap(), None);
+        assert_eq!(store.file_size(), committed);
+    }
+}
@@ -9,7 +9,7 @@
        }
    }
 */
-pub const PLAIN_ERC20_BYTECODE: &'static [u8; 2852] = &[
+pub const PLAIN_ERC20_BYTECODE: &[u8; 2852] = &[
     96, 128, 96, 64, 82, 52, 128, 21, 97, 0, 15, 87, 95, 128, 253, 91, 80, 96, 64, 81, 97, 11, 36,
     56, 3, 128, 97, 11, 36, 131, 57, 129, 1, 96, 64, 129, 144, 82, 97, 0, 46, 145, 97, 2, 100, 86,
     91, 130, 130, 96, 3, 97, 0, 60, 131, 130, 97, 3, 85, 86, 91, 80, 96, 4, 97, 0, 73, 130, 130,
@@ -1,4 +1,4 @@
-use alloy_primitives::primitives::Address;
+use alloy_primitives::primitives::{hex, Address};
 
 use crate::{
     context::{Account, Context, ContextMut, MiniEthereum},
@@ -7,7 +7,14 @@ use crate::{
     u256::U256,
 };
 
-fn addr(v: u8) -> Address {
+/// Init code of a counter, deploying `COUNTER`.
+pub(crate) const COUNTER_INIT: [u8; 26] =
+    hex!("600e600c600039600e6000f35f54600101805f555f5260205ff3");
+
+/// Runtime code of a counter: increments slot 0 and returns its new value.
+pub(crate) const COUNTER: [u8; 14] = hex!("5f54600101805f555f5260205ff3");
+
+pub(crate) fn addr(v: u8) -> Address {
     let mut arr = [0u8; 20];
     arr[19] = v;
     Address::from_slice(&arr)
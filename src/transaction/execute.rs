@@ -0,0 +1,813 @@
+use alloy_primitives::primitives::Address;
+
+use super::*;
+use crate::{
+    context::{
+        checkpointed, delegated_address, ContextMut, Info, MiniEthereum, Spec, State,
+        DELEGATION_PREFIX,
+    },
+    env::TxEnv,
+    error::{ExecError, InvalidTransaction, RevertError},
+    inspector::{Inspected, Inspector},
+    machine::{CallInfo, GasTracker, Word},
+    opcodes::ExecutionResult,
+};
+
+const STACK_SIZE: usize = 1024;
+
+/// Output of the call or deployment of a transaction, and the address of the deployed
+/// contract.
+type FrameOutput = (Result<Vec<u8>, ExecError>, Option<Address>);
+
+fn run_frame<C: ContextMut<U256>>(
+    ctx: &mut C,
+    gas_tracker: &mut GasTracker,
+    tx: &Transaction,
+    call_info: CallInfo<U256>,
+) -> FrameOutput {
+    match tx.to() {
+        Some(to) => {
+            let result = ctx
+                .call(STACK_SIZE, gas_tracker, to, call_info)
+                .map(|res| match res {
+                    ExecutionResult::Returned(data) => data,
+                    ExecutionResult::Halted => vec![],
+                });
+            (result, None)
+        }
+        None => match ctx.create(STACK_SIZE, gas_tracker, call_info, None) {
+            Ok(addr) => (Ok(vec![]), Some(addr)),
+            Err(e) => (Err(e), None),
+        },
+    }
+}
+
+/// Checks the rules specific to each envelope type.
+fn validate_transaction_type<C: ContextMut<U256> + State>(
+    ctx: &C,
+    tx: &Transaction,
+) -> Result<(), ExecError> {
+    let spec = ctx.spec();
+    if let Some(chain_id) = tx.chain_id() {
+        if chain_id != ctx.info(Info::ChainId)? {
+            return Err(InvalidTransaction::ChainIdMismatch.into());
+        }
+    }
+    if tx.max_priority_fee_per_gas() > tx.max_fee_per_gas() {
+        return Err(InvalidTransaction::PriorityFeeAboveMaxFee.into());
+    }
+    if tx.max_fee_per_gas() < ctx.info(Info::BaseFee)? {
+        return Err(InvalidTransaction::GasPriceBelowBaseFee.into());
+    }
+    match tx {
+        Transaction::Eip4844(blob_tx) => {
+            let hashes = &blob_tx.blob_versioned_hashes;
+            if hashes.is_empty() {
+                return Err(InvalidTransaction::EmptyBlobList.into());
+            }
+            if hashes.len() > spec.max_blobs_per_block() {
+                return Err(InvalidTransaction::TooManyBlobs.into());
+            }
+            if hashes
+                .iter()
+                .any(|h| h.to_be_bytes::<32>()[0] != VERSIONED_HASH_VERSION_KZG)
+            {
+                return Err(InvalidTransaction::InvalidBlobVersionedHash.into());
+            }
+            if blob_tx.max_fee_per_blob_gas < ctx.info(Info::BlobBaseFee)? {
+                return Err(InvalidTransaction::BlobGasPriceBelowBaseFee.into());
+            }
+        }
+        Transaction::Eip7702(auth_tx) => {
+            if spec < Spec::Prague {
+                return Err(InvalidTransaction::TypeNotSupported(tx.tx_type()).into());
+            }
+            if auth_tx.authorization_list.is_empty() {
+                return Err(InvalidTransaction::EmptyAuthorizationList.into());
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Checks that `caller` can send `tx`, returning its intrinsic gas.
+fn validate_transaction<C: ContextMut<U256> + State>(
+    ctx: &C,
+    caller: Address,
+    tx: &Transaction,
+) -> Result<usize, ExecError> {
+    validate_transaction_type(ctx, tx)?;
+    let nonce = ctx.nonce(caller)?;
+    if tx.nonce() != nonce {
+        return Err(InvalidTransaction::NonceMismatch {
+            expected: nonce,
+            got: tx.nonce(),
+        }
+        .into());
+    }
+    if tx.is_create() && tx.data().len() > MAX_INITCODE_SIZE {
+        return Err(InvalidTransaction::InitCodeTooLarge.into());
+    }
+    let intrinsic_gas = tx.intrinsic_gas();
+    let floor = if ctx.spec() >= Spec::Prague {
+        tx.floor_data_gas()
+    } else {
+        0
+    };
+    if tx.gas_limit() < std::cmp::max(intrinsic_gas, floor) {
+        return Err(InvalidTransaction::IntrinsicGasTooLow.into());
+    }
+    let balance = ctx.balance(caller)?;
+    let max_blob_fee = match tx {
+        Transaction::Eip4844(blob_tx) => {
+            U256::from(tx.blob_gas()).checked_mul(blob_tx.max_fee_per_blob_gas)
+        }
+        _ => Some(U256::ZERO),
+    };
+    let max_cost = U256::from(tx.gas_limit())
+        .checked_mul(tx.max_fee_per_gas())
+        .zip(max_blob_fee)
+        .and_then(|(gas, blob)| gas.checked_add(blob))
+        .and_then(|c| c.checked_add(tx.value()))
+        .ok_or(InvalidTransaction::InsufficientFunds)?;
+    if balance < max_cost {
+        return Err(InvalidTransaction::InsufficientFunds.into());
+    }
+    Ok(intrinsic_gas)
+}
+
+/// Installs the EIP-7702 delegations of an authorization list, skipping invalid entries.
+/// Returns the gas refunded for authorities that already existed.
+fn apply_authorizations<C: ContextMut<U256> + State>(
+    ctx: &mut C,
+    authorizations: &[Authorization],
+) -> Result<usize, ExecError> {
+    let chain_id = ctx.info(Info::ChainId)?;
+    let mut refund = 0;
+    for auth in authorizations {
+        if auth.chain_id != U256::ZERO && auth.chain_id != chain_id {
+            continue;
+        }
+        if auth.nonce >= U256::from(u64::MAX) {
+            continue;
+        }
+        let Some(authority) = auth.recover_authority() else {
+            continue;
+        };
+        let code = ctx.code(authority)?;
+        if !code.is_empty() && delegated_address(&code).is_none() {
+            continue;
+        }
+        let nonce = ctx.nonce(authority)?;
+        if nonce != auth.nonce {
+            continue;
+        }
+        if !nonce.is_zero() || !code.is_empty() || !ctx.balance(authority)?.is_zero() {
+            refund += PER_EMPTY_ACCOUNT_COST - PER_AUTH_BASE_COST;
+        }
+        let code = if auth.address == Address::ZERO {
+            vec![]
+        } else {
+            [&DELEGATION_PREFIX[..], auth.address.as_slice()].concat()
+        };
+        ctx.set_code(authority, code)?;
+        ctx.set_nonce(authority, nonce + U256::ONE)?;
+    }
+    Ok(refund)
+}
+
+/// Runs a transaction sent by `caller` on any state: validates it, buys its gas, executes
+/// the call or deployment and settles the fees. State changes of a failed execution are
+/// discarded, but the nonce is still bumped and the gas is still paid for.
+pub fn execute_transaction<C: ContextMut<U256> + State>(
+    ctx: &mut C,
+    caller: Address,
+    tx: &Transaction,
+) -> Result<Receipt, ExecError> {
+    run_transaction(ctx, caller, tx, |ctx, gas_tracker, call_info| {
+        run_frame(ctx, gas_tracker, tx, call_info)
+    })
+}
+
+/// Like `execute_transaction`, reporting the execution of the transaction to `inspector`.
+pub fn inspect_transaction<C: ContextMut<U256> + State, I: Inspector>(
+    ctx: &mut C,
+    caller: Address,
+    tx: &Transaction,
+    inspector: &mut I,
+) -> Result<Receipt, ExecError> {
+    run_transaction(ctx, caller, tx, |ctx, gas_tracker, call_info| {
+        run_frame(
+            &mut Inspected::new(ctx, inspector),
+            gas_tracker,
+            tx,
+            call_info,
+        )
+    })
+}
+
+fn run_transaction<C: ContextMut<U256> + State>(
+    ctx: &mut C,
+    caller: Address,
+    tx: &Transaction,
+    frame: impl FnOnce(&mut C, &mut GasTracker, CallInfo<U256>) -> FrameOutput,
+) -> Result<Receipt, ExecError> {
+    let intrinsic_gas = validate_transaction(ctx, caller, tx)?;
+    // A context error aborts the whole transaction, gas purchase included.
+    checkpointed(ctx, |ctx| {
+        let base_fee = ctx.info(Info::BaseFee)?;
+        let gas_price = tx.effective_gas_price(base_fee);
+        let blob_fee = U256::from(tx.blob_gas()) * ctx.info(Info::BlobBaseFee)?;
+        ctx.begin_transaction(
+            TxEnv::default()
+                .with_gas_price(gas_price)
+                .with_blob_versioned_hashes(tx.blob_versioned_hashes().to_vec()),
+        );
+        let balance = ctx.balance(caller)?;
+        ctx.set_balance(
+            caller,
+            balance - U256::from(tx.gas_limit()) * gas_price - blob_fee,
+        )?;
+
+        // `create` bumps the nonce of the sender itself.
+        if !tx.is_create() {
+            let nonce = ctx.nonce(caller)?;
+            ctx.set_nonce(caller, nonce + U256::ONE)?;
+        }
+        let auth_refund = apply_authorizations(ctx, tx.authorization_list())?;
+
+        let checkpoint = ctx.checkpoint();
+        let mut gas_tracker = GasTracker::new(tx.gas_limit() - intrinsic_gas);
+        let call_info = CallInfo {
+            origin: caller,
+            caller,
+            value: tx.value(),
+            data: tx.data().to_vec(),
+            is_static: false,
+        };
+        let (result, contract_address) = frame(ctx, &mut gas_tracker, call_info);
+        let (success, output, contract_address) = match result {
+            Ok(output) => {
+                ctx.discard_checkpoint(checkpoint);
+                (true, output, contract_address)
+            }
+            Err(ExecError::Revert(err)) => {
+                ctx.revert_checkpoint(checkpoint);
+                if tx.is_create() {
+                    let nonce = ctx.nonce(caller)?;
+                    ctx.set_nonce(caller, nonce + U256::ONE)?;
+                }
+                // Only REVERT gives back the gas it did not use, other halts consume it all.
+                if !matches!(err, RevertError::Revert(_)) {
+                    gas_tracker.gas_used = gas_tracker.gas_limit;
+                }
+                gas_tracker.refund = 0;
+                (false, err.into(), None)
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut gas_used = intrinsic_gas + gas_tracker.gas_used;
+        gas_used -= std::cmp::min(
+            gas_tracker.refund + auth_refund,
+            gas_used / MAX_REFUND_QUOTIENT,
+        );
+        if ctx.spec() >= Spec::Prague {
+            gas_used = std::cmp::max(gas_used, tx.floor_data_gas());
+        }
+
+        let balance = ctx.balance(caller)?;
+        ctx.set_balance(
+            caller,
+            balance + U256::from(tx.gas_limit() - gas_used) * gas_price,
+        )?;
+        let coinbase = ctx.info(Info::Coinbase)?.to_addr()?;
+        let balance = ctx.balance(coinbase)?;
+        ctx.set_balance(
+            coinbase,
+            balance + U256::from(gas_used) * (gas_price - base_fee),
+        )?;
+        ctx.end_transaction()?;
+
+        Ok(Receipt {
+            tx_type: tx.tx_type(),
+            success,
+            gas_used,
+            cumulative_gas_used: gas_used,
+            logs: std::mem::take(ctx.logs()),
+            output,
+            contract_address,
+        })
+    })
+}
+
+impl MiniEthereum {
+    /// Runs a transaction sent by `caller`, see the generic `execute_transaction`.
+    pub fn execute_transaction(
+        &mut self,
+        caller: Address,
+        tx: &Transaction,
+    ) -> Result<Receipt, ExecError> {
+        execute_transaction(self, caller, tx)
+    }
+
+    /// Like `execute_transaction`, reporting the execution of the transaction to `inspector`.
+    pub fn inspect_transaction<I: Inspector>(
+        &mut self,
+        caller: Address,
+        tx: &Transaction,
+        inspector: &mut I,
+    ) -> Result<Receipt, ExecError> {
+        inspect_transaction(self, caller, tx, inspector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::Context,
+        disk::DiskEthereum,
+        env::BlockEnv,
+        keccak::keccak,
+        machine::Machine,
+        overlay::{AccountChange, Overlay},
+        store::tests::TempPath,
+    };
+
+    const ETHER: u64 = 1_000_000_000_000_000_000;
+
+    fn sender() -> Address {
+        Address::with_last_byte(1)
+    }
+
+    fn funded_ctx() -> MiniEthereum {
+        let mut ctx = MiniEthereum::new().with_block_env(
+            BlockEnv::default()
+                .with_base_fee(U256::from(7))
+                .with_coinbase(Address::with_last_byte(0xcb)),
+        );
+        ctx.accounts.entry(sender()).or_default().value = U256::from(ETHER);
+        ctx
+    }
+
+    fn transfer() -> TxEip1559 {
+        TxEip1559 {
+            to: Some(Address::with_last_byte(2)),
+            value: U256::from(100),
+            gas_limit: 30000,
+            max_fee_per_gas: U256::from(10),
+            max_priority_fee_per_gas: U256::from(2),
+            ..Default::default()
+        }
+    }
+
+    fn blob_tx() -> TxEip4844 {
+        let mut hash = [0u8; 32];
+        hash[0] = VERSIONED_HASH_VERSION_KZG;
+        TxEip4844 {
+            to: Address::with_last_byte(2),
+            gas_limit: 30000,
+            max_fee_per_gas: U256::from(10),
+            max_priority_fee_per_gas: U256::from(2),
+            max_fee_per_blob_gas: U256::from(1),
+            blob_versioned_hashes: vec![U256::from_be_bytes(hash); 2],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_value_transfer() {
+        let mut ctx = funded_ctx();
+        let receipt = ctx
+            .execute_transaction(sender(), &transfer().into())
+            .unwrap();
+        assert!(receipt.success);
+        assert_eq!(receipt.gas_used, 21000);
+        assert_eq!(ctx.tx.gas_price, U256::from(9));
+        assert_eq!(
+            ctx.balance(sender()).unwrap(),
+            U256::from(ETHER - 100 - 21000 * 9)
+        );
+        assert_eq!(
+            ctx.balance(Address::with_last_byte(2)).unwrap(),
+            U256::from(100)
+        );
+        assert_eq!(
+            ctx.balance(Address::with_last_byte(0xcb)).unwrap(),
+            U256::from(21000 * 2)
+        );
+        assert_eq!(ctx.accounts[&sender()].nonce, U256::from(1));
+        assert_eq!(
+            ctx.execute_transaction(sender(), &transfer().into()),
+            Err(ExecError::InvalidTransaction(
+                InvalidTransaction::NonceMismatch {
+                    expected: U256::from(1),
+                    got: U256::from(0)
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_legacy_transfer() {
+        let mut ctx = funded_ctx();
+        let tx = TxLegacy {
+            gas_price: U256::from(10),
+            gas_limit: 21000,
+            to: Some(Address::with_last_byte(2)),
+            value: U256::from(100),
+            ..Default::default()
+        };
+        ctx.execute_transaction(sender(), &tx.into()).unwrap();
+        assert_eq!(ctx.tx.gas_price, U256::from(10));
+        assert_eq!(
+            ctx.balance(Address::with_last_byte(0xcb)).unwrap(),
+            U256::from(21000 * 3)
+        );
+    }
+
+    #[test]
+    fn test_invalid_transactions() {
+        let mut ctx = funded_ctx();
+        for (tx, err) in [
+            (
+                TxEip1559 {
+                    value: U256::from(ETHER),
+                    ..transfer()
+                }
+                .into(),
+                InvalidTransaction::InsufficientFunds,
+            ),
+            (
+                TxEip1559 {
+                    gas_limit: 20999,
+                    ..transfer()
+                }
+                .into(),
+                InvalidTransaction::IntrinsicGasTooLow,
+            ),
+            (
+                TxEip1559 {
+                    max_fee_per_gas: U256::from(6),
+                    max_priority_fee_per_gas: U256::from(0),
+                    ..transfer()
+                }
+                .into(),
+                InvalidTransaction::GasPriceBelowBaseFee,
+            ),
+            (
+                TxEip1559 {
+                    max_priority_fee_per_gas: U256::from(11),
+                    ..transfer()
+                }
+                .into(),
+                InvalidTransaction::PriorityFeeAboveMaxFee,
+            ),
+            (
+                TxEip1559 {
+                    to: None,
+                    data: vec![0; MAX_INITCODE_SIZE + 1],
+                    gas_limit: 10000000,
+                    ..transfer()
+                }
+                .into(),
+                InvalidTransaction::InitCodeTooLarge,
+            ),
+            (
+                TxEip1559 {
+                    chain_id: U256::from(1),
+                    ..transfer()
+                }
+                .into(),
+                InvalidTransaction::ChainIdMismatch,
+            ),
+            (
+                TxLegacy {
+                    chain_id: Some(U256::from(1)),
+                    gas_price: U256::from(10),
+                    gas_limit: 21000,
+                    ..Default::default()
+                }
+                .into(),
+                InvalidTransaction::ChainIdMismatch,
+            ),
+            (
+                TxEip2930 {
+                    gas_price: U256::from(6),
+                    gas_limit: 21000,
+                    ..Default::default()
+                }
+                .into(),
+                InvalidTransaction::GasPriceBelowBaseFee,
+            ),
+            (
+                TxEip7702 {
+                    max_fee_per_gas: U256::from(10),
+                    gas_limit: 100000,
+                    ..Default::default()
+                }
+                .into(),
+                InvalidTransaction::EmptyAuthorizationList,
+            ),
+        ] {
+            assert_eq!(
+                ctx.execute_transaction(sender(), &tx),
+                Err(ExecError::InvalidTransaction(err))
+            );
+        }
+        assert_eq!(ctx.balance(sender()).unwrap(), U256::from(ETHER));
+    }
+
+    #[test]
+    fn test_blob_transaction() {
+        let mut ctx = funded_ctx();
+        for (tx, err) in [
+            (
+                TxEip4844 {
+                    blob_versioned_hashes: vec![],
+                    ..blob_tx()
+                },
+                InvalidTransaction::EmptyBlobList,
+            ),
+            (
+                TxEip4844 {
+                    blob_versioned_hashes: vec![blob_tx().blob_versioned_hashes[0]; 10],
+                    ..blob_tx()
+                },
+                InvalidTransaction::TooManyBlobs,
+            ),
+            (
+                TxEip4844 {
+                    blob_versioned_hashes: vec![U256::from(1)],
+                    ..blob_tx()
+                },
+                InvalidTransaction::InvalidBlobVersionedHash,
+            ),
+            (
+                TxEip4844 {
+                    max_fee_per_blob_gas: U256::ZERO,
+                    ..blob_tx()
+                },
+                InvalidTransaction::BlobGasPriceBelowBaseFee,
+            ),
+        ] {
+            assert_eq!(
+                ctx.execute_transaction(sender(), &tx.into()),
+                Err(ExecError::InvalidTransaction(err))
+            );
+        }
+
+        let receipt = ctx
+            .execute_transaction(sender(), &blob_tx().into())
+            .unwrap();
+        assert!(receipt.success);
+        assert_eq!(
+            ctx.tx.blob_versioned_hashes,
+            blob_tx().blob_versioned_hashes
+        );
+        assert_eq!(
+            ctx.balance(sender()).unwrap(),
+            U256::from(ETHER - 21000 * 9 - 2 * GAS_PER_BLOB as u64)
+        );
+    }
+
+    #[test]
+    fn test_calldata_floor() {
+        let tx = Transaction::from(TxEip1559 {
+            data: vec![1; 100],
+            ..transfer()
+        });
+        assert_eq!(tx.intrinsic_gas(), 22600);
+        assert_eq!(tx.floor_data_gas(), 25000);
+
+        let mut ctx = funded_ctx();
+        assert_eq!(
+            ctx.execute_transaction(sender(), &tx).unwrap().gas_used,
+            25000
+        );
+        ctx.spec = Spec::Cancun;
+        let tx = TxEip1559 {
+            nonce: U256::from(1),
+            data: vec![1; 100],
+            ..transfer()
+        };
+        assert_eq!(
+            ctx.execute_transaction(sender(), &tx.into())
+                .unwrap()
+                .gas_used,
+            22600
+        );
+    }
+
+    #[test]
+    fn test_create_transaction() {
+        // Init code returning the single byte 0x00 as runtime code.
+        let init_code = vec![0x60, 0x00, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xf3];
+        let mut ctx = funded_ctx();
+        let tx = TxEip1559 {
+            to: None,
+            data: init_code,
+            gas_limit: 100000,
+            ..transfer()
+        };
+        let receipt = ctx.execute_transaction(sender(), &tx.into()).unwrap();
+        assert!(receipt.success);
+        let contract = receipt.contract_address.unwrap();
+        assert_eq!(ctx.code(contract).unwrap(), vec![0x00]);
+        assert_eq!(ctx.balance(contract).unwrap(), U256::from(100));
+        assert_eq!(ctx.accounts[&sender()].nonce, U256::from(1));
+        assert_eq!(ctx.accounts[&contract].nonce, U256::from(1));
+    }
+
+    #[test]
+    fn test_empty_accounts() {
+        let mut ctx = funded_ctx();
+        let tx = TxEip1559 {
+            value: U256::ZERO,
+            ..transfer()
+        };
+        ctx.execute_transaction(sender(), &tx.into()).unwrap();
+        // Touched by the zero-value call, then deleted as empty.
+        assert!(!ctx.accounts.contains_key(&Address::with_last_byte(2)));
+        assert!(ctx.accounts.contains_key(&Address::with_last_byte(0xcb)));
+
+        // PUSH20 addr EXTCODEHASH PUSH0 MSTORE PUSH1 0x20 PUSH0 RETURN
+        let code_hash = |ctx: &mut MiniEthereum, address: Address| {
+            let code = [
+                &[0x73],
+                address.as_slice(),
+                &[0x3f, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xf3],
+            ];
+            ctx.accounts
+                .entry(Address::with_last_byte(3))
+                .or_default()
+                .code = code.concat();
+            match ctx.call(
+                STACK_SIZE,
+                &mut GasTracker::new(100_000),
+                Address::with_last_byte(3),
+                CallInfo {
+                    origin: sender(),
+                    caller: sender(),
+                    value: U256::ZERO,
+                    data: vec![],
+                    is_static: false,
+                },
+            ) {
+                Ok(ExecutionResult::Returned(data)) => U256::from_be_slice(&data),
+                other => panic!("unexpected result {:?}", other),
+            }
+        };
+        assert_eq!(code_hash(&mut ctx, Address::with_last_byte(2)), U256::ZERO);
+        assert_eq!(
+            code_hash(&mut ctx, sender()),
+            U256::from_be_bytes(keccak(&[]))
+        );
+    }
+
+    /// Sends a zero-value transfer to an account holding nothing but storage, which is left
+    /// empty and deleted along with its storage.
+    fn check_empty_accounts<C: ContextMut<U256> + State>(ctx: &mut C) {
+        let empty = Address::with_last_byte(2);
+        ctx.set_balance(sender(), U256::from(ETHER)).unwrap();
+        ctx.sstore(empty, U256::ZERO, U256::ONE).unwrap();
+        let tx = TxEip1559 {
+            value: U256::ZERO,
+            ..transfer()
+        };
+        let receipt = execute_transaction(ctx, sender(), &tx.into()).unwrap();
+        assert!(receipt.success);
+        assert_eq!(ctx.sload(empty, U256::ZERO).unwrap(), U256::ZERO);
+        assert_eq!(
+            ctx.balance(Address::with_last_byte(0xcb)).unwrap(),
+            U256::from(21000 * 2)
+        );
+    }
+
+    #[test]
+    fn test_empty_accounts_on_every_state() {
+        let empty = Address::with_last_byte(2);
+        let env = funded_ctx().block;
+        let mut ctx = MiniEthereum::new().with_block_env(env.clone());
+        check_empty_accounts(&mut ctx);
+        assert!(!ctx.accounts.contains_key(&empty));
+
+        let mut base = MiniEthereum::new();
+        base.set_storage(empty, U256::ONE, U256::ONE);
+        let mut overlay = Overlay::new(&base).with_block_env(env.clone());
+        check_empty_accounts(&mut overlay);
+        assert_eq!(overlay.sload(empty, U256::ONE).unwrap(), U256::ZERO);
+        let changes = overlay.into_changes();
+        assert_eq!(
+            changes.accounts[&empty],
+            AccountChange {
+                deleted: true,
+                ..Default::default()
+            }
+        );
+        // Applying the deletion removes the account of the base.
+        changes.apply(&mut base).unwrap();
+        assert!(!base.accounts.contains_key(&empty));
+
+        let path = TempPath::new("empty-accounts");
+        let mut db = DiskEthereum::open(&path.0).unwrap().with_block_env(env);
+        check_empty_accounts(&mut db);
+        db.commit().unwrap();
+        assert_eq!(db.sload(empty, U256::ZERO).unwrap(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_context_error_aborts_transaction() {
+        struct FailingSstore;
+        impl Inspector for FailingSstore {
+            fn step(
+                &mut self,
+                machine: &mut Machine<U256>,
+            ) -> Result<Option<ExecutionResult>, ExecError> {
+                if machine.code[machine.pc] == 0x55 {
+                    return Err(ExecError::Context("storage unavailable".into()));
+                }
+                Ok(None)
+            }
+        }
+
+        let mut ctx = funded_ctx();
+        // PUSH1 0x01 PUSH0 SSTORE
+        ctx.accounts
+            .entry(Address::with_last_byte(2))
+            .or_default()
+            .code = vec![0x60, 0x01, 0x5f, 0x55];
+        let before = ctx.accounts.clone();
+        let err = ctx
+            .inspect_transaction(sender(), &transfer().into(), &mut FailingSstore)
+            .unwrap_err();
+        assert!(matches!(err, ExecError::Context(_)));
+        assert_eq!(ctx.accounts, before);
+        assert_eq!(ctx.tx, TxEnv::default());
+        assert!(ctx.journals.is_empty());
+        ctx.execute_transaction(sender(), &transfer().into())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_unknown_block_hash() {
+        let mut ctx = funded_ctx();
+        ctx.block.number = U256::from(10);
+        // PUSH1 0x05 BLOCKHASH PUSH0 MSTORE PUSH1 0x20 PUSH0 RETURN
+        ctx.accounts
+            .entry(Address::with_last_byte(2))
+            .or_default()
+            .code = vec![0x60, 0x05, 0x40, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xf3];
+        let receipt = ctx
+            .execute_transaction(sender(), &transfer().into())
+            .unwrap();
+        assert!(receipt.success);
+        assert_eq!(receipt.output, vec![0; 32]);
+    }
+
+    #[test]
+    fn test_reverted_transaction() {
+        let mut ctx = funded_ctx();
+        // PUSH1 0x01 PUSH1 0x00 SSTORE PUSH1 0x00 PUSH1 0x00 REVERT
+        ctx.accounts
+            .entry(Address::with_last_byte(2))
+            .or_default()
+            .code = vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd];
+        let receipt = ctx
+            .execute_transaction(sender(), &transfer().into())
+            .unwrap();
+        assert!(!receipt.success);
+        assert!(receipt.gas_used > 21000);
+        assert_eq!(ctx.balance(Address::with_last_byte(2)).unwrap(), U256::ZERO);
+        assert_eq!(
+            ctx.sload(Address::with_last_byte(2), U256::ZERO).unwrap(),
+            U256::ZERO
+        );
+        assert_eq!(
+            ctx.balance(sender()).unwrap(),
+            U256::from(ETHER) - U256::from(receipt.gas_used * 9)
+        );
+        assert_eq!(ctx.accounts[&sender()].nonce, U256::from(1));
+    }
+
+    #[test]
+    fn test_exceptional_halt_consumes_gas() {
+        let mut ctx = funded_ctx();
+        // PUSH1 0x00 JUMP, offset 0 is not a JUMPDEST.
+        ctx.accounts
+            .entry(Address::with_last_byte(2))
+            .or_default()
+            .code = vec![0x60, 0x00, 0x56];
+        let receipt = ctx
+            .execute_transaction(sender(), &transfer().into())
+            .unwrap();
+        assert!(!receipt.success);
+        assert_eq!(receipt.gas_used, 30000);
+        assert_eq!(
+            ctx.balance(sender()).unwrap(),
+            U256::from(ETHER - 30000 * 9)
+        );
+    }
+}
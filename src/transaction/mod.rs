@@ -0,0 +1,419 @@
+use alloy_primitives::primitives::Address;
+
+use crate::{
+    error::RlpError,
+    rlp::{self, Decodable, Encodable, Rlp},
+    u256::U256,
+};
+
+mod execute;
+mod receipt;
+mod signed;
+
+pub use execute::{execute_transaction, inspect_transaction};
+pub use receipt::{receipts_root, Bloom, Receipt, BLOOM_SIZE};
+pub use signed::{SignedTransaction, AUTHORIZATION_MAGIC};
+
+pub const TX_BASE_GAS: usize = 21000;
+pub const TX_CREATE_GAS: usize = 32000;
+pub const TX_DATA_ZERO_GAS: usize = 4;
+pub const TX_DATA_NON_ZERO_GAS: usize = 16;
+pub const INITCODE_WORD_GAS: usize = 2;
+pub const MAX_INITCODE_SIZE: usize = 49152;
+pub const ACCESS_LIST_ADDRESS_GAS: usize = 2400;
+pub const ACCESS_LIST_STORAGE_KEY_GAS: usize = 1900;
+/// Price of a calldata token under the EIP-7623 floor, a non-zero byte counts as 4 tokens.
+pub const TOTAL_COST_FLOOR_PER_TOKEN: usize = 10;
+/// Refunds are capped to `gas_used / MAX_REFUND_QUOTIENT` (EIP-3529).
+pub const MAX_REFUND_QUOTIENT: usize = 5;
+pub const GAS_PER_BLOB: usize = 131072;
+pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+/// Intrinsic cost of each EIP-7702 authorization.
+pub const PER_EMPTY_ACCOUNT_COST: usize = 25000;
+/// Part of `PER_EMPTY_ACCOUNT_COST` kept when the authority already exists.
+pub const PER_AUTH_BASE_COST: usize = 12500;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<U256>,
+}
+
+/// A signed EIP-7702 delegation of `authority` (recovered from the signature) to `address`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Authorization {
+    pub chain_id: U256,
+    pub address: Address,
+    pub nonce: U256,
+    pub y_parity: u8,
+    pub r: U256,
+    pub s: U256,
+}
+
+impl Encodable for AccessListItem {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+        self.address.rlp_append(&mut payload);
+        rlp::encode_hash_list(&mut payload, &self.storage_keys);
+        rlp::encode_list_payload(out, &payload);
+    }
+}
+
+impl Decodable for AccessListItem {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        let mut list = rlp.list()?;
+        let item = Self {
+            address: list.decode()?,
+            storage_keys: list.hash_list()?,
+        };
+        list.finish()?;
+        Ok(item)
+    }
+}
+
+impl Encodable for Authorization {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+        self.chain_id.rlp_append(&mut payload);
+        self.address.rlp_append(&mut payload);
+        self.nonce.rlp_append(&mut payload);
+        self.y_parity.rlp_append(&mut payload);
+        self.r.rlp_append(&mut payload);
+        self.s.rlp_append(&mut payload);
+        rlp::encode_list_payload(out, &payload);
+    }
+}
+
+impl Decodable for Authorization {
+    fn rlp_decode(rlp: &mut Rlp) -> Result<Self, RlpError> {
+        let mut list = rlp.list()?;
+        let auth = Self {
+            chain_id: list.decode()?,
+            address: list.decode()?,
+            nonce: list.decode()?,
+            y_parity: list.decode()?,
+            r: list.decode()?,
+            s: list.decode()?,
+        };
+        list.finish()?;
+        Ok(auth)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxLegacy {
+    /// Set for EIP-155 replay-protected transactions.
+    pub chain_id: Option<U256>,
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub gas_limit: usize,
+    /// `None` deploys `data` as init code.
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxEip2930 {
+    pub chain_id: U256,
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub gas_limit: usize,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxEip1559 {
+    pub chain_id: U256,
+    pub nonce: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_limit: usize,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxEip4844 {
+    pub chain_id: U256,
+    pub nonce: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_limit: usize,
+    pub to: Address,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+    pub max_fee_per_blob_gas: U256,
+    pub blob_versioned_hashes: Vec<U256>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxEip7702 {
+    pub chain_id: U256,
+    pub nonce: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_limit: usize,
+    pub to: Address,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+    pub authorization_list: Vec<Authorization>,
+}
+
+/// Unsigned body of every transaction envelope type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transaction {
+    Legacy(TxLegacy),
+    Eip2930(TxEip2930),
+    Eip1559(TxEip1559),
+    Eip4844(TxEip4844),
+    Eip7702(TxEip7702),
+}
+
+impl From<TxLegacy> for Transaction {
+    fn from(tx: TxLegacy) -> Self {
+        Transaction::Legacy(tx)
+    }
+}
+
+impl From<TxEip2930> for Transaction {
+    fn from(tx: TxEip2930) -> Self {
+        Transaction::Eip2930(tx)
+    }
+}
+
+impl From<TxEip1559> for Transaction {
+    fn from(tx: TxEip1559) -> Self {
+        Transaction::Eip1559(tx)
+    }
+}
+
+impl From<TxEip4844> for Transaction {
+    fn from(tx: TxEip4844) -> Self {
+        Transaction::Eip4844(tx)
+    }
+}
+
+impl From<TxEip7702> for Transaction {
+    fn from(tx: TxEip7702) -> Self {
+        Transaction::Eip7702(tx)
+    }
+}
+
+impl Transaction {
+    /// EIP-2718 type byte, 0 for legacy transactions.
+    pub fn tx_type(&self) -> u8 {
+        match self {
+            Transaction::Legacy(_) => 0,
+            Transaction::Eip2930(_) => 1,
+            Transaction::Eip1559(_) => 2,
+            Transaction::Eip4844(_) => 3,
+            Transaction::Eip7702(_) => 4,
+        }
+    }
+    pub fn chain_id(&self) -> Option<U256> {
+        match self {
+            Transaction::Legacy(tx) => tx.chain_id,
+            Transaction::Eip2930(tx) => Some(tx.chain_id),
+            Transaction::Eip1559(tx) => Some(tx.chain_id),
+            Transaction::Eip4844(tx) => Some(tx.chain_id),
+            Transaction::Eip7702(tx) => Some(tx.chain_id),
+        }
+    }
+    pub fn nonce(&self) -> U256 {
+        match self {
+            Transaction::Legacy(tx) => tx.nonce,
+            Transaction::Eip2930(tx) => tx.nonce,
+            Transaction::Eip1559(tx) => tx.nonce,
+            Transaction::Eip4844(tx) => tx.nonce,
+            Transaction::Eip7702(tx) => tx.nonce,
+        }
+    }
+    pub fn gas_limit(&self) -> usize {
+        match self {
+            Transaction::Legacy(tx) => tx.gas_limit,
+            Transaction::Eip2930(tx) => tx.gas_limit,
+            Transaction::Eip1559(tx) => tx.gas_limit,
+            Transaction::Eip4844(tx) => tx.gas_limit,
+            Transaction::Eip7702(tx) => tx.gas_limit,
+        }
+    }
+    pub fn to(&self) -> Option<Address> {
+        match self {
+            Transaction::Legacy(tx) => tx.to,
+            Transaction::Eip2930(tx) => tx.to,
+            Transaction::Eip1559(tx) => tx.to,
+            Transaction::Eip4844(tx) => Some(tx.to),
+            Transaction::Eip7702(tx) => Some(tx.to),
+        }
+    }
+    pub fn value(&self) -> U256 {
+        match self {
+            Transaction::Legacy(tx) => tx.value,
+            Transaction::Eip2930(tx) => tx.value,
+            Transaction::Eip1559(tx) => tx.value,
+            Transaction::Eip4844(tx) => tx.value,
+            Transaction::Eip7702(tx) => tx.value,
+        }
+    }
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Transaction::Legacy(tx) => &tx.data,
+            Transaction::Eip2930(tx) => &tx.data,
+            Transaction::Eip1559(tx) => &tx.data,
+            Transaction::Eip4844(tx) => &tx.data,
+            Transaction::Eip7702(tx) => &tx.data,
+        }
+    }
+    pub fn access_list(&self) -> &[AccessListItem] {
+        match self {
+            Transaction::Legacy(_) => &[],
+            Transaction::Eip2930(tx) => &tx.access_list,
+            Transaction::Eip1559(tx) => &tx.access_list,
+            Transaction::Eip4844(tx) => &tx.access_list,
+            Transaction::Eip7702(tx) => &tx.access_list,
+        }
+    }
+    /// The gas price for legacy and EIP-2930 transactions.
+    pub fn max_fee_per_gas(&self) -> U256 {
+        match self {
+            Transaction::Legacy(tx) => tx.gas_price,
+            Transaction::Eip2930(tx) => tx.gas_price,
+            Transaction::Eip1559(tx) => tx.max_fee_per_gas,
+            Transaction::Eip4844(tx) => tx.max_fee_per_gas,
+            Transaction::Eip7702(tx) => tx.max_fee_per_gas,
+        }
+    }
+    /// The gas price for legacy and EIP-2930 transactions.
+    pub fn max_priority_fee_per_gas(&self) -> U256 {
+        match self {
+            Transaction::Legacy(tx) => tx.gas_price,
+            Transaction::Eip2930(tx) => tx.gas_price,
+            Transaction::Eip1559(tx) => tx.max_priority_fee_per_gas,
+            Transaction::Eip4844(tx) => tx.max_priority_fee_per_gas,
+            Transaction::Eip7702(tx) => tx.max_priority_fee_per_gas,
+        }
+    }
+    pub fn blob_versioned_hashes(&self) -> &[U256] {
+        match self {
+            Transaction::Eip4844(tx) => &tx.blob_versioned_hashes,
+            _ => &[],
+        }
+    }
+    pub fn authorization_list(&self) -> &[Authorization] {
+        match self {
+            Transaction::Eip7702(tx) => &tx.authorization_list,
+            _ => &[],
+        }
+    }
+    pub fn is_create(&self) -> bool {
+        self.to().is_none()
+    }
+    pub fn blob_gas(&self) -> usize {
+        self.blob_versioned_hashes().len() * GAS_PER_BLOB
+    }
+    fn calldata_tokens(&self) -> usize {
+        let zeros = self.data().iter().filter(|b| **b == 0).count();
+        zeros + (self.data().len() - zeros) * 4
+    }
+    /// Gas charged before any code runs.
+    pub fn intrinsic_gas(&self) -> usize {
+        let data = self.data();
+        let zeros = data.iter().filter(|b| **b == 0).count();
+        let mut gas =
+            TX_BASE_GAS + zeros * TX_DATA_ZERO_GAS + (data.len() - zeros) * TX_DATA_NON_ZERO_GAS;
+        if self.is_create() {
+            gas += TX_CREATE_GAS + data.len().div_ceil(32) * INITCODE_WORD_GAS;
+        }
+        for item in self.access_list() {
+            gas += ACCESS_LIST_ADDRESS_GAS + item.storage_keys.len() * ACCESS_LIST_STORAGE_KEY_GAS;
+        }
+        gas + self.authorization_list().len() * PER_EMPTY_ACCOUNT_COST
+    }
+    /// Minimum gas a transaction pays for its calldata (EIP-7623).
+    pub fn floor_data_gas(&self) -> usize {
+        TX_BASE_GAS + self.calldata_tokens() * TOTAL_COST_FLOOR_PER_TOKEN
+    }
+    pub fn effective_gas_price(&self, base_fee: U256) -> U256 {
+        std::cmp::min(
+            self.max_fee_per_gas(),
+            base_fee.saturating_add(self.max_priority_fee_per_gas()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_list_rlp() {
+        let access_list = vec![
+            AccessListItem {
+                address: Address::with_last_byte(3),
+                storage_keys: vec![U256::ZERO, U256::from(1)],
+            },
+            AccessListItem::default(),
+        ];
+        let mut encoded = Vec::new();
+        rlp::encode_list(&mut encoded, &access_list);
+        assert_eq!(
+            Rlp::new(&encoded).decode_list::<AccessListItem>(),
+            Ok(access_list)
+        );
+        let auth = Authorization {
+            chain_id: U256::from(1),
+            address: Address::with_last_byte(4),
+            nonce: U256::from(2),
+            y_parity: 1,
+            r: U256::MAX,
+            s: U256::from(5),
+        };
+        assert_eq!(rlp::decode(&rlp::encode(&auth)), Ok(auth));
+    }
+
+    #[test]
+    fn test_intrinsic_gas() {
+        let tx = TxEip1559 {
+            to: Some(Address::with_last_byte(2)),
+            data: vec![0, 1, 0, 2],
+            ..Default::default()
+        };
+        assert_eq!(
+            Transaction::from(tx.clone()).intrinsic_gas(),
+            21000 + 2 * 4 + 2 * 16
+        );
+        let tx = TxEip1559 {
+            access_list: vec![AccessListItem {
+                address: Address::with_last_byte(3),
+                storage_keys: vec![U256::from(1), U256::from(2)],
+            }],
+            ..tx
+        };
+        assert_eq!(
+            Transaction::from(tx).intrinsic_gas(),
+            21040 + 2400 + 2 * 1900
+        );
+        let tx = TxEip1559 {
+            to: None,
+            data: vec![1; 33],
+            ..Default::default()
+        };
+        assert_eq!(
+            Transaction::from(tx).intrinsic_gas(),
+            21000 + 33 * 16 + 32000 + 2 * 2
+        );
+        let tx = TxEip7702 {
+            authorization_list: vec![Authorization::default(); 2],
+            ..Default::default()
+        };
+        assert_eq!(Transaction::from(tx).intrinsic_gas(), 21000 + 2 * 25000);
+    }
+}
@@ -0,0 +1,227 @@
+use alloy_primitives::primitives::Address;
+
+use crate::{
+    context::Log,
+    keccak::keccak,
+    rlp::{encode_bytes, encode_hash_list, encode_list, encode_list_payload, Encodable},
+    trie::ordered_trie_root,
+};
+
+pub const BLOOM_SIZE: usize = 256;
+
+/// 2048-bit filter of the addresses and topics of a set of logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bloom(pub [u8; BLOOM_SIZE]);
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self([0; BLOOM_SIZE])
+    }
+}
+
+impl Bloom {
+    /// Bits set for an input: the low 11 bits of the first three byte pairs of its hash.
+    fn bits(input: &[u8]) -> [(usize, u8); 3] {
+        let hash = keccak(input);
+        [0, 2, 4].map(|i| {
+            let bit = (((hash[i] as usize) << 8) | hash[i + 1] as usize) & 2047;
+            (BLOOM_SIZE - 1 - bit / 8, 1 << (bit % 8))
+        })
+    }
+
+    pub fn from_logs(logs: &[Log]) -> Self {
+        let mut bloom = Self::default();
+        for log in logs {
+            bloom.accrue_log(log);
+        }
+        bloom
+    }
+
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (byte, mask) in Self::bits(input) {
+            self.0[byte] |= mask;
+        }
+    }
+
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(log.address.as_slice());
+        for topic in &log.topics {
+            self.accrue(&topic.to_be_bytes::<32>());
+        }
+    }
+
+    pub fn accrue_bloom(&mut self, other: &Bloom) {
+        for (byte, other) in self.0.iter_mut().zip(other.0) {
+            *byte |= other;
+        }
+    }
+
+    /// False positives are possible, false negatives are not.
+    pub fn contains_input(&self, input: &[u8]) -> bool {
+        Self::bits(input)
+            .iter()
+            .all(|(byte, mask)| self.0[*byte] & mask != 0)
+    }
+}
+
+impl Encodable for Bloom {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        encode_bytes(out, &self.0);
+    }
+}
+
+impl Encodable for Log {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+        self.address.rlp_append(&mut payload);
+        encode_hash_list(&mut payload, &self.topics);
+        encode_bytes(&mut payload, &self.data);
+        encode_list_payload(out, &payload);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Receipt {
+    pub tx_type: u8,
+    pub success: bool,
+    pub gas_used: usize,
+    /// Gas used by the block up to and including this transaction, equal to `gas_used`
+    /// for transactions executed on their own.
+    pub cumulative_gas_used: usize,
+    pub logs: Vec<Log>,
+    pub output: Vec<u8>,
+    pub contract_address: Option<Address>,
+}
+
+impl Receipt {
+    pub fn logs_bloom(&self) -> Bloom {
+        Bloom::from_logs(&self.logs)
+    }
+
+    /// EIP-2718 encoding, `rlp([status, cumulative_gas_used, logs_bloom, logs])` prefixed
+    /// with the transaction type unless legacy.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        self.success.rlp_append(&mut payload);
+        self.cumulative_gas_used.rlp_append(&mut payload);
+        self.logs_bloom().rlp_append(&mut payload);
+        encode_list(&mut payload, &self.logs);
+        let mut out = Vec::new();
+        if self.tx_type != 0 {
+            out.push(self.tx_type);
+        }
+        encode_list_payload(&mut out, &payload);
+        out
+    }
+}
+
+/// Receipts root of a block, the receipts must carry their cumulative gas.
+pub fn receipts_root(receipts: &[Receipt]) -> [u8; 32] {
+    ordered_trie_root(&receipts.iter().map(Receipt::encode).collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::u256::U256;
+    use alloy_primitives::primitives::hex;
+
+    fn receipts() -> Vec<Receipt> {
+        let log1 = Log {
+            address: Address::with_last_byte(0x42),
+            topics: vec![U256::from(1)],
+            data: vec![0x2a; 32],
+        };
+        let log2 = Log {
+            address: Address::with_last_byte(0xaa),
+            topics: vec![U256::from(2), U256::from(3)],
+            data: vec![],
+        };
+        vec![
+            Receipt {
+                tx_type: 0,
+                success: true,
+                cumulative_gas_used: 21000,
+                ..Default::default()
+            },
+            Receipt {
+                tx_type: 2,
+                success: true,
+                cumulative_gas_used: 50000,
+                logs: vec![log1.clone()],
+                ..Default::default()
+            },
+            Receipt {
+                tx_type: 3,
+                success: false,
+                cumulative_gas_used: 80000,
+                logs: vec![log1, log2],
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_logs_bloom() {
+        let receipts = receipts();
+        assert_eq!(receipts[0].logs_bloom(), Bloom::default());
+        let bloom = receipts[2].logs_bloom();
+        assert_eq!(
+            bloom.0,
+            hex!("04000004000000000000000000000000000000000000000000000000000000000000000000000000000000000000002000000000020000000000000000040000000000000000000000000000000000000000000000440000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000100000008000000000000800000000000000000400000000000000000000000000000000000000000000000000000000000000040000000000000000000000000400000000000000000008000000000000000001000")
+        );
+        assert!(bloom.contains_input(Address::with_last_byte(0xaa).as_slice()));
+        assert!(bloom.contains_input(&U256::from(3).to_be_bytes::<32>()));
+        assert!(!receipts[1]
+            .logs_bloom()
+            .contains_input(Address::with_last_byte(0xaa).as_slice()));
+
+        let mut block_bloom = receipts[1].logs_bloom();
+        block_bloom.accrue_bloom(&Bloom::from_logs(&receipts[2].logs[1..]));
+        assert_eq!(block_bloom, bloom);
+    }
+
+    #[test]
+    fn test_receipt_encoding() {
+        let receipts = receipts();
+        let encoded = receipts[1].encode();
+        assert_eq!(encoded[..8], hex!("02f901630182c350"));
+        assert_eq!(
+            encoded[encoded.len() - 92..],
+            hex!("f85af858940000000000000000000000000000000000000042e1a00000000000000000000000000000000000000000000000000000000000000001a02a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a")
+        );
+        assert_eq!(receipts[0].encode()[..6], hex!("f90108018252"));
+        assert_eq!(receipts[2].encode()[..8], hex!("03f901c080830138"));
+    }
+
+    #[test]
+    fn test_receipts_root() {
+        let receipts = receipts();
+        assert_eq!(
+            receipts_root(&receipts[..1]),
+            hex!("056b23fbba480696b65fe5a59b8f2148a1299103c4f57df839233af2cf4ca2d2")
+        );
+        assert_eq!(
+            receipts_root(&receipts),
+            hex!("5fd8be53d4a22b3b487cf7bd4d85750e8f9cd89d05f171080beda06101812ace")
+        );
+
+        let many: Vec<Receipt> = (0..200)
+            .map(|i| Receipt {
+                tx_type: 1,
+                success: i % 3 != 0,
+                cumulative_gas_used: 21000 * (i + 1),
+                logs: if i % 5 == 0 {
+                    receipts[2].logs[1..].to_vec()
+                } else {
+                    vec![]
+                },
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(
+            receipts_root(&many),
+            hex!("71d4501e961b4c1e1fd219ba7065a23c0e482a363b8f13cfe2807a760ed9b12e")
+        );
+    }
+}
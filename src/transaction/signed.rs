@@ -0,0 +1,547 @@
+use alloy_primitives::primitives::Address;
+
+use super::*;
+use crate::{
+    context::MiniEthereum,
+    error::{ExecError, InvalidTransaction},
+    keccak::keccak,
+    signature::{recover_address, Signature},
+};
+
+/// Prefix of the message signed by an EIP-7702 authority.
+pub const AUTHORIZATION_MAGIC: u8 = 0x05;
+
+impl Authorization {
+    pub fn signature_hash(&self) -> [u8; 32] {
+        let mut payload = Vec::new();
+        self.chain_id.rlp_append(&mut payload);
+        self.address.rlp_append(&mut payload);
+        self.nonce.rlp_append(&mut payload);
+        let mut msg = vec![AUTHORIZATION_MAGIC];
+        rlp::encode_list_payload(&mut msg, &payload);
+        keccak(&msg)
+    }
+    pub fn signature(&self) -> Signature {
+        Signature {
+            y_parity: self.y_parity != 0,
+            r: self.r,
+            s: self.s,
+        }
+    }
+    /// The account delegating its code, `None` if the signature is invalid.
+    pub fn recover_authority(&self) -> Option<Address> {
+        let signature = self.signature();
+        if self.y_parity > 1 || !signature.is_low_s() {
+            return None;
+        }
+        recover_address(&self.signature_hash(), &signature)
+    }
+}
+
+impl Transaction {
+    /// Appends the fields of the transaction body, without signature, in envelope order.
+    fn rlp_append_fields(&self, out: &mut Vec<u8>) {
+        match self {
+            Transaction::Legacy(tx) => {
+                tx.nonce.rlp_append(out);
+                tx.gas_price.rlp_append(out);
+                tx.gas_limit.rlp_append(out);
+                tx.to.rlp_append(out);
+                tx.value.rlp_append(out);
+                tx.data.rlp_append(out);
+            }
+            Transaction::Eip2930(tx) => {
+                tx.chain_id.rlp_append(out);
+                tx.nonce.rlp_append(out);
+                tx.gas_price.rlp_append(out);
+                tx.gas_limit.rlp_append(out);
+                tx.to.rlp_append(out);
+                tx.value.rlp_append(out);
+                tx.data.rlp_append(out);
+                rlp::encode_list(out, &tx.access_list);
+            }
+            Transaction::Eip1559(tx) => {
+                tx.chain_id.rlp_append(out);
+                tx.nonce.rlp_append(out);
+                tx.max_priority_fee_per_gas.rlp_append(out);
+                tx.max_fee_per_gas.rlp_append(out);
+                tx.gas_limit.rlp_append(out);
+                tx.to.rlp_append(out);
+                tx.value.rlp_append(out);
+                tx.data.rlp_append(out);
+                rlp::encode_list(out, &tx.access_list);
+            }
+            Transaction::Eip4844(tx) => {
+                tx.chain_id.rlp_append(out);
+                tx.nonce.rlp_append(out);
+                tx.max_priority_fee_per_gas.rlp_append(out);
+                tx.max_fee_per_gas.rlp_append(out);
+                tx.gas_limit.rlp_append(out);
+                tx.to.rlp_append(out);
+                tx.value.rlp_append(out);
+                tx.data.rlp_append(out);
+                rlp::encode_list(out, &tx.access_list);
+                tx.max_fee_per_blob_gas.rlp_append(out);
+                rlp::encode_hash_list(out, &tx.blob_versioned_hashes);
+            }
+            Transaction::Eip7702(tx) => {
+                tx.chain_id.rlp_append(out);
+                tx.nonce.rlp_append(out);
+                tx.max_priority_fee_per_gas.rlp_append(out);
+                tx.max_fee_per_gas.rlp_append(out);
+                tx.gas_limit.rlp_append(out);
+                tx.to.rlp_append(out);
+                tx.value.rlp_append(out);
+                tx.data.rlp_append(out);
+                rlp::encode_list(out, &tx.access_list);
+                rlp::encode_list(out, &tx.authorization_list);
+            }
+        }
+    }
+
+    /// Reads the fields of a typed transaction body, legacy transactions are handled by the
+    /// caller since their chain id lives in the signature.
+    fn decode_typed_fields(tx_type: u8, list: &mut Rlp) -> Result<Self, RlpError> {
+        Ok(match tx_type {
+            1 => TxEip2930 {
+                chain_id: list.decode()?,
+                nonce: list.decode()?,
+                gas_price: list.decode()?,
+                gas_limit: list.decode()?,
+                to: list.decode()?,
+                value: list.decode()?,
+                data: list.decode()?,
+                access_list: list.decode_list()?,
+            }
+            .into(),
+            2 => TxEip1559 {
+                chain_id: list.decode()?,
+                nonce: list.decode()?,
+                max_priority_fee_per_gas: list.decode()?,
+                max_fee_per_gas: list.decode()?,
+                gas_limit: list.decode()?,
+                to: list.decode()?,
+                value: list.decode()?,
+                data: list.decode()?,
+                access_list: list.decode_list()?,
+            }
+            .into(),
+            3 => TxEip4844 {
+                chain_id: list.decode()?,
+                nonce: list.decode()?,
+                max_priority_fee_per_gas: list.decode()?,
+                max_fee_per_gas: list.decode()?,
+                gas_limit: list.decode()?,
+                to: list.decode()?,
+                value: list.decode()?,
+                data: list.decode()?,
+                access_list: list.decode_list()?,
+                max_fee_per_blob_gas: list.decode()?,
+                blob_versioned_hashes: list.hash_list()?,
+            }
+            .into(),
+            4 => TxEip7702 {
+                chain_id: list.decode()?,
+                nonce: list.decode()?,
+                max_priority_fee_per_gas: list.decode()?,
+                max_fee_per_gas: list.decode()?,
+                gas_limit: list.decode()?,
+                to: list.decode()?,
+                value: list.decode()?,
+                data: list.decode()?,
+                access_list: list.decode_list()?,
+                authorization_list: list.decode_list()?,
+            }
+            .into(),
+            _ => return Err(RlpError::Custom("unknown transaction type")),
+        })
+    }
+
+    /// Hash signed by the sender of the transaction.
+    pub fn signature_hash(&self) -> [u8; 32] {
+        let mut payload = Vec::new();
+        self.rlp_append_fields(&mut payload);
+        let mut msg = Vec::new();
+        match self {
+            Transaction::Legacy(tx) => {
+                // EIP-155 appends the chain id and two empty values.
+                if let Some(chain_id) = tx.chain_id {
+                    chain_id.rlp_append(&mut payload);
+                    0u64.rlp_append(&mut payload);
+                    0u64.rlp_append(&mut payload);
+                }
+            }
+            _ => msg.push(self.tx_type()),
+        }
+        rlp::encode_list_payload(&mut msg, &payload);
+        keccak(&msg)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedTransaction {
+    pub tx: Transaction,
+    pub signature: Signature,
+}
+
+impl SignedTransaction {
+    /// Decodes an EIP-2718 envelope, as returned by `eth_getRawTransactionByHash` or sent
+    /// with `eth_sendRawTransaction`. Blob transactions may carry their sidecar, which is
+    /// dropped.
+    pub fn decode(raw: &[u8]) -> Result<Self, RlpError> {
+        let first = *raw.first().ok_or(RlpError::UnexpectedEof)?;
+        if first >= 0xc0 {
+            return Self::decode_legacy(raw);
+        }
+        let mut rlp = Rlp::new(&raw[1..]);
+        let mut list = rlp.list()?;
+        rlp.finish()?;
+        if first == 3 && list.is_list()? {
+            list = list.list()?;
+        }
+        let tx = Transaction::decode_typed_fields(first, &mut list)?;
+        let y_parity: bool = list.decode()?;
+        let signature = Signature {
+            y_parity,
+            r: list.decode()?,
+            s: list.decode()?,
+        };
+        list.finish()?;
+        Ok(Self { tx, signature })
+    }
+
+    fn decode_legacy(raw: &[u8]) -> Result<Self, RlpError> {
+        let mut rlp = Rlp::new(raw);
+        let mut list = rlp.list()?;
+        rlp.finish()?;
+        let mut tx = TxLegacy {
+            chain_id: None,
+            nonce: list.decode()?,
+            gas_price: list.decode()?,
+            gas_limit: list.decode()?,
+            to: list.decode()?,
+            value: list.decode()?,
+            data: list.decode()?,
+        };
+        // Chain ids are 256 bits, so `v` may not fit in 64 bits.
+        let v: U256 = list.decode()?;
+        let y_parity = if v == U256::from(27) || v == U256::from(28) {
+            v == U256::from(28)
+        } else if v >= U256::from(35) {
+            let offset = v - U256::from(35);
+            tx.chain_id = Some(offset / U256::from(2));
+            offset.bit(0)
+        } else {
+            return Err(RlpError::Custom("invalid signature v value"));
+        };
+        let signature = Signature {
+            y_parity,
+            r: list.decode()?,
+            s: list.decode()?,
+        };
+        list.finish()?;
+        Ok(Self {
+            tx: tx.into(),
+            signature,
+        })
+    }
+
+    /// EIP-2718 encoding of the transaction, the inverse of `decode`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        self.tx.rlp_append_fields(&mut payload);
+        let mut out = Vec::new();
+        match &self.tx {
+            Transaction::Legacy(tx) => {
+                let v = match tx.chain_id {
+                    Some(chain_id) => chain_id * U256::from(2) + U256::from(35),
+                    None => U256::from(27),
+                } + U256::from(self.signature.y_parity as u8);
+                v.rlp_append(&mut payload);
+            }
+            tx => {
+                out.push(tx.tx_type());
+                self.signature.y_parity.rlp_append(&mut payload);
+            }
+        }
+        self.signature.r.rlp_append(&mut payload);
+        self.signature.s.rlp_append(&mut payload);
+        rlp::encode_list_payload(&mut out, &payload);
+        out
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        keccak(&self.encode())
+    }
+
+    pub fn recover_sender(&self) -> Result<Address, InvalidTransaction> {
+        if !self.signature.is_low_s() {
+            return Err(InvalidTransaction::InvalidSignature);
+        }
+        recover_address(&self.tx.signature_hash(), &self.signature)
+            .ok_or(InvalidTransaction::InvalidSignature)
+    }
+}
+
+impl MiniEthereum {
+    /// Decodes a raw signed transaction, recovers its sender and executes it.
+    pub fn execute_raw_transaction(&mut self, raw: &[u8]) -> Result<Receipt, ExecError> {
+        let signed = SignedTransaction::decode(raw).map_err(InvalidTransaction::InvalidEncoding)?;
+        let sender = signed.recover_sender()?;
+        self.execute_transaction(sender, &signed.tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::primitives::{address, hex};
+    use k256::ecdsa::SigningKey;
+
+    use crate::{
+        context::{Context, DELEGATION_PREFIX},
+        env::BlockEnv,
+        signature::public_key_to_address,
+    };
+
+    fn sign(key: &SigningKey, tx: Transaction) -> SignedTransaction {
+        let (sig, recovery_id) = key.sign_prehash_recoverable(&tx.signature_hash()).unwrap();
+        SignedTransaction {
+            tx,
+            signature: Signature {
+                y_parity: recovery_id.is_y_odd(),
+                r: U256::from_be_slice(&sig.r().to_bytes()),
+                s: U256::from_be_slice(&sig.s().to_bytes()),
+            },
+        }
+    }
+
+    #[test]
+    fn test_eip155_example() {
+        let raw = hex!("f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83");
+        let signed = SignedTransaction::decode(&raw).unwrap();
+        assert_eq!(
+            signed.tx,
+            TxLegacy {
+                chain_id: Some(U256::from(1)),
+                nonce: U256::from(9),
+                gas_price: U256::from(20000000000u64),
+                gas_limit: 21000,
+                to: Some(address!("3535353535353535353535353535353535353535")),
+                value: U256::from(1000000000000000000u64),
+                data: vec![],
+            }
+            .into()
+        );
+        assert_eq!(
+            signed.tx.signature_hash(),
+            hex!("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53")
+        );
+        assert_eq!(
+            signed.recover_sender(),
+            Ok(address!("9d8A62f656a8d1615C1294fd71e9CFb3E4855A4F"))
+        );
+        assert_eq!(signed.encode(), raw);
+    }
+
+    #[test]
+    fn test_legacy_large_chain_id() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let tx = TxLegacy {
+            chain_id: Some(U256::from(u64::MAX) * U256::from(1000)),
+            gas_limit: 21000,
+            ..Default::default()
+        };
+        let signed = sign(&key, tx.into());
+        let decoded = SignedTransaction::decode(&signed.encode()).unwrap();
+        assert_eq!(decoded, signed);
+        assert_eq!(
+            decoded.recover_sender(),
+            Ok(public_key_to_address(key.verifying_key()))
+        );
+    }
+
+    #[test]
+    fn test_decode_mainnet_transactions() {
+        let raw = hex!("f9015482078b8505d21dba0083022ef1947a250d5630b4cf539739df2c5dacb4c659f2488d880c46549a521b13d8b8e47ff36ab50000000000000000000000000000000000000000000066ab5a608bd00a23f2fe000000000000000000000000000000000000000000000000000000000000008000000000000000000000000048c04ed5691981c42154c6167398f95e8f38a7ff00000000000000000000000000000000000000000000000000000000632ceac70000000000000000000000000000000000000000000000000000000000000002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc20000000000000000000000006c6ee5e31d828de241282b9606c8e98ea48526e225a0c9077369501641a92ef7399ff81c21639ed4fd8fc69cb793cfa1dbfab342e10aa0615facb2f1bcf3274a354cfe384a38d0cc008a11c2dd23a69111bc6930ba27a8");
+        let signed = SignedTransaction::decode(&raw).unwrap();
+        assert_eq!(signed.tx.chain_id(), Some(U256::from(1)));
+        assert_eq!(
+            signed.hash(),
+            hex!("280cde7cdefe4b188750e76c888f13bd05ce9a4d7767730feefe8a0e50ca6fc4")
+        );
+        assert_eq!(
+            signed.recover_sender(),
+            Ok(address!("a12e1462d0ceD572f396F58B6E2D03894cD7C8a4"))
+        );
+
+        let raw = hex!("02f86f0102843b9aca0085029e7822d68298f094d9e1459a7a482635700cbc20bbaf52d495ab9c9680841b55ba3ac080a0c199674fcb29f353693dd779c017823b954b3c69dffa3cd6b2a6ff7888798039a028ca912de909e7e6cdef9cdcaf24c54dd8c1032946dfa1d85c206b32a9064fe8");
+        let signed = SignedTransaction::decode(&raw).unwrap();
+        assert_eq!(signed.tx.tx_type(), 2);
+        assert_eq!(
+            signed.hash(),
+            hex!("ce4dc6d7a7549a98ee3b071b67e970879ff51b5b95d1c340bacd80fa1e1aab31")
+        );
+        assert_eq!(
+            signed.recover_sender(),
+            Ok(address!("001e2b7dE757bA469a57bF6b23d982458a07eFcE"))
+        );
+        assert_eq!(signed.encode(), raw);
+
+        // Sepolia blob transaction.
+        let raw = hex!("03f9011d83aa36a7820fa28477359400852e90edd0008252089411e9ca82a3a762b4b5bd264d4173a242e7a770648080c08504a817c800f8a5a0012ec3d6f66766bedb002a190126b3549fce0047de0d4c25cffce0dc1c57921aa00152d8e24762ff22b1cfd9f8c0683786a7ca63ba49973818b3d1e9512cd2cec4a0013b98c6c83e066d5b14af2b85199e3d4fc7d1e778dd53130d180f5077e2d1c7a001148b495d6e859114e670ca54fb6e2657f0cbae5b08063605093a4b3dc9f8f1a0011ac212f13c5dff2b2c6b600a79635103d6f580a4221079951181b25c7e654901a0c8de4cced43169f9aa3d36506363b2d2c44f6c49fc1fd91ea114c86f3757077ea01e11fdd0d1934eda0492606ee0bb80a7bf8f35cc5f86ec60fe5031ba48bfd544");
+        let signed = SignedTransaction::decode(&raw).unwrap();
+        assert_eq!(signed.tx.blob_versioned_hashes().len(), 5);
+        assert_eq!(
+            signed.hash(),
+            hex!("9a22ccb0029bc8b0ddd073be1a1d923b7ae2b2ea52100bae0db4424f9107e9c0")
+        );
+        assert_eq!(
+            signed.recover_sender(),
+            Ok(address!("A83C816D4f9b2783761a22BA6FADB0eB0606D7B2"))
+        );
+    }
+
+    #[test]
+    fn test_sign_and_recover() {
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let sender = public_key_to_address(key.verifying_key());
+        let access_list = vec![AccessListItem {
+            address: Address::with_last_byte(3),
+            storage_keys: vec![U256::ZERO],
+        }];
+        for tx in [
+            Transaction::from(TxEip2930 {
+                chain_id: U256::from(1),
+                gas_price: U256::from(10),
+                gas_limit: 30000,
+                to: None,
+                data: vec![0x60, 0x00],
+                access_list: access_list.clone(),
+                ..Default::default()
+            }),
+            Transaction::from(TxEip7702 {
+                chain_id: U256::from(1),
+                to: Address::with_last_byte(2),
+                access_list,
+                authorization_list: vec![Authorization {
+                    chain_id: U256::from(1),
+                    address: Address::with_last_byte(4),
+                    r: U256::from(1),
+                    s: U256::from(2),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+        ] {
+            let signed = sign(&key, tx);
+            let decoded = SignedTransaction::decode(&signed.encode()).unwrap();
+            assert_eq!(decoded, signed);
+            assert_eq!(decoded.recover_sender(), Ok(sender));
+        }
+    }
+
+    #[test]
+    fn test_invalid_raw_transactions() {
+        assert_eq!(
+            SignedTransaction::decode(&hex!("05c0")),
+            Err(RlpError::Custom("unknown transaction type"))
+        );
+        assert_eq!(SignedTransaction::decode(&[]), Err(RlpError::UnexpectedEof));
+
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let mut signed = sign(&key, TxEip1559::default().into());
+        signed.signature.s = U256::MAX - signed.signature.s;
+        assert_eq!(
+            signed.recover_sender(),
+            Err(InvalidTransaction::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_execute_raw_transaction() {
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let sender = public_key_to_address(key.verifying_key());
+        let mut ctx =
+            MiniEthereum::new().with_block_env(BlockEnv::default().with_chain_id(U256::from(1)));
+        ctx.accounts.entry(sender).or_default().value = U256::from(1000000u64);
+        let tx = TxLegacy {
+            chain_id: Some(U256::from(1)),
+            gas_price: U256::from(1),
+            gas_limit: 21000,
+            to: Some(Address::with_last_byte(2)),
+            value: U256::from(5),
+            ..Default::default()
+        };
+        let raw = sign(&key, tx.clone().into()).encode();
+        assert!(ctx.execute_raw_transaction(&raw).unwrap().success);
+        assert_eq!(ctx.accounts[&sender].nonce, U256::from(1));
+
+        // Replaying the transaction on another chain is rejected.
+        let raw = sign(
+            &key,
+            TxLegacy {
+                chain_id: Some(U256::from(5)),
+                nonce: U256::from(1),
+                ..tx
+            }
+            .into(),
+        )
+        .encode();
+        assert_eq!(
+            ctx.execute_raw_transaction(&raw),
+            Err(ExecError::InvalidTransaction(
+                InvalidTransaction::ChainIdMismatch
+            ))
+        );
+        assert_eq!(
+            ctx.execute_raw_transaction(&hex!("f800")),
+            Err(ExecError::InvalidTransaction(
+                InvalidTransaction::InvalidEncoding(RlpError::NonCanonicalSize)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_execute_authorization() {
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let sender = public_key_to_address(key.verifying_key());
+        let delegate = Address::with_last_byte(0xde);
+        let mut ctx = MiniEthereum::new();
+        ctx.accounts.entry(sender).or_default().value = U256::from(1000000u64);
+        // PUSH1 0x01 PUSH1 0x00 SSTORE STOP
+        ctx.accounts.entry(delegate).or_default().code = vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x00];
+
+        // The sender nonce is bumped before the authorization list is processed.
+        let mut auth = Authorization {
+            address: delegate,
+            nonce: U256::from(1),
+            ..Default::default()
+        };
+        let (sig, recovery_id) = key
+            .sign_prehash_recoverable(&auth.signature_hash())
+            .unwrap();
+        auth.y_parity = recovery_id.is_y_odd() as u8;
+        auth.r = U256::from_be_slice(&sig.r().to_bytes());
+        auth.s = U256::from_be_slice(&sig.s().to_bytes());
+        assert_eq!(auth.recover_authority(), Some(sender));
+
+        let tx = TxEip7702 {
+            to: sender,
+            gas_limit: 100000,
+            max_fee_per_gas: U256::from(1),
+            authorization_list: vec![auth.clone(), auth],
+            ..Default::default()
+        };
+        let receipt = ctx.execute_transaction(sender, &tx.into()).unwrap();
+        assert!(receipt.success);
+        assert_eq!(
+            ctx.code(sender).unwrap(),
+            [&DELEGATION_PREFIX[..], delegate.as_slice()].concat()
+        );
+        // The second copy of the authorization has a stale nonce and is skipped.
+        assert_eq!(ctx.accounts[&sender].nonce, U256::from(2));
+        assert_eq!(ctx.sload(sender, U256::ZERO).unwrap(), U256::from(1));
+        assert_eq!(ctx.sload(delegate, U256::ZERO).unwrap(), U256::ZERO);
+    }
+}
@@ -0,0 +1,581 @@
+use alloy_primitives::primitives::hex;
+
+use crate::{
+    error::{ProofError, RlpError},
+    keccak::keccak,
+    rlp::{self, encode_bytes, encode_list_payload, Rlp},
+};
+
+/// Root of a trie without any entry, `keccak(rlp(""))`.
+pub const EMPTY_ROOT: [u8; 32] =
+    hex!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+
+/// Nodes keep their reference in the parent once computed, so that only the nodes on the
+/// path of a modified key are encoded and hashed again.
+#[derive(Debug, Clone, Default)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+        reference: Option<Vec<u8>>,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<Node>,
+        reference: Option<Vec<u8>>,
+    },
+    Branch {
+        children: Box<[Node; 16]>,
+        value: Option<Vec<u8>>,
+        reference: Option<Vec<u8>>,
+    },
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// Inverse of `hex_prefix`, returning the path and whether it belongs to a leaf.
+fn decode_hex_prefix(bytes: &[u8]) -> Result<(Vec<u8>, bool), RlpError> {
+    let (first, rest) = bytes
+        .split_first()
+        .ok_or(RlpError::Custom("empty node path"))?;
+    let mut path = match first >> 4 {
+        0 | 2 if first & 0x0f == 0 => vec![],
+        1 | 3 => vec![first & 0x0f],
+        _ => return Err(RlpError::Custom("invalid node path flag")),
+    };
+    path.extend(nibbles(rest));
+    Ok((path, first & 0x20 != 0))
+}
+
+/// Compact encoding of a nibble path, flagging leaves and odd lengths in the first nibble.
+fn hex_prefix(path: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+impl Node {
+    fn leaf(path: Vec<u8>, value: Vec<u8>) -> Node {
+        Node::Leaf {
+            path,
+            value,
+            reference: None,
+        }
+    }
+
+    fn branch(children: Box<[Node; 16]>, value: Option<Vec<u8>>) -> Node {
+        Node::Branch {
+            children,
+            value,
+            reference: None,
+        }
+    }
+
+    fn empty_branch() -> Node {
+        Node::branch(Box::new(std::array::from_fn(|_| Node::Empty)), None)
+    }
+
+    /// Hangs the node below `prefix`, merging it into the path of a leaf or an extension.
+    fn under(self, prefix: &[u8]) -> Node {
+        if prefix.is_empty() {
+            return self;
+        }
+        match self {
+            Node::Empty => Node::Empty,
+            Node::Leaf { path, value, .. } => Node::leaf([prefix, &path].concat(), value),
+            Node::Extension { path, child, .. } => Node::Extension {
+                path: [prefix, &path].concat(),
+                child,
+                reference: None,
+            },
+            branch @ Node::Branch { .. } => Node::Extension {
+                path: prefix.to_vec(),
+                child: Box::new(branch),
+                reference: None,
+            },
+        }
+    }
+
+    fn insert(self, path: &[u8], value: Vec<u8>) -> Node {
+        match self {
+            Node::Empty => Node::leaf(path.to_vec(), value),
+            Node::Leaf {
+                path: leaf_path,
+                value: leaf_value,
+                ..
+            } => {
+                if leaf_path == path {
+                    return Node::leaf(leaf_path, value);
+                }
+                let common = common_prefix(&leaf_path, path);
+                Node::empty_branch()
+                    .insert(&leaf_path[common..], leaf_value)
+                    .insert(&path[common..], value)
+                    .under(&path[..common])
+            }
+            Node::Extension {
+                path: ext_path,
+                child,
+                ..
+            } => {
+                let common = common_prefix(&ext_path, path);
+                if common == ext_path.len() {
+                    return child.insert(&path[common..], value).under(&ext_path);
+                }
+                let Node::Branch { mut children, .. } = Node::empty_branch() else {
+                    unreachable!()
+                };
+                children[ext_path[common] as usize] = child.under(&ext_path[common + 1..]);
+                Node::branch(children, None)
+                    .insert(&path[common..], value)
+                    .under(&path[..common])
+            }
+            Node::Branch {
+                mut children,
+                value: branch_value,
+                ..
+            } => match path.split_first() {
+                None => Node::branch(children, Some(value)),
+                Some((nibble, rest)) => {
+                    let child = std::mem::take(&mut children[*nibble as usize]);
+                    children[*nibble as usize] = child.insert(rest, value);
+                    Node::branch(children, branch_value)
+                }
+            },
+        }
+    }
+
+    /// Removes a key known to be present, collapsing the branches left with a single entry.
+    fn remove(self, path: &[u8]) -> Node {
+        match self {
+            Node::Empty | Node::Leaf { .. } => Node::Empty,
+            Node::Extension {
+                path: ext_path,
+                child,
+                ..
+            } => child.remove(&path[ext_path.len()..]).under(&ext_path),
+            Node::Branch {
+                mut children,
+                mut value,
+                ..
+            } => {
+                match path.split_first() {
+                    None => value = None,
+                    Some((nibble, rest)) => {
+                        let child = std::mem::take(&mut children[*nibble as usize]);
+                        children[*nibble as usize] = child.remove(rest);
+                    }
+                }
+                let mut remaining = children
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, child)| !matches!(child, Node::Empty))
+                    .map(|(i, _)| i);
+                match (remaining.next(), remaining.next(), value) {
+                    (None, _, Some(value)) => Node::leaf(vec![], value),
+                    (Some(i), None, None) => std::mem::take(&mut children[i]).under(&[i as u8]),
+                    (_, _, value) => Node::branch(children, value),
+                }
+            }
+        }
+    }
+
+    fn get(&self, path: &[u8]) -> Option<&[u8]> {
+        match self {
+            Node::Empty => None,
+            Node::Leaf {
+                path: leaf_path,
+                value,
+                ..
+            } => (leaf_path == path).then_some(value.as_slice()),
+            Node::Extension {
+                path: ext_path,
+                child,
+                ..
+            } => child.get(path.strip_prefix(ext_path.as_slice())?),
+            Node::Branch {
+                children, value, ..
+            } => match path.split_first() {
+                None => value.as_deref(),
+                Some((nibble, rest)) => children[*nibble as usize].get(rest),
+            },
+        }
+    }
+
+    fn encode(&mut self, out: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+        match self {
+            Node::Empty => return encode_bytes(out, &[]),
+            Node::Leaf { path, value, .. } => {
+                encode_bytes(&mut payload, &hex_prefix(path, true));
+                encode_bytes(&mut payload, value);
+            }
+            Node::Extension { path, child, .. } => {
+                encode_bytes(&mut payload, &hex_prefix(path, false));
+                payload.extend(child.reference());
+            }
+            Node::Branch {
+                children, value, ..
+            } => {
+                for child in children.iter_mut() {
+                    payload.extend(child.reference());
+                }
+                encode_bytes(&mut payload, value.as_deref().unwrap_or_default());
+            }
+        }
+        encode_list_payload(out, &payload);
+    }
+
+    /// Appends the nodes below this one on `path` that are referenced by hash.
+    fn prove(&mut self, path: &[u8], proof: &mut Vec<Vec<u8>>) {
+        let (child, rest) = match self {
+            Node::Extension {
+                path: ext_path,
+                child,
+                ..
+            } => match path.strip_prefix(ext_path.as_slice()) {
+                Some(rest) => (child.as_mut(), rest),
+                None => return,
+            },
+            Node::Branch { children, .. } => match path.split_first() {
+                Some((nibble, rest)) => (&mut children[*nibble as usize], rest),
+                None => return,
+            },
+            Node::Empty | Node::Leaf { .. } => return,
+        };
+        if child.reference().len() == 33 {
+            let mut encoded = Vec::new();
+            child.encode(&mut encoded);
+            proof.push(encoded);
+        }
+        child.prove(rest, proof);
+    }
+
+    fn reference_slot(&mut self) -> Option<&mut Option<Vec<u8>>> {
+        match self {
+            Node::Empty => None,
+            Node::Leaf { reference, .. }
+            | Node::Extension { reference, .. }
+            | Node::Branch { reference, .. } => Some(reference),
+        }
+    }
+
+    /// How the node appears in its parent: inlined if its encoding is shorter than a hash,
+    /// by hash otherwise.
+    fn reference(&mut self) -> Vec<u8> {
+        if let Some(Some(reference)) = self.reference_slot() {
+            return reference.clone();
+        }
+        let mut encoded = Vec::new();
+        self.encode(&mut encoded);
+        let reference = if encoded.len() < 32 {
+            encoded
+        } else {
+            let mut hashed = Vec::with_capacity(33);
+            encode_bytes(&mut hashed, &keccak(&encoded));
+            hashed
+        };
+        if let Some(slot) = self.reference_slot() {
+            *slot = Some(reference.clone());
+        }
+        reference
+    }
+}
+
+/// A Merkle Patricia Trie mapping byte keys to non-empty values.
+#[derive(Debug, Clone, Default)]
+pub struct Trie {
+    root: Node,
+}
+
+impl Trie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.root, Node::Empty)
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+        assert!(!value.is_empty(), "trie values cannot be empty");
+        let root = std::mem::take(&mut self.root);
+        self.root = root.insert(&nibbles(key), value);
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.root.get(&nibbles(key))
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.get(key)?.to_vec();
+        let root = std::mem::take(&mut self.root);
+        self.root = root.remove(&nibbles(key));
+        Some(value)
+    }
+
+    /// Only the nodes modified since the last call are hashed again.
+    pub fn root(&mut self) -> [u8; 32] {
+        let mut encoded = Vec::new();
+        self.root.encode(&mut encoded);
+        keccak(&encoded)
+    }
+
+    /// Nodes on the path of `key` from the root, as returned by `eth_getProof`. Nodes
+    /// inlined in their parent are not repeated. It also proves the absence of a missing key.
+    pub fn proof(&mut self, key: &[u8]) -> Vec<Vec<u8>> {
+        let mut proof = Vec::new();
+        if !self.is_empty() {
+            let mut encoded = Vec::new();
+            self.root.encode(&mut encoded);
+            proof.push(encoded);
+            self.root.prove(&nibbles(key), &mut proof);
+        }
+        proof
+    }
+}
+
+/// Checks a proof from `Trie::proof` against `root`, returning the value of `key` or `None`
+/// if the proof shows that it is absent.
+pub fn verify_proof(
+    root: &[u8; 32],
+    key: &[u8],
+    proof: &[Vec<u8>],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    let path = nibbles(key);
+    let mut path = path.as_slice();
+    let mut nodes = proof.iter();
+    let mut node: &[u8] = match nodes.next() {
+        None if *root == EMPTY_ROOT => return Ok(None),
+        None => return Err(ProofError::MissingNode),
+        Some(node) if keccak(node) == *root => node,
+        Some(_) => return Err(ProofError::HashMismatch),
+    };
+    let value = loop {
+        let mut rlp = Rlp::new(node);
+        let mut list = rlp.list()?;
+        rlp.finish()?;
+        let mut items = Vec::with_capacity(17);
+        while !list.is_empty() {
+            items.push(list.raw()?);
+        }
+        let child = match items.len() {
+            17 => match path.split_first() {
+                None => break Rlp::new(items[16]).bytes()?,
+                Some((nibble, rest)) => {
+                    path = rest;
+                    items[*nibble as usize]
+                }
+            },
+            2 => {
+                let (node_path, is_leaf) = decode_hex_prefix(Rlp::new(items[0]).bytes()?)?;
+                match path.strip_prefix(node_path.as_slice()) {
+                    Some([]) if is_leaf => break Rlp::new(items[1]).bytes()?,
+                    Some(rest) if !is_leaf => {
+                        path = rest;
+                        items[1]
+                    }
+                    _ => break &[],
+                }
+            }
+            _ => return Err(RlpError::InvalidLength.into()),
+        };
+        let mut reference = Rlp::new(child);
+        if reference.is_list()? {
+            node = child;
+            continue;
+        }
+        let hash = reference.bytes()?;
+        if hash.is_empty() {
+            break &[];
+        }
+        node = nodes.next().ok_or(ProofError::MissingNode)?;
+        if keccak(node) != hash {
+            return Err(ProofError::HashMismatch);
+        }
+    };
+    if nodes.next().is_some() {
+        return Err(ProofError::TrailingNodes);
+    }
+    Ok((!value.is_empty()).then(|| value.to_vec()))
+}
+
+/// Root of a list stored under the RLP encoding of each index, as for the transactions and
+/// receipts of a block.
+pub fn ordered_trie_root<T: AsRef<[u8]>>(items: &[T]) -> [u8; 32] {
+    let mut trie = Trie::new();
+    for (i, item) in items.iter().enumerate() {
+        trie.insert(&rlp::encode(&i), item.as_ref().to_vec());
+    }
+    trie.root()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_prefix() {
+        assert_eq!(hex_prefix(&[1, 2, 3, 4, 5], false), vec![0x11, 0x23, 0x45]);
+        assert_eq!(
+            hex_prefix(&[0, 1, 2, 3, 4, 5], false),
+            vec![0x00, 0x01, 0x23, 0x45]
+        );
+        assert_eq!(
+            hex_prefix(&[0x0f, 1, 0x0c, 0x0b, 8], true),
+            vec![0x3f, 0x1c, 0xb8]
+        );
+        assert_eq!(hex_prefix(&[], true), vec![0x20]);
+    }
+
+    #[test]
+    fn test_trie_root() {
+        let mut trie = Trie::new();
+        assert_eq!(trie.root(), EMPTY_ROOT);
+        // Example from the Ethereum wiki.
+        for (key, value) in [
+            ("do", "verb"),
+            ("dog", "puppy"),
+            ("doge", "coin"),
+            ("horse", "stallion"),
+        ] {
+            trie.insert(key.as_bytes(), value.as_bytes().to_vec());
+        }
+        assert_eq!(
+            trie.root(),
+            hex!("5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84")
+        );
+        assert_eq!(trie.get(b"dog"), Some(&b"puppy"[..]));
+        assert_eq!(trie.get(b"do"), Some(&b"verb"[..]));
+        assert_eq!(trie.get(b"d"), None);
+        assert_eq!(trie.get(b"doges"), None);
+
+        trie.insert(b"dog", b"wolf".to_vec());
+        assert_eq!(trie.get(b"dog"), Some(&b"wolf"[..]));
+        assert_ne!(
+            trie.root(),
+            hex!("5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84")
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        for i in 0..200u64 {
+            let key = keccak(&i.to_be_bytes())[..(i % 5 + 1) as usize].to_vec();
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        let mut trie = Trie::new();
+        let mut roots = vec![trie.root()];
+        for key in &keys {
+            trie.insert(key, key.clone());
+            roots.push(trie.root());
+        }
+        assert_eq!(trie.remove(b"missing"), None);
+        // Removing the keys in reverse goes back through the same roots.
+        for (i, key) in keys.iter().enumerate().rev() {
+            assert_eq!(trie.root(), roots[i + 1]);
+            assert_eq!(trie.remove(key), Some(key.clone()));
+            assert_eq!(trie.get(key), None);
+        }
+        assert_eq!(trie.root(), EMPTY_ROOT);
+        assert!(trie.is_empty());
+
+        let mut puppy = Trie::new();
+        for key in ["do", "dog", "doge", "horse", "dogecoin"] {
+            puppy.insert(key.as_bytes(), b"x".to_vec());
+        }
+        puppy.remove(b"dogecoin");
+        puppy.remove(b"dog");
+        let mut expected = Trie::new();
+        for key in ["do", "doge", "horse"] {
+            expected.insert(key.as_bytes(), b"x".to_vec());
+        }
+        assert_eq!(puppy.root(), expected.root());
+    }
+
+    #[test]
+    fn test_proof() {
+        let mut trie = Trie::new();
+        assert_eq!(trie.proof(b"dog"), Vec::<Vec<u8>>::new());
+        assert_eq!(verify_proof(&trie.root(), b"dog", &[]), Ok(None));
+
+        let keys: Vec<[u8; 32]> = (0..100u64).map(|i| keccak(&i.to_be_bytes())).collect();
+        for key in &keys {
+            trie.insert(key, key[..(key[0] % 32 + 1) as usize].to_vec());
+        }
+        for key in ["do", "dog", "doge", "horse"] {
+            trie.insert(key.as_bytes(), key.as_bytes().to_vec());
+        }
+        let root = trie.root();
+        for key in &keys {
+            assert_eq!(
+                verify_proof(&root, key, &trie.proof(key))
+                    .unwrap()
+                    .as_deref(),
+                trie.get(key)
+            );
+        }
+        for key in ["do", "dog", "doge", "horse", "d", "dogs", "cat"] {
+            let proof = trie.proof(key.as_bytes());
+            assert_eq!(
+                verify_proof(&root, key.as_bytes(), &proof)
+                    .unwrap()
+                    .as_deref(),
+                trie.get(key.as_bytes())
+            );
+        }
+
+        let mut proof = trie.proof(&keys[0]);
+        assert_eq!(
+            verify_proof(&EMPTY_ROOT, &keys[0], &proof),
+            Err(ProofError::HashMismatch)
+        );
+        proof.push(vec![0x80]);
+        assert_eq!(
+            verify_proof(&root, &keys[0], &proof),
+            Err(ProofError::TrailingNodes)
+        );
+        proof.truncate(1);
+        assert_eq!(
+            verify_proof(&root, &keys[0], &proof),
+            Err(ProofError::MissingNode)
+        );
+        let last = proof.len() - 1;
+        proof[last][10] ^= 1;
+        assert_eq!(
+            verify_proof(&root, &keys[0], &proof),
+            Err(ProofError::HashMismatch)
+        );
+    }
+
+    #[test]
+    fn test_ordered_trie_root() {
+        assert_eq!(ordered_trie_root::<Vec<u8>>(&[]), EMPTY_ROOT);
+        // The insertion order does not matter.
+        let items: Vec<Vec<u8>> = (0..300u64).map(|i| rlp::encode(&(i * 7))).collect();
+        let mut trie = Trie::new();
+        for i in (0..items.len()).rev() {
+            trie.insert(&rlp::encode(&i), items[i].clone());
+        }
+        assert_eq!(trie.root(), ordered_trie_root(&items));
+    }
+}
@@ -0,0 +1,334 @@
+use alloy_primitives::primitives::Address;
+use hmac::{Hmac, Mac};
+use k256::{
+    ecdsa::SigningKey,
+    elliptic_curve::{sec1::ToEncodedPoint, PrimeField},
+    Scalar, SecretKey,
+};
+use sha2::Sha512;
+
+use crate::{
+    eip712::Eip712Domain,
+    keccak::keccak,
+    signature::{public_key_to_address, Signature},
+    transaction::{Authorization, SignedTransaction, Transaction},
+    u256::U256,
+};
+
+/// Mnemonic of the Hardhat and Anvil development accounts.
+pub const DEV_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+/// BIP-44 path of the `index`-th Ethereum account of a mnemonic.
+pub fn derivation_path(index: u32) -> String {
+    format!("m/44'/60'/0'/0/{}", index)
+}
+
+const HARDENED: u32 = 0x80000000;
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// BIP-39 seed of a mnemonic. The phrase is used as is, it is not checked against the
+/// BIP-39 word list.
+fn mnemonic_to_seed(phrase: &str, passphrase: &str) -> [u8; 64] {
+    let mut seed = [0u8; 64];
+    pbkdf2::pbkdf2_hmac::<Sha512>(
+        phrase.as_bytes(),
+        format!("mnemonic{}", passphrase).as_bytes(),
+        2048,
+        &mut seed,
+    );
+    seed
+}
+
+/// BIP-32 derivation of the private key at `path` from a seed.
+fn derive_key(seed: &[u8], path: &str) -> Option<SecretKey> {
+    let master = hmac_sha512(b"Bitcoin seed", seed);
+    let mut key = SecretKey::from_slice(&master[..32]).ok()?;
+    let mut chain_code: [u8; 32] = master[32..].try_into().unwrap();
+    let mut segments = path.split('/');
+    if segments.next() != Some("m") {
+        return None;
+    }
+    for segment in segments {
+        let index = match segment.strip_suffix('\'') {
+            Some(hardened) => hardened.parse::<u32>().ok()?.checked_add(HARDENED)?,
+            None => segment.parse::<u32>().ok().filter(|i| *i < HARDENED)?,
+        };
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend(key.to_bytes());
+        } else {
+            data.extend(key.public_key().to_encoded_point(true).as_bytes());
+        }
+        data.extend(index.to_be_bytes());
+        let derived = hmac_sha512(&chain_code, &data);
+        let tweak: [u8; 32] = derived[..32].try_into().unwrap();
+        let tweak = Option::<Scalar>::from(Scalar::from_repr(tweak.into()))?;
+        let child = tweak + *key.to_nonzero_scalar();
+        key = SecretKey::from_bytes(&child.to_repr()).ok()?;
+        chain_code = derived[32..].try_into().unwrap();
+    }
+    Some(key)
+}
+
+/// A secp256k1 keypair able to sign transactions and messages.
+#[derive(Debug, Clone)]
+pub struct LocalAccount {
+    key: SigningKey,
+    address: Address,
+}
+
+impl LocalAccount {
+    pub fn from_private_key(private_key: &[u8; 32]) -> Option<Self> {
+        let key = SigningKey::from_slice(private_key).ok()?;
+        let address = public_key_to_address(key.verifying_key());
+        Some(Self { key, address })
+    }
+
+    pub fn random() -> Self {
+        let key = SigningKey::random(&mut rand_core::OsRng);
+        let address = public_key_to_address(key.verifying_key());
+        Self { key, address }
+    }
+
+    /// Account at `path` of a BIP-39 mnemonic, e.g. `derivation_path(0)`.
+    pub fn from_mnemonic(phrase: &str, path: &str) -> Option<Self> {
+        let key = derive_key(&mnemonic_to_seed(phrase, ""), path)?;
+        Self::from_private_key(&key.to_bytes().into())
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn private_key(&self) -> [u8; 32] {
+        self.key.to_bytes().into()
+    }
+
+    pub fn sign_hash(&self, hash: &[u8; 32]) -> Signature {
+        let (sig, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(hash)
+            .expect("signing a 32-byte prehash cannot fail");
+        Signature {
+            y_parity: recovery_id.is_y_odd(),
+            r: U256::from_be_slice(&sig.r().to_bytes()),
+            s: U256::from_be_slice(&sig.s().to_bytes()),
+        }
+    }
+
+    pub fn sign_transaction(&self, tx: Transaction) -> SignedTransaction {
+        let signature = self.sign_hash(&tx.signature_hash());
+        SignedTransaction { tx, signature }
+    }
+
+    /// Signs an EIP-7702 delegation of this account's code to `address`.
+    pub fn sign_authorization(
+        &self,
+        chain_id: U256,
+        address: Address,
+        nonce: U256,
+    ) -> Authorization {
+        let mut auth = Authorization {
+            chain_id,
+            address,
+            nonce,
+            ..Default::default()
+        };
+        let signature = self.sign_hash(&auth.signature_hash());
+        auth.y_parity = signature.y_parity as u8;
+        auth.r = signature.r;
+        auth.s = signature.s;
+        auth
+    }
+
+    /// EIP-191 signature of a message, as done by `personal_sign`.
+    pub fn sign_message(&self, message: &[u8]) -> Signature {
+        let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+        data.extend(message);
+        self.sign_hash(&keccak(&data))
+    }
+
+    /// EIP-712 signature of a struct, given its `hash_struct`.
+    pub fn sign_typed_data(&self, domain: &Eip712Domain, struct_hash: &[u8; 32]) -> Signature {
+        self.sign_hash(&domain.signing_hash(struct_hash))
+    }
+}
+
+/// The first `count` accounts of `DEV_MNEMONIC`, the same as Hardhat and Anvil.
+pub fn dev_accounts(count: u32) -> Vec<LocalAccount> {
+    let seed = mnemonic_to_seed(DEV_MNEMONIC, "");
+    (0..count)
+        .map(|i| {
+            let key = derive_key(&seed, &derivation_path(i)).expect("valid derivation path");
+            LocalAccount::from_private_key(&key.to_bytes().into()).unwrap()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::{Context, ContextMut, MiniEthereum},
+        eip712::{encode_address, encode_uint, hash_struct},
+        env::BlockEnv,
+        machine::{CallInfo, GasTracker},
+        opcodes::ExecutionResult,
+        signature::recover_address,
+        transaction::TxEip1559,
+    };
+    use alloy_primitives::primitives::{address, hex};
+
+    #[test]
+    fn test_dev_accounts() {
+        let accounts = dev_accounts(3);
+        assert_eq!(
+            accounts[0].address(),
+            address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266")
+        );
+        assert_eq!(
+            accounts[0].private_key(),
+            hex!("ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80")
+        );
+        assert_eq!(
+            accounts[1].address(),
+            address!("70997970C51812dc3A010C7d01b50e0d17dc79C8")
+        );
+        assert_eq!(
+            accounts[2].address(),
+            address!("3C44CdDdB6a900fa2b585dd299e03d12FA4293BC")
+        );
+        assert_eq!(
+            LocalAccount::from_mnemonic(DEV_MNEMONIC, &derivation_path(1))
+                .unwrap()
+                .private_key(),
+            hex!("59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d")
+        );
+        assert!(LocalAccount::from_mnemonic(DEV_MNEMONIC, "44'/60'").is_none());
+    }
+
+    #[test]
+    fn test_sign() {
+        let account = LocalAccount::random();
+        assert_ne!(account.address(), LocalAccount::random().address());
+        let hash = keccak(b"hello");
+        assert_eq!(
+            recover_address(&hash, &account.sign_hash(&hash)),
+            Some(account.address())
+        );
+
+        let mut data = b"\x19Ethereum Signed Message:\n5".to_vec();
+        data.extend(b"hello");
+        assert_eq!(
+            recover_address(&keccak(&data), &account.sign_message(b"hello")),
+            Some(account.address())
+        );
+
+        let auth =
+            account.sign_authorization(U256::from(1), Address::with_last_byte(2), U256::ZERO);
+        assert_eq!(auth.recover_authority(), Some(account.address()));
+    }
+
+    #[test]
+    fn test_eip712_mail_signature() {
+        let cow = LocalAccount::from_private_key(&keccak(b"cow")).unwrap();
+        assert_eq!(
+            cow.address(),
+            address!("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826")
+        );
+        let signature = cow.sign_hash(&hex!(
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        ));
+        assert!(signature.y_parity);
+        assert_eq!(
+            signature.r,
+            U256::from_be_bytes(hex!(
+                "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d"
+            ))
+        );
+        assert_eq!(
+            signature.s,
+            U256::from_be_bytes(hex!(
+                "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_permit_signature_checked_onchain() {
+        let [owner, spender] = &dev_accounts(2)[..] else {
+            unreachable!()
+        };
+        let token = Address::with_last_byte(0x70);
+        let domain = Eip712Domain::new("Token", "1", U256::from(1), token);
+        let permit = hash_struct(
+            "Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)",
+            &[
+                encode_address(owner.address()),
+                encode_address(spender.address()),
+                encode_uint(U256::from(1000)),
+                encode_uint(U256::ZERO),
+                encode_uint(U256::MAX),
+            ],
+        );
+        let digest = domain.signing_hash(&permit);
+        let signature = owner.sign_typed_data(&domain, &permit);
+
+        let mut ctx = MiniEthereum::new();
+        let mut input = digest.to_vec();
+        input.extend(U256::from(27 + signature.y_parity as u64).to_be_bytes::<32>());
+        input.extend(signature.r.to_be_bytes::<32>());
+        input.extend(signature.s.to_be_bytes::<32>());
+        let mut gt = GasTracker::new(100000);
+        assert_eq!(
+            ctx.call(
+                1024,
+                &mut gt,
+                Address::with_last_byte(0x01),
+                CallInfo {
+                    data: input,
+                    ..Default::default()
+                },
+            ),
+            Ok(ExecutionResult::Returned(
+                owner.address().into_word().to_vec()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_fund_and_send() {
+        let accounts = dev_accounts(2);
+        let mut ctx = MiniEthereum::new()
+            .with_block_env(BlockEnv::default().with_chain_id(U256::from(31337)))
+            .with_funded_accounts(
+                &accounts.iter().map(|a| a.address()).collect::<Vec<_>>(),
+                U256::from(1000000),
+            );
+        let signed = accounts[0].sign_transaction(
+            TxEip1559 {
+                chain_id: U256::from(31337),
+                to: Some(accounts[1].address()),
+                value: U256::from(10),
+                gas_limit: 21000,
+                ..Default::default()
+            }
+            .into(),
+        );
+        assert!(
+            ctx.execute_raw_transaction(&signed.encode())
+                .unwrap()
+                .success
+        );
+        assert_eq!(
+            ctx.balance(accounts[1].address()).unwrap(),
+            U256::from(1000010)
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use std::{collections::BTreeMap, error::Error, str::FromStr};
+
+use alloy_primitives::primitives::{hex, Address};
+use serde_json::{Map, Value};
+
+use crate::{
+    diff::AccountState,
+    error::WitnessError,
+    genesis::{format_word, parse_quantity, parse_word},
+    overlay::{Backend, Overlay},
+    u256::U256,
+};
+
+/// The part of a state an execution accessed: every account it touched with the storage
+/// slots it read or wrote, and the block hashes it asked for. Values are the ones before
+/// execution.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Witness {
+    pub accounts: BTreeMap<Address, AccountState>,
+    pub block_hashes: BTreeMap<U256, U256>,
+}
+
+fn parse_account(value: &Value) -> Option<AccountState> {
+    let mut account = AccountState {
+        balance: parse_quantity(value.get("balance")?)?,
+        nonce: parse_quantity(value.get("nonce")?)?,
+        code: hex::decode(value.get("code")?.as_str()?).ok()?,
+        storage: BTreeMap::new(),
+    };
+    for (slot, value) in value.get("storage")?.as_object()? {
+        account
+            .storage
+            .insert(parse_word(slot)?, parse_word(value.as_str()?)?);
+    }
+    Some(account)
+}
+
+impl Witness {
+    /// JSON encoding, accounts in the genesis `alloc` format except that every field is
+    /// present and zero slots are kept: a slot read as zero is part of the witness too.
+    pub fn to_json(&self) -> Value {
+        let accounts: Map<String, Value> = self
+            .accounts
+            .iter()
+            .map(|(address, account)| {
+                let storage: Map<String, Value> = account
+                    .storage
+                    .iter()
+                    .map(|(slot, value)| (format_word(slot), format_word(value).into()))
+                    .collect();
+                let mut entry = Map::new();
+                entry.insert("balance".into(), format!("{:#x}", account.balance).into());
+                entry.insert("nonce".into(), format!("{:#x}", account.nonce).into());
+                entry.insert("code".into(), hex::encode_prefixed(&account.code).into());
+                entry.insert("storage".into(), Value::Object(storage));
+                (hex::encode_prefixed(address), Value::Object(entry))
+            })
+            .collect();
+        let block_hashes: Map<String, Value> = self
+            .block_hashes
+            .iter()
+            .map(|(number, hash)| (format!("{:#x}", number), format_word(hash).into()))
+            .collect();
+        let mut out = Map::new();
+        out.insert("accounts".into(), Value::Object(accounts));
+        out.insert("blockHashes".into(), Value::Object(block_hashes));
+        Value::Object(out)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, WitnessError> {
+        let invalid = |reason: &str| WitnessError::InvalidJson(reason.to_string());
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| WitnessError::InvalidJson(e.to_string()))?;
+        let mut witness = Self::default();
+        let accounts = value["accounts"]
+            .as_object()
+            .ok_or_else(|| invalid("accounts is not an object"))?;
+        for (address, account) in accounts {
+            let parsed = Address::from_str(address).map_err(|_| invalid(address))?;
+            let account = parse_account(account).ok_or_else(|| invalid(address))?;
+            witness.accounts.insert(parsed, account);
+        }
+        let block_hashes = value["blockHashes"]
+            .as_object()
+            .ok_or_else(|| invalid("blockHashes is not an object"))?;
+        for (number, hash) in block_hashes {
+            let parsed = U256::from_str(number).map_err(|_| invalid(number))?;
+            let hash = hash
+                .as_str()
+                .and_then(parse_word)
+                .ok_or_else(|| invalid(number))?;
+            witness.block_hashes.insert(parsed, hash);
+        }
+        Ok(witness)
+    }
+
+    fn account(&self, address: Address) -> Result<&AccountState, WitnessError> {
+        self.accounts
+            .get(&address)
+            .ok_or(WitnessError::MissingAccount(address))
+    }
+}
+
+impl Backend for Witness {
+    fn balance(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        Ok(self.account(address)?.balance)
+    }
+    fn nonce(&self, address: Address) -> Result<U256, Box<dyn Error>> {
+        Ok(self.account(address)?.nonce)
+    }
+    fn code(&self, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(self.account(address)?.code.clone())
+    }
+    fn storage(&self, address: Address, slot: U256) -> Result<U256, Box<dyn Error>> {
+        match self.account(address)?.storage.get(&slot) {
+            Some(value) => Ok(*value),
+            None => Err(Box::new(WitnessError::MissingSlot(address, slot))),
+        }
+    }
+    fn block_hash(&self, number: U256) -> Result<U256, Box<dyn Error>> {
+        match self.block_hashes.get(&number) {
+            Some(hash) => Ok(*hash),
+            None => Err(Box::new(WitnessError::MissingBlockHash(number))),
+        }
+    }
+    /// Writing state outside the witness means the recording missed it.
+    fn check_write(&self, address: Address, slot: Option<U256>) -> Result<(), Box<dyn Error>> {
+        match slot {
+            Some(slot) => self.storage(address, slot).map(|_| ()),
+            None => self.account(address).map(|_| ()).map_err(Into::into),
+        }
+    }
+}
+
+/// A context holding nothing but a `Witness`, to re-execute what the witness was
+/// recorded from. Accessing state outside the witness fails with a `WitnessError`.
+pub type WitnessContext = Overlay<Witness>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::{Context, ContextMut, MiniEthereum},
+        env::BlockEnv,
+        error::ExecError,
+        machine::{CallInfo, GasTracker},
+        tests::{addr, COUNTER},
+        transaction::{execute_transaction, Transaction, TxEip1559},
+    };
+
+    const STACK_SIZE: usize = 1024;
+
+    /// Stores the hash of block 10 in slot 1: `PUSH1 10 BLOCKHASH PUSH1 1 SSTORE STOP`.
+    const BLOCK_HASH_STORE: [u8; 7] = hex!("600a4060015500");
+
+    fn base() -> MiniEthereum {
+        let mut ctx = MiniEthereum::new()
+            .with_block_env(BlockEnv::default().with_number(U256::from(12)))
+            .with_block_hash(U256::from(10), U256::from(0xabcd));
+        ctx.fund(addr(1), U256::from(1000));
+        let counter = ctx.accounts.entry(addr(0x42)).or_default();
+        counter.code = COUNTER.to_vec();
+        counter.storage.insert(U256::ZERO, U256::from(5));
+        ctx.accounts.entry(addr(0x43)).or_default().code = BLOCK_HASH_STORE.to_vec();
+        ctx
+    }
+
+    fn call<C: ContextMut<U256>>(ctx: &mut C, to: Address, value: u64) -> Result<(), ExecError> {
+        ctx.call(
+            STACK_SIZE,
+            &mut GasTracker::new(1_000_000),
+            to,
+            CallInfo {
+                origin: addr(1),
+                caller: addr(1),
+                value: U256::from(value),
+                data: vec![],
+                is_static: false,
+            },
+        )
+        .map(|_| ())
+    }
+
+    #[test]
+    fn test_witness_replay() {
+        let base = base();
+        let mut recorder = Overlay::new(&base).with_block_env(base.block.clone());
+        call(&mut recorder, addr(0x42), 10).unwrap();
+        call(&mut recorder, addr(0x43), 0).unwrap();
+        let witness = recorder.witness().unwrap();
+        assert_eq!(
+            witness.accounts.keys().collect::<Vec<_>>(),
+            [&addr(1), &addr(0x42), &addr(0x43)]
+        );
+        assert_eq!(
+            witness.accounts[&addr(0x43)].storage,
+            [(U256::ONE, U256::ZERO)].into_iter().collect()
+        );
+        assert_eq!(
+            witness.block_hashes,
+            [(U256::from(10), U256::from(0xabcd))].into_iter().collect()
+        );
+
+        // The witness survives a round trip through JSON and is enough to replay.
+        let witness = Witness::from_json(&witness.to_json().to_string()).unwrap();
+        let mut replay = WitnessContext::new(witness)
+            .with_block_env(BlockEnv::default().with_number(U256::from(12)));
+        call(&mut replay, addr(0x42), 10).unwrap();
+        call(&mut replay, addr(0x43), 0).unwrap();
+        assert_eq!(replay.changes(), recorder.changes());
+        assert_eq!(
+            replay.sload(addr(0x43), U256::ONE).unwrap(),
+            U256::from(0xabcd)
+        );
+    }
+
+    #[test]
+    fn test_witness_replay_transaction() {
+        let mut base = base();
+        base.fund(addr(1), U256::from(1_000_000));
+        base.block.coinbase = addr(0xcb);
+        let tx: Transaction = TxEip1559 {
+            to: Some(addr(0x42)),
+            value: U256::from(10),
+            gas_limit: 100_000,
+            max_fee_per_gas: U256::ONE,
+            max_priority_fee_per_gas: U256::ONE,
+            ..Default::default()
+        }
+        .into();
+        let expected = base.clone().execute_transaction(addr(1), &tx).unwrap();
+
+        let mut recorder = Overlay::new(&base).with_block_env(base.block.clone());
+        assert_eq!(
+            execute_transaction(&mut recorder, addr(1), &tx).unwrap(),
+            expected
+        );
+        let witness = recorder.witness().unwrap();
+        assert!(witness.accounts.contains_key(&addr(0xcb)));
+
+        let mut replay = WitnessContext::new(witness).with_block_env(base.block.clone());
+        assert_eq!(
+            execute_transaction(&mut replay, addr(1), &tx).unwrap(),
+            expected
+        );
+        assert_eq!(replay.changes(), recorder.changes());
+        assert_eq!(replay.sload(addr(0x42), U256::ZERO).unwrap(), U256::from(6));
+        assert_eq!(replay.nonce(addr(1)).unwrap(), U256::ONE);
+    }
+
+    #[test]
+    fn test_witness_missing_state() {
+        let base = base();
+        let mut recorder = Overlay::new(&base).with_block_env(base.block.clone());
+        call(&mut recorder, addr(0x42), 0).unwrap();
+        let witness = recorder.witness().unwrap();
+
+        let missing = |res: Result<(), ExecError>| match res {
+            Err(ExecError::Context(err)) => err.downcast_ref::<WitnessError>().cloned(),
+            _ => None,
+        };
+        let mut replay = WitnessContext::new(witness.clone())
+            .with_block_env(BlockEnv::default().with_number(U256::from(12)));
+        assert_eq!(
+            missing(call(&mut replay, addr(0x43), 0)),
+            Some(WitnessError::MissingAccount(addr(0x43)))
+        );
+        // The recorded call still replays with another value.
+        call(&mut replay, addr(0x42), 1).unwrap();
+
+        let mut witness = witness;
+        witness.accounts.insert(
+            addr(0x43),
+            AccountState {
+                code: BLOCK_HASH_STORE.to_vec(),
+                ..Default::default()
+            },
+        );
+        let mut replay = WitnessContext::new(witness.clone())
+            .with_block_env(BlockEnv::default().with_number(U256::from(12)));
+        assert_eq!(
+            missing(call(&mut replay, addr(0x43), 0)),
+            Some(WitnessError::MissingBlockHash(U256::from(10)))
+        );
+        witness.block_hashes.insert(U256::from(10), U256::ONE);
+        let mut replay = WitnessContext::new(witness)
+            .with_block_env(BlockEnv::default().with_number(U256::from(12)));
+        assert_eq!(
+            missing(call(&mut replay, addr(0x43), 0)),
+            Some(WitnessError::MissingSlot(addr(0x43), U256::ONE))
+        );
+    }
+
+    #[test]
+    fn test_invalid_witness_json() {
+        assert!(Witness::from_json("{").is_err());
+        assert!(Witness::from_json(r#"{"accounts": {}}"#).is_err());
+        assert_eq!(
+            Witness::from_json(r#"{"accounts": {"0x01": {}}, "blockHashes": {}}"#),
+            Err(WitnessError::InvalidJson("0x01".to_string()))
+        );
+        assert_eq!(
+            Witness::from_json(r#"{"accounts": {}, "blockHashes": {}}"#),
+            Ok(Witness::default())
+        );
+    }
+}